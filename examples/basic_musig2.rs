@@ -5,8 +5,11 @@
 //! to signature verification.
 
 use musig2::{CompactSignature, FirstRound, KeyAggContext, PartialSignature, SecNonceSpices};
-use rand::{rngs::OsRng, Rng};
-use secp256k1::{PublicKey, Secp256k1, SecretKey};
+use musig2_example::rng::nonce_seed;
+use musig2_example::secp::SECP256K1;
+use musig2_example::verify::verify_participant_set;
+use rand::rngs::OsRng;
+use secp256k1::{PublicKey, SecretKey};
 
 fn main() {
     // Create secret and public keys for three participants
@@ -15,22 +18,21 @@ fn main() {
     let secret_key_2 = SecretKey::new(&mut rng);
     let secret_key_3 = SecretKey::new(&mut rng);
 
-    let secp = Secp256k1::new();
-    let public_key_1 = PublicKey::from_secret_key(&secp, &secret_key_1);
-    let public_key_2 = PublicKey::from_secret_key(&secp, &secret_key_2);
-    let public_key_3 = PublicKey::from_secret_key(&secp, &secret_key_3);
+    let public_key_1 = PublicKey::from_secret_key(&SECP256K1, &secret_key_1);
+    let public_key_2 = PublicKey::from_secret_key(&SECP256K1, &secret_key_2);
+    let public_key_3 = PublicKey::from_secret_key(&SECP256K1, &secret_key_3);
 
     // Message to sign
     let message = b"Hello, MuSig2!";
 
     // Create key aggregation context
     let pubkeys = vec![public_key_1, public_key_2, public_key_3];
-    let key_agg_ctx = KeyAggContext::new(pubkeys).unwrap();
+    let key_agg_ctx = KeyAggContext::new(pubkeys.clone()).unwrap();
 
     // First round: Generate and exchange public nonces
     let mut first_round_1 = FirstRound::new(
         key_agg_ctx.clone(),
-        rand::thread_rng().gen::<[u8; 32]>(),
+        nonce_seed(&mut rand::thread_rng()).unwrap(),
         0,
         SecNonceSpices::new()
             .with_seckey(secret_key_1)
@@ -40,7 +42,7 @@ fn main() {
 
     let mut first_round_2 = FirstRound::new(
         key_agg_ctx.clone(),
-        rand::thread_rng().gen::<[u8; 32]>(),
+        nonce_seed(&mut rand::thread_rng()).unwrap(),
         1,
         SecNonceSpices::new()
             .with_seckey(secret_key_2)
@@ -50,7 +52,7 @@ fn main() {
 
     let mut first_round_3 = FirstRound::new(
         key_agg_ctx.clone(),
-        rand::thread_rng().gen::<[u8; 32]>(),
+        nonce_seed(&mut rand::thread_rng()).unwrap(),
         2,
         SecNonceSpices::new()
             .with_seckey(secret_key_3)
@@ -111,4 +113,19 @@ fn main() {
         "Final signature: {}",
         hex::encode(final_signature.serialize())
     );
+
+    // Bind the signature to the group: it must verify for exactly these keys, in this order
+    let reordered = [public_key_2, public_key_1, public_key_3];
+    for (name, set) in [("expected", &pubkeys[..]), ("reordered", &reordered[..])] {
+        match verify_participant_set(set, None, aggregated_pubkey, final_signature, message) {
+            Ok(outcome) => println!(
+                "Signature from the {} participant set: {} (key matches: {}, signature valid: {})",
+                name,
+                if outcome.is_valid() { "yes" } else { "no" },
+                outcome.key_matches,
+                outcome.signature_valid
+            ),
+            Err(e) => println!("Participant set check failed: {}", e),
+        }
+    }
 }
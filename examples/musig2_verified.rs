@@ -0,0 +1,180 @@
+//! MuSig2 Example with Verification
+//!
+//! This example goes through the same three-party workflow as `basic_musig2`, but with the
+//! checks an aggregator should make before trusting what the participants send it: every
+//! participant must sign under the same aggregated nonce, and every partial signature is
+//! verified on its own before the final signature is assembled.
+//!
+//! Pass `--tamper <index>` to corrupt that participant's partial signature and watch the
+//! checks catch it:
+//!
+//! ```shell
+//! cargo run --example musig2_verified -- --tamper 1
+//! ```
+
+use musig2::secp::Scalar;
+use musig2::{
+    AggNonce, CompactSignature, FirstRound, KeyAggContext, PartialSignature, PubNonce,
+    SecNonceSpices, SecondRound,
+};
+use musig2_example::rng::nonce_seed;
+use musig2_example::secp::SECP256K1;
+use rand::rngs::OsRng;
+use secp256k1::{PublicKey, SecretKey};
+
+fn main() {
+    let tamper = tampered_index();
+
+    // Create secret and public keys for three participants
+    let mut rng = OsRng;
+    let secret_keys: Vec<SecretKey> = (0..3).map(|_| SecretKey::new(&mut rng)).collect();
+    let pubkeys: Vec<PublicKey> = secret_keys
+        .iter()
+        .map(|secret_key| PublicKey::from_secret_key(&SECP256K1, secret_key))
+        .collect();
+
+    // Message to sign
+    let message = b"Hello, MuSig2!";
+
+    // Create key aggregation context
+    let key_agg_ctx = KeyAggContext::new(pubkeys.clone()).unwrap();
+    let aggregated_pubkey: PublicKey = key_agg_ctx.aggregated_pubkey();
+    println!(
+        "Aggregated public key: {}",
+        hex::encode(aggregated_pubkey.serialize())
+    );
+
+    // First round: every participant generates a nonce
+    let mut first_rounds: Vec<FirstRound> = secret_keys
+        .iter()
+        .enumerate()
+        .map(|(index, secret_key)| {
+            FirstRound::new(
+                key_agg_ctx.clone(),
+                nonce_seed(&mut rand::thread_rng()).unwrap(),
+                index,
+                SecNonceSpices::new()
+                    .with_seckey(*secret_key)
+                    .with_message(message),
+            )
+            .unwrap()
+        })
+        .collect();
+    let pub_nonces: Vec<PubNonce> = first_rounds
+        .iter()
+        .map(|first_round| first_round.our_public_nonce())
+        .collect();
+
+    // Exchange nonces between participants
+    for (index, first_round) in first_rounds.iter_mut().enumerate() {
+        for (other, pub_nonce) in pub_nonces.iter().enumerate() {
+            if other != index {
+                first_round.receive_nonce(other, pub_nonce.clone()).unwrap();
+            }
+        }
+    }
+
+    // The aggregator sums the public nonces itself. The signature's nonce point comes from
+    // this aggregated nonce, so it is what every partial signature must be made under.
+    let aggregated_nonce: AggNonce = pub_nonces.iter().sum();
+    println!(
+        "Aggregated nonce: {}",
+        hex::encode(aggregated_nonce.serialize())
+    );
+
+    // Second round: every participant signs
+    let second_rounds: Vec<SecondRound<&[u8]>> = first_rounds
+        .into_iter()
+        .zip(&secret_keys)
+        .map(|(first_round, secret_key)| {
+            first_round
+                .finalize(*secret_key, message.as_slice())
+                .unwrap()
+        })
+        .collect();
+
+    // Check 1: every participant ended up with the aggregator's aggregated nonce. A participant
+    // that was given a different set of nonces signs under another nonce point, and its
+    // partial signature can't be combined with the others'.
+    for (index, second_round) in second_rounds.iter().enumerate() {
+        if *second_round.aggregated_nonce() != aggregated_nonce {
+            println!(
+                "Participant {} signed under a different aggregated nonce",
+                index
+            );
+            return;
+        }
+    }
+    println!("All participants agree on the aggregated nonce.");
+
+    let mut partial_signatures: Vec<PartialSignature> = second_rounds
+        .iter()
+        .map(|second_round| second_round.our_signature())
+        .collect();
+    if let Some(index) = tamper {
+        println!(
+            "Tampering with the partial signature of participant {}.",
+            index
+        );
+        partial_signatures[index] += Scalar::one();
+    }
+
+    // Check 2: verify each partial signature against its signer's key and nonce. A bad
+    // partial would only make the final signature invalid, without saying whose it was; here
+    // the culprit is known and can be excluded or blamed before anything is published.
+    let mut all_valid = true;
+    for (index, partial_signature) in partial_signatures.iter().enumerate() {
+        match musig2::verify_partial(
+            &key_agg_ctx,
+            *partial_signature,
+            &aggregated_nonce,
+            pubkeys[index],
+            &pub_nonces[index],
+            message,
+        ) {
+            Ok(()) => println!("Partial signature of participant {} is valid.", index),
+            Err(e) => {
+                println!(
+                    "Partial signature of participant {} is invalid: {:?}",
+                    index, e
+                );
+                all_valid = false;
+            }
+        }
+    }
+    if !all_valid {
+        println!("Refusing to aggregate invalid partial signatures.");
+        return;
+    }
+
+    // Only now are the verified partials combined, under the same aggregated nonce
+    let final_signature: CompactSignature = musig2::aggregate_partial_signatures(
+        &key_agg_ctx,
+        &aggregated_nonce,
+        partial_signatures,
+        message,
+    )
+    .unwrap();
+    println!(
+        "Final signature: {}",
+        hex::encode(final_signature.serialize())
+    );
+
+    // Check 3: the final signature is an ordinary BIP340 signature under the aggregated key
+    match musig2::verify_single(aggregated_pubkey, final_signature, message) {
+        Ok(_) => println!("Signature verified successfully!"),
+        Err(e) => println!("Signature verification failed: {:?}", e),
+    }
+}
+
+/// Index of the participant whose partial signature `--tamper <index>` asks to corrupt.
+fn tampered_index() -> Option<usize> {
+    let args: Vec<String> = std::env::args().collect();
+    let position = args.iter().position(|arg| arg == "--tamper")?;
+    let index = args
+        .get(position + 1)
+        .and_then(|index| index.parse().ok())
+        .filter(|index| *index < 3)
+        .expect("--tamper takes a participant index from 0 to 2");
+    Some(index)
+}
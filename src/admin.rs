@@ -0,0 +1,24 @@
+use crate::error::Unauthorized;
+use warp::Filter;
+
+/// Header carrying the admin token on admin-gated endpoints.
+pub const ADMIN_TOKEN_HEADER: &str = "x-admin-token";
+
+/// Filter that only passes requests presenting the configured admin token.
+///
+/// When no token is configured, admin endpoints are disabled and every request is rejected.
+pub fn admin_auth(
+    token: Option<String>,
+) -> impl Filter<Extract = (), Error = warp::Rejection> + Clone {
+    warp::header::optional::<String>(ADMIN_TOKEN_HEADER)
+        .and_then(move |provided: Option<String>| {
+            let token = token.clone();
+            async move {
+                match (token, provided) {
+                    (Some(expected), Some(provided)) if expected == provided => Ok(()),
+                    _ => Err(warp::reject::custom(Unauthorized)),
+                }
+            }
+        })
+        .untuple_one()
+}
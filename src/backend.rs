@@ -0,0 +1,202 @@
+use musig2::errors::{RoundFinalizeError, SignerIndexError, SigningError};
+use musig2::secp::Scalar;
+use musig2::{FirstRound, KeyAggContext, SecNonceSpices, SecondRound};
+use secp256k1::{All, PublicKey, Secp256k1, SecretKey};
+
+/// Backend computing a signer's MuSig2 contributions.
+///
+/// The signer node only talks to its key through this trait. [`SoftwareSigner`] keeps the
+/// secret key in memory; an HSM backend implements the same methods by forwarding nonce
+/// derivation and partial signing to the device, so the key never enters process memory.
+pub trait PartialSigner: Send + Sync {
+    /// Public key this backend signs for.
+    fn public_key(&self) -> PublicKey;
+
+    /// Starts a signing round, deriving the secret nonce from `nonce_seed` and, when it is
+    /// already known, the message to be signed.
+    fn first_round(
+        &self,
+        key_agg_ctx: KeyAggContext,
+        nonce_seed: [u8; 32],
+        signer_index: usize,
+        message: Option<&[u8]>,
+    ) -> Result<FirstRound, SignerIndexError>;
+
+    /// Completes a first round holding every nonce, producing our partial signature. With a
+    /// `tweak`, signs for `public_key() + tweak·G` as used in the key aggregation context.
+    ///
+    /// Failures that happen before the secret nonce is used must hand `first_round` back in
+    /// the [`FinalizeFailure`], so that finalizing can be retried with the same round.
+    fn finalize(
+        &self,
+        first_round: FirstRound,
+        message: Vec<u8>,
+        tweak: Option<Scalar>,
+    ) -> Result<SecondRound<Vec<u8>>, FinalizeFailure>;
+}
+
+/// Error from [`PartialSigner::finalize`].
+pub struct FinalizeFailure {
+    pub error: RoundFinalizeError,
+    /// The round, when it wasn't used up and may be finalized again
+    pub first_round: Option<Box<FirstRound>>,
+}
+
+impl FinalizeFailure {
+    /// A failure that left `first_round` untouched.
+    pub fn retryable(error: RoundFinalizeError, first_round: FirstRound) -> Self {
+        Self {
+            error,
+            first_round: Some(Box::new(first_round)),
+        }
+    }
+}
+
+/// Default backend signing with an in-memory secret key.
+pub struct SoftwareSigner {
+    secret_key: SecretKey,
+    public_key: PublicKey,
+}
+
+impl SoftwareSigner {
+    pub fn new(secret_key: SecretKey, secp: &Secp256k1<All>) -> Self {
+        Self {
+            secret_key,
+            public_key: PublicKey::from_secret_key(secp, &secret_key),
+        }
+    }
+}
+
+impl PartialSigner for SoftwareSigner {
+    fn public_key(&self) -> PublicKey {
+        self.public_key
+    }
+
+    fn first_round(
+        &self,
+        key_agg_ctx: KeyAggContext,
+        nonce_seed: [u8; 32],
+        signer_index: usize,
+        message: Option<&[u8]>,
+    ) -> Result<FirstRound, SignerIndexError> {
+        let mut spices = SecNonceSpices::new().with_seckey(self.secret_key);
+        if let Some(message) = &message {
+            spices = spices.with_message(message);
+        }
+        FirstRound::new(key_agg_ctx, nonce_seed, signer_index, spices)
+    }
+
+    fn finalize(
+        &self,
+        first_round: FirstRound,
+        message: Vec<u8>,
+        tweak: Option<Scalar>,
+    ) -> Result<SecondRound<Vec<u8>>, FinalizeFailure> {
+        if !first_round.is_complete() {
+            return Err(FinalizeFailure::retryable(
+                RoundFinalizeError::Incomplete,
+                first_round,
+            ));
+        }
+        let secret_key = match tweak {
+            Some(tweak) => match (Scalar::from(self.secret_key) + tweak).not_zero() {
+                Ok(secret_key) => SecretKey::from(secret_key),
+                Err(_) => {
+                    return Err(FinalizeFailure::retryable(
+                        RoundFinalizeError::SigningError(SigningError::UnknownKey),
+                        first_round,
+                    ))
+                }
+            },
+            None => self.secret_key,
+        };
+        // From here on the round is consumed, whatever the outcome
+        first_round
+            .finalize(secret_key, message)
+            .map_err(|error| FinalizeFailure {
+                error,
+                first_round: None,
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::secp::{verify_schnorr, SECP256K1};
+    use musig2::{CompactSignature, PartialSignature};
+
+    fn software_signer(byte: u8) -> SoftwareSigner {
+        SoftwareSigner::new(SecretKey::from_slice(&[byte; 32]).unwrap(), &SECP256K1)
+    }
+
+    #[test]
+    fn backends_complete_a_ceremony_through_the_trait() {
+        let backends: Vec<Box<dyn PartialSigner>> =
+            vec![Box::new(software_signer(1)), Box::new(software_signer(2))];
+        let pubkeys: Vec<PublicKey> = backends.iter().map(|b| b.public_key()).collect();
+        let key_agg_ctx = KeyAggContext::new(pubkeys).unwrap();
+        let message = b"backend".to_vec();
+
+        let mut first_rounds: Vec<FirstRound> = backends
+            .iter()
+            .enumerate()
+            .map(|(i, backend)| {
+                backend
+                    .first_round(key_agg_ctx.clone(), [i as u8 + 7; 32], i, Some(&message))
+                    .unwrap()
+            })
+            .collect();
+        let nonces: Vec<_> = first_rounds.iter().map(|r| r.our_public_nonce()).collect();
+        first_rounds[0].receive_nonce(1, nonces[1].clone()).unwrap();
+        first_rounds[1].receive_nonce(0, nonces[0].clone()).unwrap();
+
+        let mut second_rounds: Vec<SecondRound<Vec<u8>>> = backends
+            .iter()
+            .zip(first_rounds)
+            .map(|(backend, round)| {
+                let Ok(second_round) = backend.finalize(round, message.clone(), None) else {
+                    panic!("Failed to finalize a complete round");
+                };
+                second_round
+            })
+            .collect();
+        let partial: PartialSignature = second_rounds[1].our_signature();
+        second_rounds[0].receive_signature(1, partial).unwrap();
+        let signature: CompactSignature = second_rounds[0].finalize().unwrap();
+
+        assert!(verify_schnorr(key_agg_ctx.aggregated_pubkey(), signature, &message).is_ok());
+    }
+
+    #[test]
+    fn nonces_are_derived_from_the_seed() {
+        let backend = software_signer(1);
+        let key_agg_ctx =
+            KeyAggContext::new([backend.public_key(), software_signer(2).public_key()]).unwrap();
+        let nonce = |seed| {
+            backend
+                .first_round(key_agg_ctx.clone(), seed, 0, None)
+                .unwrap()
+                .our_public_nonce()
+        };
+        assert_eq!(nonce([3; 32]), nonce([3; 32]));
+        assert_ne!(nonce([3; 32]), nonce([4; 32]));
+    }
+
+    #[test]
+    fn incomplete_rounds_are_handed_back() {
+        let backend = software_signer(1);
+        let key_agg_ctx =
+            KeyAggContext::new([backend.public_key(), software_signer(2).public_key()]).unwrap();
+        let first_round = backend.first_round(key_agg_ctx, [5; 32], 0, None).unwrap();
+        let nonce = first_round.our_public_nonce();
+
+        let Err(failure) = backend.finalize(first_round, b"early".to_vec(), None) else {
+            panic!("Finalized a round without the other signer's nonce");
+        };
+        assert!(matches!(failure.error, RoundFinalizeError::Incomplete));
+        // The returned round still holds the same secret nonce
+        let first_round = failure.first_round.unwrap();
+        assert_eq!(first_round.our_public_nonce(), nonce);
+    }
+}
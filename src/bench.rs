@@ -0,0 +1,114 @@
+use crate::operator::Operator;
+use crate::types::{MessageEncoding, SigningDomain, SigningRequest};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Instant;
+
+/// End-to-end latency of a series of ceremonies, as measured by [`run_bench`].
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct BenchReport {
+    pub ceremonies: usize,
+    pub failures: usize,
+    /// Latencies of the successful ceremonies in milliseconds; unset when none succeeded
+    pub min_ms: Option<f64>,
+    pub median_ms: Option<f64>,
+    pub p95_ms: Option<f64>,
+    pub max_ms: Option<f64>,
+}
+
+impl BenchReport {
+    /// Builds the report from the latencies of the successful ceremonies, out of `ceremonies`.
+    pub fn from_latencies(ceremonies: usize, mut latencies_ms: Vec<f64>) -> Self {
+        latencies_ms.sort_by(f64::total_cmp);
+        Self {
+            ceremonies,
+            failures: ceremonies.saturating_sub(latencies_ms.len()),
+            min_ms: latencies_ms.first().copied(),
+            median_ms: percentile(&latencies_ms, 0.5),
+            p95_ms: percentile(&latencies_ms, 0.95),
+            max_ms: latencies_ms.last().copied(),
+        }
+    }
+
+    /// Share of the ceremonies that failed, from 0 to 1.
+    pub fn failure_rate(&self) -> f64 {
+        if self.ceremonies == 0 {
+            0.0
+        } else {
+            self.failures as f64 / self.ceremonies as f64
+        }
+    }
+}
+
+/// Nearest-rank percentile of sorted samples.
+fn percentile(sorted: &[f64], fraction: f64) -> Option<f64> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = (fraction * sorted.len() as f64).ceil() as usize;
+    Some(sorted[rank.clamp(1, sorted.len()) - 1])
+}
+
+/// Runs `ceremonies` signing ceremonies one after another with the operator's signers and
+/// measures each from start to verified signature. Every ceremony signs its own message, so
+/// deterministic session IDs don't repeat. Failures are logged and counted, and the run goes
+/// on.
+///
+/// The ceremonies go through the same code as `/sign`, including whatever the operator was
+/// configured to persist or publish; give it a configuration without those to leave no trace.
+pub async fn run_bench(operator: &Operator, ceremonies: usize) -> BenchReport {
+    let mut latencies_ms = Vec::with_capacity(ceremonies);
+    for ceremony in 1..=ceremonies {
+        let request = SigningRequest {
+            message: format!("bench ceremony {}", ceremony),
+            session_id: None,
+            attempt: 0,
+            encodings: Vec::new(),
+            tweaks: HashMap::new(),
+            signer_count: None,
+            message_encoding: MessageEncoding::Utf8,
+            signing_domain: SigningDomain::Raw,
+            include_nonces: false,
+            include_timings: false,
+            taproot_merkle_root: None,
+        };
+        let start = Instant::now();
+        match operator.run_ceremony(request).await {
+            Ok(_) => latencies_ms.push(start.elapsed().as_secs_f64() * 1000.0),
+            Err(e) => eprintln!("Ceremony {}/{} failed: {}", ceremony, ceremonies, e),
+        }
+    }
+    BenchReport::from_latencies(ceremonies, latencies_ms)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percentiles_use_the_nearest_rank() {
+        let latencies: Vec<f64> = (1..=20).rev().map(f64::from).collect();
+        let report = BenchReport::from_latencies(25, latencies);
+        assert_eq!(report.failures, 5);
+        assert_eq!(report.failure_rate(), 0.2);
+        assert_eq!(report.min_ms, Some(1.0));
+        assert_eq!(report.median_ms, Some(10.0));
+        assert_eq!(report.p95_ms, Some(19.0));
+        assert_eq!(report.max_ms, Some(20.0));
+
+        let single = BenchReport::from_latencies(1, vec![7.5]);
+        assert_eq!(single.median_ms, Some(7.5));
+        assert_eq!(single.p95_ms, Some(7.5));
+    }
+
+    #[test]
+    fn runs_without_successes_have_no_latencies() {
+        let report = BenchReport::from_latencies(3, Vec::new());
+        assert_eq!(report.failure_rate(), 1.0);
+        assert_eq!(report.median_ms, None);
+        assert_eq!(
+            BenchReport::from_latencies(0, Vec::new()).failure_rate(),
+            0.0
+        );
+    }
+}
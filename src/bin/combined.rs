@@ -0,0 +1,105 @@
+use clap::Parser;
+use musig2_example::backend::SoftwareSigner;
+use musig2_example::client::HttpClient;
+use musig2_example::operator::{Operator, OperatorConfig};
+use musig2_example::reply;
+use musig2_example::runtime::build_runtime;
+use musig2_example::secp::SECP256K1;
+use musig2_example::signer::Signer;
+use secp256k1::SecretKey;
+use std::net::Ipv4Addr;
+use std::num::NonZeroUsize;
+use std::time::Duration;
+use tokio::net::TcpListener;
+
+/// Operator and a set of in-process signers, for local testing.
+#[derive(Parser, Debug)]
+struct Cli {
+    /// Port to run the operator node
+    #[arg(long, default_value = "3030")]
+    port: u16,
+
+    /// Number of signers to start next to the operator
+    #[arg(long, default_value = "3")]
+    signers: usize,
+
+    /// Pretty-print JSON responses, for reading them during development
+    #[arg(long)]
+    pretty: bool,
+
+    /// Tokio worker threads; one per CPU core when unset
+    #[arg(long)]
+    worker_threads: Option<NonZeroUsize>,
+}
+
+/// Binds a free local port, returning the listener with its port.
+async fn bind_ephemeral() -> std::io::Result<(TcpListener, u16)> {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await?;
+    let port = listener.local_addr()?.port();
+    Ok((listener, port))
+}
+
+fn main() {
+    tracing_subscriber::fmt::init();
+    let args = Cli::parse();
+    reply::set_pretty(args.pretty);
+    build_runtime(args.worker_threads)
+        .expect("Failed to build the tokio runtime")
+        .block_on(run(args));
+}
+
+async fn run(args: Cli) {
+    let config = OperatorConfig {
+        port: args.port,
+        ..Default::default()
+    };
+    let operator = Operator::new(HttpClient::new(), config, None);
+    let server = operator.clone();
+    tokio::spawn(async move {
+        // The server goes down with the process on Ctrl-C
+        if let Err(e) = server.start_server(std::future::pending()).await {
+            eprintln!("Operator server failed: {}", e);
+        }
+    });
+
+    let operator_url = format!("http://127.0.0.1:{}", args.port);
+    for _ in 0..args.signers {
+        // Bound up front, so the signer accepts connections by the time it registers
+        let (listener, port) = match bind_ephemeral().await {
+            Ok(bound) => bound,
+            Err(e) => {
+                eprintln!("Failed to bind a port for a signer: {}", e);
+                return;
+            }
+        };
+        let signer = Signer::new(
+            HttpClient::new(),
+            operator_url.clone(),
+            port,
+            None,
+            None,
+            3,
+            SoftwareSigner::new(SecretKey::new(&mut rand::thread_rng()), &SECP256K1),
+        );
+        let server = signer.clone();
+        tokio::spawn(async move { server.serve(listener).await });
+
+        // The operator server may still be starting up
+        let mut attempts = 0;
+        while let Err(e) = signer.register().await {
+            attempts += 1;
+            if attempts == 10 {
+                eprintln!("Failed to register signer on port {}: {:?}", port, e);
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    }
+
+    println!(
+        "🚀 Operator listening on port {} with {} signer(s).",
+        args.port, args.signers
+    );
+    let _ = tokio::signal::ctrl_c().await;
+    println!("Shutting down...");
+}
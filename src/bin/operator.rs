@@ -1,276 +1,431 @@
-use clap::Parser;
-use musig2::KeyAggContext;
-use musig2_example::client::HttpClient;
-use musig2_example::error::handle_rejection;
-use musig2_example::types::{
-    GenerateNonceRequest, ReceiveNoncesRequest, ReceiveNoncesResponse,
-    ReceivePartialSignaturesRequest, ReceivePartialSignaturesResponse, SignerRegistrationRequest,
-    SigningRequest, SigningResponse, SigningSession,
+use clap::{Parser, Subcommand};
+use musig2_example::bench::run_bench;
+use musig2_example::client::{HttpClient, RetryPolicy, DEFAULT_MAX_REDIRECTS};
+use musig2_example::commitment::KeyCommitment;
+use musig2_example::config::Config;
+use musig2_example::hook::{signature_hook, NoHook, SignatureHookKind};
+use musig2_example::journal::SessionJournal;
+use musig2_example::key_agg::KeyAggEncoding;
+use musig2_example::operator::{
+    load_static_signers, FinalizeAt, Operator, OperatorConfig, DEFAULT_MAX_MESSAGE_LEN,
+    DEFAULT_PORT,
 };
-use secp256k1::PublicKey;
-use std::collections::HashMap;
+use musig2_example::registry::SignerRegistry;
+use musig2_example::reply;
+use musig2_example::runtime::build_runtime;
+use musig2_example::store::SessionStore;
+use musig2_example::types::SignerRegistrationRequest;
+use musig2_example::verify::VerifyPolicy;
+use std::net::{IpAddr, Ipv4Addr};
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
 use std::sync::Arc;
-use tokio::sync::Mutex;
-use uuid::Uuid;
-use warp::Filter;
+use std::time::Duration;
 
 /// Operator node for managing communication between signers.
 #[derive(Parser, Debug)]
 struct Cli {
-    /// Port to run the operator node
-    #[arg(long, default_value = "3030")]
-    port: u16,
-}
+    /// TOML file with operator settings; flags given here take precedence over it
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    /// Port to run the operator node [default: 3030]
+    #[arg(long)]
+    port: Option<u16>,
+
+    /// Address to bind the operator node to [default: 127.0.0.1]
+    #[arg(long)]
+    bind_address: Option<IpAddr>,
+
+    /// Derive session IDs from the participants, message and attempt instead of random UUIDs
+    #[arg(long)]
+    deterministic_session_ids: bool,
+
+    /// Maximum number of messages accepted by a single /sign-batch request
+    #[arg(long, default_value = "16")]
+    max_batch_size: usize,
+
+    /// Longest message accepted by /sign and /sign-batch, in bytes after decoding
+    #[arg(long, default_value_t = DEFAULT_MAX_MESSAGE_LEN)]
+    max_message_len: usize,
+
+    /// Run an interactive command prompt next to the HTTP server
+    #[arg(long)]
+    interactive: bool,
+
+    /// File recording the aggregated key, checked against later ceremonies
+    #[arg(long)]
+    key_commitment: Option<PathBuf>,
+
+    /// Directory persisting sessions awaiting distribution, which are recovered on startup
+    #[arg(long)]
+    session_journal: Option<PathBuf>,
+
+    /// SQLite database recording every session's progress; interrupted sessions are listed on startup
+    #[arg(long)]
+    session_db: Option<PathBuf>,
+
+    /// How many times to verify the aggregated signature
+    #[arg(long, value_enum, default_value_t = VerifyPolicy::Single)]
+    verify_policy: VerifyPolicy,
+
+    /// JSON file with a fixed list of signers; disables the /register endpoint
+    #[arg(long)]
+    static_signers: Option<PathBuf>,
+
+    /// Drop a signer that misbehaves mid-ceremony and retry with the remaining ones; the
+    /// aggregated key then changes to that of the remaining signers
+    #[arg(long)]
+    exclude_faulty_signers: bool,
+
+    /// Continue without signers that fail the nonce round, as long as at least this many
+    /// answered; the aggregated key then changes to that of the answering signers
+    #[arg(long, value_parser = clap::value_parser!(u64).range(2..))]
+    min_signers: Option<u64>,
+
+    /// Milliseconds allowed for collecting nonces from all signers
+    #[arg(long)]
+    nonce_deadline: Option<u64>,
+
+    /// Milliseconds allowed for collecting partial signatures from all signers
+    #[arg(long)]
+    partial_deadline: Option<u64>,
+
+    /// Milliseconds allowed for distributing partial signatures to all signers
+    #[arg(long)]
+    distribution_deadline: Option<u64>,
+
+    /// Validate signer responses against the protocol schema, rejecting incompatible signers
+    #[arg(long)]
+    strict_signer_responses: bool,
+
+    /// Whether the operator or the signers produce the final signature
+    #[arg(long, value_enum, default_value_t = FinalizeAt::Signers)]
+    finalize_at: FinalizeAt,
+
+    /// Times to rerun a ceremony after losing the connection to a signer
+    #[arg(long, default_value = "0")]
+    connection_retries: u32,
+
+    /// Times to resend a single request to a signer that failed to connect to it
+    #[arg(long, default_value = "0")]
+    request_retries: u32,
+
+    /// Wait before the first resend of a request, doubled for each further one, in milliseconds
+    #[arg(long, default_value = "100")]
+    request_retry_delay_ms: u64,
+
+    /// Send signers the full key aggregation context, or just the expected aggregated key
+    #[arg(long, value_enum, default_value_t = KeyAggEncoding::Full)]
+    key_agg_encoding: KeyAggEncoding,
+
+    /// Redirects followed per request to a signer; only 307 and 308 redirects are followed
+    #[arg(long, default_value_t = DEFAULT_MAX_REDIRECTS)]
+    max_signer_redirects: usize,
+
+    /// Timeout for each request to a signer, in milliseconds
+    #[arg(long)]
+    signer_timeout_ms: Option<u64>,
 
-#[derive(Debug)]
-#[allow(dead_code)]
-struct OperatorError(String);
+    /// Key shared with the signers, used to sign every request sent to them
+    #[arg(long)]
+    operator_auth_key: Option<String>,
 
-impl warp::reject::Reject for OperatorError {}
+    /// Token required on admin endpoints; admin endpoints are disabled when unset
+    #[arg(long)]
+    admin_token: Option<String>,
 
-#[derive(Clone)]
-struct Operator {
-    client: HttpClient,
-    port: u16,
-    signers: Arc<Mutex<HashMap<(usize, PublicKey), String>>>,
-    session: Arc<Mutex<Option<SigningSession>>>,
-}
+    /// Unix socket to write each completed ceremony's response to, as a line of JSON
+    #[arg(long)]
+    result_socket: Option<PathBuf>,
 
-impl Operator {
-    pub fn new(client: HttpClient, port: u16) -> Self {
-        Self {
-            client,
-            port,
-            signers: Arc::new(Mutex::new(HashMap::new())),
-            session: Arc::new(Mutex::new(None)),
-        }
-    }
+    /// What to do with each completed ceremony's signature
+    #[arg(long, value_enum, default_value_t = SignatureHookKind::None)]
+    signature_hook: SignatureHookKind,
 
-    pub async fn start_server(&self) {
-        let state = self.clone();
-        let state_filter = warp::any().map(move || state.clone());
+    /// URL the webhook signature hook POSTs each completed ceremony's response to
+    #[arg(long)]
+    signature_webhook_url: Option<String>,
 
-        // Register signer endpoint
-        let register = warp::post()
-            .and(warp::path("register"))
-            .and(warp::body::json())
-            .and(state_filter.clone())
-            .and_then(|req, state: Operator| async move { state.register_signer(req).await });
+    /// Write a JSON summary of ceremony metrics to this file on shutdown
+    #[arg(long)]
+    metrics_out: Option<PathBuf>,
 
-        // Signing endpoint
-        let sign = warp::post()
-            .and(warp::path("sign"))
-            .and(warp::body::json())
-            .and(state_filter.clone())
-            .and_then(|req, state: Operator| async move { state.sign_message(req).await });
+    /// Pretty-print JSON responses, for reading them during development
+    #[arg(long)]
+    pretty: bool,
 
-        let routes = register.or(sign).recover(handle_rejection);
+    /// Tokio worker threads; one per CPU core when unset
+    #[arg(long)]
+    worker_threads: Option<NonZeroUsize>,
 
-        println!("Operator running on port {}...", self.port);
-        warp::serve(routes).run(([127, 0, 0, 1], self.port)).await;
-    }
+    #[command(subcommand)]
+    command: Option<Command>,
+}
 
-    async fn register_signer(
-        self,
-        registration: SignerRegistrationRequest,
-    ) -> Result<impl warp::Reply, warp::Rejection> {
-        let mut signers = self.signers.lock().await;
-        let index = signers.len();
-        signers.insert((index, registration.public_key), registration.address);
-        println!(
-            "🔑 Signer node with index {} and public key {} registered successfully.",
-            index, registration.public_key
-        );
-        Ok(warp::reply::json(
-            &"Registered successfully with public key",
-        ))
-    }
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Run ceremonies against the signers from --static-signers or --session-db and report
+    /// their latency, instead of serving requests; nothing is persisted
+    Bench {
+        /// Number of ceremonies to run
+        #[arg(long, default_value = "10")]
+        ceremonies: NonZeroUsize,
+    },
+}
 
-    async fn sign_message(
-        self,
-        request: SigningRequest,
-    ) -> Result<impl warp::Reply, warp::Rejection> {
-        println!("Initiating signing of the message: {:?}", request.message);
-        let signers = self.signers.lock().await;
-
-        // Create KeyAggContext from registered signers
-        let pubkeys: Vec<PublicKey> = signers.iter().map(|((_, pubkey), _)| *pubkey).collect();
-
-        // println!("Pubkeys for KeyAggContext: {:?}", pubkeys);
-
-        let key_agg_ctx = KeyAggContext::new(pubkeys).map_err(|_| {
-            warp::reject::custom(OperatorError(
-                "Failed to create key aggregation context".to_string(),
-            ))
-        })?;
-
-        // Create new session
-        let session_id = Uuid::new_v4().to_string();
-        let session = SigningSession {
-            session_id: session_id.clone(),
-            message: request.message.clone(),
-            key_agg_ctx: key_agg_ctx.clone(),
-        };
-
-        // Store session
-        let mut session_guard = self.session.lock().await;
-        *session_guard = Some(session);
-
-        // Request nonces from all signers
-        let client = self.client.inner();
-        let mut indexed_nonces = HashMap::new();
-
-        for ((i, _), address) in signers.iter() {
-            let nonce_request = GenerateNonceRequest {
-                session_id: session_id.clone(),
-                message: request.message.clone(),
-                key_agg_ctx: key_agg_ctx.clone(),
-                signer_index: *i,
-            };
-
-            let response = client
-                .post(format!("{}/nonce", address))
-                .json(&nonce_request)
-                .send()
-                .await
-                .map_err(|_| {
-                    warp::reject::custom(OperatorError("Failed to request nonce".to_string()))
-                })?;
-
-            let nonce: Vec<u8> = response.json().await.map_err(|_| {
-                warp::reject::custom(OperatorError("Failed to parse nonce response".to_string()))
-            })?;
-
-            indexed_nonces.insert(*i, nonce.clone());
-        }
+fn main() -> Result<(), warp::Error> {
+    tracing_subscriber::fmt::init();
+    let args = Cli::parse();
+    reply::set_pretty(args.pretty);
+    build_runtime(args.worker_threads)
+        .expect("Failed to build the tokio runtime")
+        .block_on(run(args))
+}
 
-        // Distribute nonces to all signers and collect partial signatures
-        let client = self.client.inner();
-        let mut indexed_partial_sigs = HashMap::new();
-
-        for ((i, _), address) in signers.iter() {
-            let mut other_nonces = indexed_nonces.clone();
-            // Remove this signer's own nonce
-            other_nonces.remove(&i);
-
-            let receive_nonces_request = ReceiveNoncesRequest {
-                session_id: session_id.clone(),
-                nonces: other_nonces,
-            };
-
-            let response: ReceiveNoncesResponse = client
-                .put(format!("{}/nonces", address))
-                .json(&receive_nonces_request)
-                .send()
-                .await
-                .map_err(|_| {
-                    warp::reject::custom(OperatorError("Failed to distribute nonces".to_string()))
-                })?
-                .json()
-                .await
-                .map_err(|_| {
-                    warp::reject::custom(OperatorError(
-                        "Failed to parse response from /nonces".to_string(),
-                    ))
-                })?;
-
-            indexed_partial_sigs.insert(*i, response.partial_signature);
+async fn run(args: Cli) -> Result<(), warp::Error> {
+    let bench_ceremonies = args
+        .command
+        .as_ref()
+        .map(|Command::Bench { ceremonies }| ceremonies.get());
+
+    let file_config = match &args.config {
+        Some(path) => match Config::load(path) {
+            Ok(config) => config,
+            Err(e) => {
+                eprintln!("Failed to load the config from {}: {}", path.display(), e);
+                return Ok(());
+            }
+        },
+        None => Config::default(),
+    };
+
+    let key_commitment = match &args.key_commitment {
+        Some(path) => match KeyCommitment::load(path) {
+            Ok(Some(commitment)) => {
+                println!(
+                    "📌 Loaded aggregated key {} for participant set {}",
+                    commitment.aggregated_key, commitment.participant_set_hash
+                );
+                Some(commitment)
+            }
+            Ok(None) => None,
+            Err(e) => {
+                eprintln!(
+                    "Failed to load key commitment from {}: {}",
+                    path.display(),
+                    e
+                );
+                return Ok(());
+            }
+        },
+        None => None,
+    };
+
+    let static_signers = match &args.static_signers {
+        Some(path) => match load_static_signers(path) {
+            Ok(signers) => {
+                println!(
+                    "📋 Loaded {} static signer(s) from {}, registration is disabled.",
+                    signers.len(),
+                    path.display()
+                );
+                Some(signers)
+            }
+            Err(e) => {
+                eprintln!(
+                    "Failed to load static signers from {}: {}",
+                    path.display(),
+                    e
+                );
+                return Ok(());
+            }
+        },
+        None => None,
+    };
+
+    let session_journal = match &args.session_journal {
+        // A bench leaves nothing to recover
+        Some(_) if bench_ceremonies.is_some() => None,
+        Some(dir) => match SessionJournal::open(dir) {
+            Ok(journal) => Some(journal),
+            Err(e) => {
+                eprintln!(
+                    "Failed to open the session journal at {}: {}",
+                    dir.display(),
+                    e
+                );
+                return Ok(());
+            }
+        },
+        None => None,
+    };
+
+    let session_store = match &args.session_db {
+        // Opening a missing database would create it
+        Some(path) if bench_ceremonies.is_some() && !path.exists() => {
+            eprintln!("No session database at {}", path.display());
+            return Ok(());
         }
-
-        // Distribute partial signatures to all signers
-        let client = self.client.inner();
-        let mut final_signatures = Vec::new();
-
-        for ((i, _), address) in signers.iter() {
-            let mut other_sigs = indexed_partial_sigs.clone();
-            // Remove this signer's own partial signature
-            other_sigs.remove(&i);
-
-            // println!(
-            //     "Sending partial signatures to signer {} at {}",
-            //     pubkey, address
-            // );
-            // println!(
-            //     "Sending {} partial signatures: {:?}",
-            //     other_sigs.len(),
-            //     other_sigs
-            // );
-
-            let partial_sigs_request = ReceivePartialSignaturesRequest {
-                session_id: session_id.clone(),
-                partial_signatures: other_sigs,
-            };
-
-            let response = client
-                .put(format!("{}/partial-signatures", address))
-                .json(&partial_sigs_request)
-                .send()
-                .await
-                .map_err(|e| {
-                    eprintln!("Failed to send request to {}: {:?}", address, e);
-                    warp::reject::custom(OperatorError("Failed to send request".to_string()))
-                })?;
-
-            // Handle non-success status codes
-            if !response.status().is_success() {
-                let error_text = response.text().await.map_err(|e| {
-                    eprintln!("Failed to get error response text: {:?}", e);
-                    warp::reject::custom(OperatorError("Failed to get error response".to_string()))
-                })?;
-                eprintln!("Error response from {}: {}", address, error_text);
-                return Err(warp::reject::custom(OperatorError(format!(
-                    "Signer error: {}",
-                    error_text
-                ))));
+        Some(path) => match SessionStore::open(path) {
+            Ok(store) => Some(store),
+            Err(e) => {
+                eprintln!(
+                    "Failed to open the session database at {}: {}",
+                    path.display(),
+                    e
+                );
+                return Ok(());
             }
-
-            // Try to parse the response
-            let parsed_response: ReceivePartialSignaturesResponse =
-                response.json().await.map_err(|e| {
-                    eprintln!("Failed to parse response JSON: {:?}", e);
-                    warp::reject::custom(OperatorError("Failed to parse response".to_string()))
-                })?;
-
-            final_signatures.push(parsed_response.final_signature);
+        },
+        None => None,
+    };
+
+    let signature_hook = match signature_hook(args.signature_hook, args.signature_webhook_url) {
+        Ok(hook) => hook,
+        Err(e) => {
+            eprintln!("Invalid signature hook: {}", e);
+            return Ok(());
         }
+    };
+
+    let config = OperatorConfig {
+        port: args.port.or(file_config.port).unwrap_or(DEFAULT_PORT),
+        bind_address: args
+            .bind_address
+            .or(file_config.bind_address)
+            .unwrap_or(Ipv4Addr::LOCALHOST.into()),
+        deterministic_session_ids: args.deterministic_session_ids,
+        max_batch_size: args.max_batch_size,
+        max_message_len: args.max_message_len,
+        key_commitment_path: args.key_commitment,
+        verify_policy: args.verify_policy,
+        static_signers,
+        exclude_faulty_signers: args.exclude_faulty_signers,
+        min_signers: args
+            .min_signers
+            .map(|count| count as usize)
+            .or(file_config.min_signers),
+        nonce_deadline: args.nonce_deadline.map(Duration::from_millis),
+        partial_deadline: args.partial_deadline.map(Duration::from_millis),
+        distribution_deadline: args.distribution_deadline.map(Duration::from_millis),
+        strict_signer_responses: args.strict_signer_responses,
+        admin_token: args.admin_token,
+        finalize_at: args.finalize_at,
+        connection_retries: args.connection_retries,
+        request_retries: RetryPolicy {
+            max_retries: args.request_retries,
+            base_delay: Duration::from_millis(args.request_retry_delay_ms),
+        },
+        key_agg_encoding: args.key_agg_encoding,
+        signer_timeout: args
+            .signer_timeout_ms
+            .or(file_config.signer_timeout_ms)
+            .map(Duration::from_millis),
+        operator_auth_key: args.operator_auth_key,
+        session_journal,
+        session_store,
+        result_socket: args.result_socket,
+        signature_hook,
+    };
+
+    let client = HttpClient::with_max_redirects(args.max_signer_redirects);
+    if let Some(ceremonies) = bench_ceremonies {
+        bench(client, config, ceremonies).await;
+        return Ok(());
+    }
+    let operator = Operator::new(client, config, key_commitment);
+    operator.recover_sessions().await;
+
+    if args.interactive {
+        let server = operator.clone();
+        // The server goes down with the process when the prompt exits
+        tokio::spawn(async move {
+            if let Err(e) = server.start_server(std::future::pending()).await {
+                eprintln!("Operator server failed: {}", e);
+            }
+        });
+        operator
+            .run_repl(
+                tokio::io::BufReader::new(tokio::io::stdin()),
+                tokio::io::stdout(),
+            )
+            .await;
+    } else {
+        // Start operator server, draining in-flight requests on Ctrl-C
+        operator
+            .start_server(async {
+                let _ = tokio::signal::ctrl_c().await;
+                println!("Shutting down operator...");
+            })
+            .await?;
+    }
 
-        // Verify all signers produced the same final signature
-        if !final_signatures.windows(2).all(|w| w[0] == w[1]) {
-            return Err(warp::reject::custom(OperatorError(
-                "Inconsistent final signatures".to_string(),
-            )));
+    if let Some(path) = &args.metrics_out {
+        match operator.write_metrics(path) {
+            Ok(()) => println!("📊 Wrote ceremony metrics to {}", path.display()),
+            Err(e) => eprintln!("Failed to write metrics to {}: {}", path.display(), e),
         }
-
-        // Since all signers produced the same final signature, we can use the first one
-        let aggregated_signature = final_signatures[0];
-        // Get the aggregated pubkey
-        let aggregated_pubkey: PublicKey = key_agg_ctx.aggregated_pubkey();
-
-        // Verify the signature
-        let is_signature_valid = musig2::verify_single(
-            aggregated_pubkey,
-            aggregated_signature,
-            request.message.as_bytes(),
-        )
-        .is_ok();
-
-        let response = SigningResponse {
-            session_id,
-            aggregated_pubkey,
-            aggregated_signature,
-            is_signature_valid,
-        };
-
-        Ok(warp::reply::json(&response))
     }
+    Ok(())
 }
 
-#[tokio::main]
-async fn main() {
-    let args = Cli::parse();
+/// Runs the `bench` subcommand. The signers are read once, and the ceremonies run without the
+/// session database, the key commitment, the result socket or the signature hook, so that
+/// nothing outlives the process.
+async fn bench(client: HttpClient, mut config: OperatorConfig, ceremonies: usize) {
+    let signers = match (config.static_signers.take(), config.session_store.take()) {
+        (Some(signers), _) => signers,
+        (None, Some(store)) => match store.signers() {
+            Ok(signers) => signers
+                .into_iter()
+                .map(|signer| SignerRegistrationRequest {
+                    address: signer.address,
+                    public_key: signer.public_key,
+                })
+                .collect(),
+            Err(e) => {
+                eprintln!("Failed to read the registered signers: {}", e);
+                return;
+            }
+        },
+        (None, None) => {
+            eprintln!("bench runs against the signers from --static-signers or --session-db");
+            return;
+        }
+    };
+    if signers.len() < 2 {
+        eprintln!("bench needs at least 2 signers, found {}", signers.len());
+        return;
+    }
 
-    let client = HttpClient::new();
-    let operator = Operator::new(client, args.port);
-    // Start operator server
-    operator.start_server().await;
+    println!(
+        "⏱️ Running {} ceremonies with {} signers...",
+        ceremonies,
+        signers.len()
+    );
+    config.static_signers = Some(signers);
+    config.key_commitment_path = None;
+    config.result_socket = None;
+    config.signature_hook = Arc::new(NoHook);
+    let operator = Operator::new(client, config, None);
+    let report = run_bench(&operator, ceremonies).await;
+
+    let ms = |latency: Option<f64>| match latency {
+        Some(latency) => format!("{:.1} ms", latency),
+        None => "-".to_string(),
+    };
+    println!(
+        "📊 {} ceremonies, {} failed ({:.1}%)",
+        report.ceremonies,
+        report.failures,
+        report.failure_rate() * 100.0
+    );
+    println!(
+        "   min {}, median {}, p95 {}, max {}",
+        ms(report.min_ms),
+        ms(report.median_ms),
+        ms(report.p95_ms),
+        ms(report.max_ms)
+    );
 }
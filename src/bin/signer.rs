@@ -1,19 +1,15 @@
 use clap::Parser;
-use musig2::{FirstRound, PartialSignature, PubNonce, SecNonceSpices, SecondRound};
+use musig2_example::backend::SoftwareSigner;
 use musig2_example::client::HttpClient;
-use musig2_example::error::handle_rejection;
-use musig2_example::types::{
-    GenerateNonceRequest, ReceiveNoncesRequest, ReceiveNoncesResponse,
-    ReceivePartialSignaturesRequest, ReceivePartialSignaturesResponse, SignerRegistrationRequest,
-    SigningSession,
-};
-use secp256k1::{PublicKey, Secp256k1, SecretKey};
-use warp::Filter;
-
-use rand::Rng;
-use std::collections::HashMap;
-use std::sync::Arc;
-use tokio::sync::Mutex;
+use musig2_example::keystore::{self, SECRET_KEY_ENV};
+use musig2_example::reply;
+use musig2_example::rng::MAX_NONCE_SEED_ATTEMPTS;
+use musig2_example::runtime::build_runtime;
+use musig2_example::secp::SECP256K1;
+use musig2_example::signer::Signer;
+use secp256k1::SecretKey;
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
 
 /// Signer node for responding to signing requests.
 #[derive(Parser, Debug)]
@@ -25,252 +21,119 @@ struct Cli {
     /// Operator URL
     #[arg(long, default_value = "http://127.0.0.1:3030")]
     operator_url: String,
-}
-
-#[derive(Debug)]
-#[allow(dead_code)]
-struct SignerError(String);
-
-impl warp::reject::Reject for SignerError {}
-
-#[derive(Clone)]
-struct Signer {
-    client: HttpClient,
-    operator_url: String,
-    url: String,
-    secret_key: SecretKey,
-    public_key: PublicKey,
-    session: Arc<Mutex<Option<SigningSession>>>,
-    first_rounds: Arc<Mutex<HashMap<String, FirstRound>>>,
-    second_rounds: Arc<Mutex<HashMap<String, SecondRound<Vec<u8>>>>>,
-}
-
-impl Signer {
-    pub fn new(client: HttpClient, operator_url: String, port: u16) -> Self {
-        let address = format!("http://127.0.0.1:{}", port);
-        let secp = Secp256k1::new();
-        let secret_key = SecretKey::new(&mut rand::thread_rng());
-        let public_key = PublicKey::from_secret_key(&secp, &secret_key);
-        println!("Public key: {:?}", public_key);
-        Self {
-            client,
-            operator_url,
-            url: address,
-            secret_key,
-            public_key,
-            session: Arc::new(Mutex::new(None)),
-            first_rounds: Arc::new(Mutex::new(HashMap::new())),
-            second_rounds: Arc::new(Mutex::new(HashMap::new())),
-        }
-    }
-
-    pub async fn register(&self) -> Result<impl warp::Reply, warp::Rejection> {
-        // Submit public key to operator
-        let registration = SignerRegistrationRequest {
-            address: self.url.clone(),
-            public_key: self.public_key,
-        };
-
-        let response = self
-            .client
-            .inner()
-            .post(format!("{}/register", self.operator_url))
-            .json(&registration)
-            .send()
-            .await
-            .map_err(|e| warp::reject::custom(SignerError(e.to_string())))?;
 
-        if response.status().is_success() {
-            println!("✅ Signer node registered successfully.");
-            Ok(warp::reply())
-        } else {
-            let error = response
-                .text()
-                .await
-                .map_err(|e| warp::reject::custom(SignerError(e.to_string())))?;
-            Err(warp::reject::custom(SignerError(error)))
-        }
-    }
+    /// Token required on admin endpoints; admin endpoints are disabled when unset
+    #[arg(long)]
+    admin_token: Option<String>,
 
-    pub async fn start_server(&self) {
-        let state = self.clone();
-        let state_filter = warp::any().map(move || state.clone());
+    /// Key shared with the operator; requests to the signing endpoints must be signed with it
+    #[arg(long)]
+    operator_auth_key: Option<String>,
 
-        // Generate nonce endpoint
-        let generate_nonce = warp::post()
-            .and(warp::path("nonce"))
-            .and(warp::body::json())
-            .and(state_filter.clone())
-            .and_then(|req, state: Signer| async move { state.handle_generate_nonce(req).await });
+    /// Don't register with the operator, e.g. when it uses a static signer set
+    #[arg(long)]
+    skip_registration: bool,
 
-        // Receive nonces endpoint
-        let receive_nonces = warp::put()
-            .and(warp::path("nonces"))
-            .and(warp::body::json())
-            .and(state_filter.clone())
-            .and_then(|req, state: Signer| async move { state.handle_receive_nonces(req).await });
+    /// Hex-encoded secret key to sign with. Falls back to the SIGNER_SECRET_KEY environment
+    /// variable, then to a freshly generated key
+    #[arg(long, conflicts_with = "keystore")]
+    secret_key: Option<SecretKey>,
 
-        // Receive partial signatures endpoint
-        let receive_partial_signatures = warp::put()
-            .and(warp::path("partial-signatures"))
-            .and(warp::body::json())
-            .and(state_filter.clone())
-            .and_then(|req, state: Signer| async move {
-                state.handle_receive_partial_signatures(req).await
-            });
+    /// Encrypted file to load the secret key from, created with a fresh key if it doesn't exist
+    #[arg(long, requires = "keystore_password")]
+    keystore: Option<PathBuf>,
 
-        let routes = generate_nonce
-            .or(receive_nonces)
-            .or(receive_partial_signatures)
-            .recover(handle_rejection);
+    /// Password the keystore is encrypted with
+    #[arg(long, requires = "keystore")]
+    keystore_password: Option<String>,
 
-        println!(
-            "Signer running on port {}...",
-            self.url.split(':').last().unwrap()
-        );
-        warp::serve(routes)
-            .run((
-                [127, 0, 0, 1],
-                self.url.split(':').last().unwrap().parse().unwrap(),
-            ))
-            .await;
-    }
+    /// Draws of the RNG per nonce while it produces weak seeds, before the nonce request fails
+    #[arg(long, default_value_t = 3, value_parser = clap::value_parser!(u32).range(1..=MAX_NONCE_SEED_ATTEMPTS as i64))]
+    nonce_seed_attempts: u32,
 
-    async fn handle_generate_nonce(
-        self,
-        request: GenerateNonceRequest,
-    ) -> Result<impl warp::Reply, warp::Rejection> {
-        let first_round = FirstRound::new(
-            request.key_agg_ctx.clone(),
-            rand::thread_rng().gen::<[u8; 32]>(),
-            request.signer_index,
-            SecNonceSpices::new()
-                .with_seckey(self.secret_key)
-                .with_message(&request.message.as_bytes().to_vec()),
-        )
-        .map_err(|_| warp::reject::custom(SignerError("Failed to generate nonce".to_string())))?;
+    /// Register as a warm standby for the signer holding the same key
+    #[arg(long)]
+    standby: bool,
 
-        let public_nonce = first_round.our_public_nonce();
+    /// Pretty-print JSON responses, for reading them during development
+    #[arg(long)]
+    pretty: bool,
 
-        // Store session data and FirstRound separately
-        let mut session_guard = self.session.lock().await;
-        let mut first_rounds = self.first_rounds.lock().await;
+    /// Tokio worker threads; one per CPU core when unset
+    #[arg(long)]
+    worker_threads: Option<NonZeroUsize>,
+}
 
-        let session = SigningSession {
-            session_id: request.session_id.clone(),
-            message: request.message.clone(),
-            key_agg_ctx: request.key_agg_ctx,
+/// The secret key to sign with and where it came from: the keystore, `--secret-key`, the
+/// environment, or freshly generated, in that order.
+fn load_secret_key(args: &Cli) -> Result<(SecretKey, String), String> {
+    if let (Some(path), Some(password)) = (&args.keystore, &args.keystore_password) {
+        let (secret_key, created) = keystore::load_or_create(path, password)
+            .map_err(|e| format!("Failed to load the keystore at {}: {}", path.display(), e))?;
+        let source = if created {
+            format!("a new key generated into {}", path.display())
+        } else {
+            format!("the key from {}", path.display())
         };
-        *session_guard = Some(session);
-
-        first_rounds.insert(request.session_id, first_round);
-
-        Ok(warp::reply::json(&public_nonce.serialize().to_vec()))
+        return Ok((secret_key, source));
     }
+    if let Some(secret_key) = args.secret_key {
+        return Ok((secret_key, "the key from --secret-key".to_string()));
+    }
+    match keystore::secret_key_from_env() {
+        Ok(Some(secret_key)) => Ok((secret_key, format!("the key from {}", SECRET_KEY_ENV))),
+        Ok(None) => Ok((
+            SecretKey::new(&mut rand::thread_rng()),
+            "a freshly generated key".to_string(),
+        )),
+        Err(e) => Err(format!("Invalid {}: {}", SECRET_KEY_ENV, e)),
+    }
+}
 
-    async fn handle_receive_nonces(
-        self,
-        request: ReceiveNoncesRequest,
-    ) -> Result<impl warp::Reply, warp::Rejection> {
-        let session_guard = self.session.lock().await;
-        let session = session_guard.as_ref().ok_or_else(|| {
-            warp::reject::custom(SignerError("No active session found".to_string()))
-        })?;
-
-        let mut first_rounds = self.first_rounds.lock().await;
-        let mut second_rounds = self.second_rounds.lock().await;
-
-        let mut first_round = first_rounds.remove(&request.session_id).ok_or_else(|| {
-            warp::reject::custom(SignerError("First round not found".to_string()))
-        })?;
-
-        // Receive nonces from other signers
-        for (index, nonce_bytes) in request.nonces {
-            // println!("Received nonce for signer index {}", index);
-            let other_nonce = PubNonce::from_bytes(&nonce_bytes).map_err(|_| {
-                warp::reject::custom(SignerError("Invalid nonce format".to_string()))
-            })?;
+fn main() {
+    let args = Cli::parse();
+    reply::set_pretty(args.pretty);
+    build_runtime(args.worker_threads)
+        .expect("Failed to build the tokio runtime")
+        .block_on(run(args));
+}
 
-            first_round.receive_nonce(index, other_nonce).map_err(|e| {
-                eprintln!("Failed to receive nonce from index {}: {:?}", index, e);
-                warp::reject::custom(SignerError(format!(
-                    "Failed to receive nonce from index {}",
-                    index
-                )))
-            })?;
+async fn run(args: Cli) {
+    let secret_key = match load_secret_key(&args) {
+        Ok((secret_key, source)) => {
+            println!("🔑 Signing with {}", source);
+            secret_key
         }
+        Err(e) => {
+            eprintln!("{}", e);
+            return;
+        }
+    };
 
-        // Finalize first round
-        let message_bytes = session.message.as_bytes().to_vec();
-
-        let second_round = first_round
-            .finalize(self.secret_key, message_bytes.clone())
-            .map_err(|_| {
-                warp::reject::custom(SignerError("Failed to finalize first round".to_string()))
-            })?;
-
-        let partial_signature: PartialSignature = second_round.our_signature();
-        second_rounds.insert(request.session_id.clone(), second_round);
-        println!(
-            "Partial signature: {:?}",
-            hex::encode(partial_signature.serialize())
-        );
-
-        Ok(warp::reply::json(&ReceiveNoncesResponse {
-            partial_signature,
-        }))
+    let client = HttpClient::new();
+    let signer = Signer::new(
+        client,
+        args.operator_url,
+        args.port,
+        args.admin_token,
+        args.operator_auth_key,
+        args.nonce_seed_attempts,
+        SoftwareSigner::new(secret_key, &SECP256K1),
+    );
+    // Registration keeps retrying in the background, so the operator may come up later
+    let registered = args.standby || !args.skip_registration;
+    if registered {
+        signer.register_in_background(args.standby).await;
     }
-
-    async fn handle_receive_partial_signatures(
-        self,
-        request: ReceivePartialSignaturesRequest,
-    ) -> Result<impl warp::Reply, warp::Rejection> {
-        let mut second_rounds = self.second_rounds.lock().await;
-
-        let mut second_round = second_rounds.remove(&request.session_id).ok_or_else(|| {
-            warp::reject::custom(SignerError("Second round not found".to_string()))
-        })?;
-
-        // Receive partial signatures from other signers
-        for (index, sig) in request.partial_signatures {
-            // println!(
-            //     "Processing partial signature for signer index {}: {:?}",
-            //     index, sig
-            // );
-            // let our_partial_signature: PartialSignature = second_round.our_signature();
-            // println!(
-            //     "Our signer's partial signature: {:?}",
-            //     our_partial_signature
-            // );
-            if let Err(e) = second_round.receive_signature(index, sig) {
-                eprintln!("Failed to receive signature from index {}: {:?}", index, e);
-                return Err(warp::reject::custom(SignerError(format!(
-                    "Failed to receive partial signature from index {}",
-                    index
-                ))));
+    // Start signer server, leaving the operator's signer set on Ctrl-C
+    tokio::select! {
+        _ = signer.start_server() => {}
+        _ = tokio::signal::ctrl_c() => {
+            if registered {
+                if let Err(rejection) = signer.deregister().await {
+                    eprintln!("Failed to deregister from the operator: {:?}", rejection);
+                }
             }
         }
-
-        // Finalize to get the final signature
-        let final_signature = second_round.finalize().map_err(|e| {
-            eprintln!("Failed to finalize signature: {:?}", e);
-            warp::reject::custom(SignerError("Failed to finalize signature".to_string()))
-        })?;
-
-        Ok(warp::reply::json(&ReceivePartialSignaturesResponse {
-            final_signature,
-        }))
     }
 }
-
-#[tokio::main]
-async fn main() {
-    let args = Cli::parse();
-
-    let client = HttpClient::new();
-    let signer = Signer::new(client, args.operator_url, args.port);
-    // Register signer to the operator
-    signer.register().await.unwrap();
-    // Start signer server
-    signer.start_server().await;
-}
@@ -1,20 +1,42 @@
-use reqwest::Client;
+use reqwest::redirect::{Attempt, Policy};
+use reqwest::{Client, RequestBuilder, Response, StatusCode};
+use std::time::Duration;
+
+/// Redirects followed per request unless configured otherwise.
+pub const DEFAULT_MAX_REDIRECTS: usize = 3;
 
 #[derive(Clone)]
 pub struct HttpClient {
     client: Client,
+    max_redirects: usize,
 }
 
 impl HttpClient {
     pub fn new() -> Self {
+        Self::with_max_redirects(DEFAULT_MAX_REDIRECTS)
+    }
+
+    /// A client following at most `max_redirects` redirects per request, and only 307 and 308
+    /// ones: the others turn a POST or PUT into a GET without its body, which no peer endpoint
+    /// answers meaningfully. Any other redirect is handed back as the response itself.
+    pub fn with_max_redirects(max_redirects: usize) -> Self {
+        let policy = Policy::custom(move |attempt| follow_redirect(attempt, max_redirects));
         Self {
-            client: Client::new(),
+            client: Client::builder()
+                .redirect(policy)
+                .build()
+                .expect("Failed to build the HTTP client"),
+            max_redirects,
         }
     }
 
     pub fn inner(&self) -> &Client {
         &self.client
     }
+
+    pub fn max_redirects(&self) -> usize {
+        self.max_redirects
+    }
 }
 
 impl Default for HttpClient {
@@ -22,3 +44,200 @@ impl Default for HttpClient {
         Self::new()
     }
 }
+
+fn follow_redirect(attempt: Attempt, max_redirects: usize) -> reqwest::redirect::Action {
+    if !matches!(
+        attempt.status(),
+        StatusCode::TEMPORARY_REDIRECT | StatusCode::PERMANENT_REDIRECT
+    ) {
+        return attempt.stop();
+    }
+    // `previous` holds the original URL along with every redirect followed so far
+    if attempt.previous().len() > max_redirects {
+        let error = format!("more than {} redirects", max_redirects);
+        return attempt.error(error);
+    }
+    attempt.follow()
+}
+
+/// How often a request that failed to reach its peer is sent again, waiting `base_delay`
+/// before the first retry and twice as long before each one after it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub base_delay: Duration,
+}
+
+impl RetryPolicy {
+    /// Sends every request once.
+    pub const NONE: Self = Self {
+        max_retries: 0,
+        base_delay: Duration::ZERO,
+    };
+
+    fn delay(&self, retry: u32) -> Duration {
+        self.base_delay.saturating_mul(2u32.saturating_pow(retry))
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::NONE
+    }
+}
+
+/// Sends `request`, retrying as `policy` allows while it fails to connect to the peer. Only
+/// then is the request certain not to have reached it: after a timeout or an error mid-request
+/// the peer may have acted on it, and the nonce requests this is used for aren't idempotent.
+/// Any response is returned as is, so a 4xx or 5xx answer is never retried either. Requests
+/// with a streaming body can't be repeated and are sent once.
+pub async fn send_with_retry(
+    request: RequestBuilder,
+    policy: RetryPolicy,
+) -> reqwest::Result<Response> {
+    let mut retry = 0;
+    loop {
+        let attempt = match request.try_clone() {
+            Some(attempt) if retry < policy.max_retries => attempt,
+            _ => return request.send().await,
+        };
+        match attempt.send().await {
+            Err(e) if e.is_connect() => {
+                let delay = policy.delay(retry);
+                retry += 1;
+                eprintln!(
+                    "🔁 Request failed ({}), retrying in {} ms ({}/{})",
+                    e,
+                    delay.as_millis(),
+                    retry,
+                    policy.max_retries
+                );
+                tokio::time::sleep(delay).await;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Whether the request failed on its way to or from the peer, rather than the peer answering.
+pub fn is_connection_error(error: &reqwest::Error) -> bool {
+    error.is_connect() || error.is_timeout() || error.is_request() || error.is_body()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{Ipv4Addr, TcpListener as StdListener};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Instant;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    fn free_port() -> u16 {
+        StdListener::bind((Ipv4Addr::LOCALHOST, 0))
+            .and_then(|listener| listener.local_addr())
+            .unwrap()
+            .port()
+    }
+
+    /// Serves on `listener`, counting connections. Each one reads a request and is answered
+    /// with `200 OK` if `answer` is set, or closed without a response otherwise.
+    fn serve(listener: TcpListener, answer: bool) -> Arc<AtomicUsize> {
+        let connections = Arc::new(AtomicUsize::new(0));
+        let counted = connections.clone();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                counted.fetch_add(1, Ordering::SeqCst);
+                let mut request = [0; 4096];
+                let _ = stream.read(&mut request).await;
+                if answer {
+                    let _ = stream
+                        .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\nok")
+                        .await;
+                }
+            }
+        });
+        connections
+    }
+
+    fn nonce_request(port: u16) -> RequestBuilder {
+        Client::new()
+            .post(format!("http://127.0.0.1:{}/nonce", port))
+            .body("{}")
+    }
+
+    #[test]
+    fn retry_delays_double() {
+        let policy = RetryPolicy {
+            max_retries: 3,
+            base_delay: Duration::from_millis(100),
+        };
+        let delays: Vec<u128> = (0..3)
+            .map(|retry| policy.delay(retry).as_millis())
+            .collect();
+        assert_eq!(delays, [100, 200, 400]);
+    }
+
+    #[tokio::test]
+    async fn refused_connections_are_retried_until_the_peer_is_up() {
+        let port = free_port();
+        let policy = RetryPolicy {
+            max_retries: 3,
+            base_delay: Duration::from_millis(300),
+        };
+        let started = Instant::now();
+        // Attempts go out at roughly 0, 300 and 900 ms; the peer comes up in between the
+        // second and the third, so exactly two of them are refused
+        let sent = tokio::spawn(send_with_retry(nonce_request(port), policy));
+        tokio::time::sleep(Duration::from_millis(600)).await;
+        let connections = serve(
+            TcpListener::bind((Ipv4Addr::LOCALHOST, port))
+                .await
+                .unwrap(),
+            true,
+        );
+
+        let response = sent.await.unwrap().expect("The third attempt succeeds");
+        assert_eq!(response.text().await.unwrap(), "ok");
+        assert_eq!(connections.load(Ordering::SeqCst), 1);
+        assert!(started.elapsed() >= Duration::from_millis(900));
+    }
+
+    #[tokio::test]
+    async fn requests_that_reached_the_peer_are_not_resent() {
+        let policy = RetryPolicy {
+            max_retries: 3,
+            base_delay: Duration::ZERO,
+        };
+
+        // The peer drops the connection after reading the request
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let connections = serve(listener, false);
+        let error = send_with_retry(nonce_request(port), policy)
+            .await
+            .expect_err("The peer never answers");
+        assert!(!error.is_connect());
+        assert_eq!(connections.load(Ordering::SeqCst), 1);
+
+        // The peer reads the request but never answers in time
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let accepted = Arc::new(AtomicUsize::new(0));
+        let counted = accepted.clone();
+        tokio::spawn(async move {
+            let mut held = Vec::new();
+            while let Ok((stream, _)) = listener.accept().await {
+                counted.fetch_add(1, Ordering::SeqCst);
+                held.push(stream);
+            }
+        });
+        let request = nonce_request(port).timeout(Duration::from_millis(100));
+        let error = send_with_retry(request, policy)
+            .await
+            .expect_err("The peer never answers");
+        assert!(error.is_timeout());
+        assert_eq!(accepted.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,99 @@
+use crate::serde_utils::{deserialize_public_key, serialize_public_key};
+use secp256k1::PublicKey;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::Path;
+
+/// Persisted record of the aggregated key a participant set produced.
+///
+/// Reloading it lets the operator notice when the registered signers would no longer produce
+/// the key (and so the Taproot address) that was handed out before.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct KeyCommitment {
+    pub participant_set_hash: String,
+    /// Hex-encoded tweak applied to the aggregated key, if any
+    pub tweak: Option<String>,
+    #[serde(
+        serialize_with = "serialize_public_key",
+        deserialize_with = "deserialize_public_key"
+    )]
+    pub aggregated_key: PublicKey,
+}
+
+impl KeyCommitment {
+    pub fn new(pubkeys: &[PublicKey], tweak: Option<String>, aggregated_key: PublicKey) -> Self {
+        Self {
+            participant_set_hash: participant_set_hash(pubkeys),
+            tweak,
+            aggregated_key,
+        }
+    }
+
+    pub fn load(path: &Path) -> std::io::Result<Option<Self>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let contents = std::fs::read_to_string(path)?;
+        serde_json::from_str(&contents)
+            .map(Some)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        let contents = serde_json::to_string_pretty(self)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        std::fs::write(path, contents)
+    }
+}
+
+/// Hex SHA-256 over the sorted compressed pubkeys, independent of registration order.
+pub fn participant_set_hash(pubkeys: &[PublicKey]) -> String {
+    let mut sorted: Vec<[u8; 33]> = pubkeys.iter().map(|pk| pk.serialize()).collect();
+    sorted.sort();
+
+    let mut hasher = Sha256::new();
+    for pubkey in &sorted {
+        hasher.update(pubkey);
+    }
+    hex::encode(hasher.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::secp::SECP256K1;
+    use secp256k1::SecretKey;
+    use uuid::Uuid;
+
+    fn pubkey() -> PublicKey {
+        PublicKey::from_secret_key(&SECP256K1, &SecretKey::new(&mut rand::thread_rng()))
+    }
+
+    #[test]
+    fn set_hash_ignores_registration_order() {
+        let (a, b, c) = (pubkey(), pubkey(), pubkey());
+        assert_eq!(
+            participant_set_hash(&[a, b, c]),
+            participant_set_hash(&[c, a, b])
+        );
+        assert_ne!(
+            participant_set_hash(&[a, b]),
+            participant_set_hash(&[a, b, c])
+        );
+    }
+
+    #[test]
+    fn commitments_round_trip_through_their_file() {
+        let path = std::env::temp_dir().join(format!("key-commitment-{}.json", Uuid::new_v4()));
+        assert!(KeyCommitment::load(&path).unwrap().is_none());
+
+        let commitment = KeyCommitment::new(&[pubkey(), pubkey()], None, pubkey());
+        commitment.save(&path).unwrap();
+        assert_eq!(KeyCommitment::load(&path).unwrap(), Some(commitment));
+
+        std::fs::write(&path, "not a commitment").unwrap();
+        let error = KeyCommitment::load(&path).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,73 @@
+use serde::Deserialize;
+use std::net::IpAddr;
+use std::path::Path;
+
+/// Operator settings read from a TOML file given with `--config`. Every field is optional; a
+/// flag given on the command line takes precedence over the file, which takes precedence over
+/// the flag's default.
+#[derive(Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub port: Option<u16>,
+    /// Address the HTTP server binds to
+    pub bind_address: Option<IpAddr>,
+    pub signer_timeout_ms: Option<u64>,
+    pub min_signers: Option<usize>,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        Self::parse(&contents)
+    }
+
+    pub fn parse(contents: &str) -> Result<Self, String> {
+        let config: Config = toml::from_str(contents).map_err(|e| e.to_string())?;
+        // Same bound as the --min-signers flag
+        if config.min_signers.is_some_and(|count| count < 2) {
+            return Err("min_signers must be at least 2".to_string());
+        }
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn every_setting_is_optional() {
+        assert_eq!(Config::parse(""), Ok(Config::default()));
+        let config = Config::parse(
+            r#"
+            port = 3040
+            bind_address = "0.0.0.0"
+            signer_timeout_ms = 2500
+            min_signers = 3
+            "#,
+        );
+        assert_eq!(
+            config,
+            Ok(Config {
+                port: Some(3040),
+                bind_address: Some(Ipv4Addr::UNSPECIFIED.into()),
+                signer_timeout_ms: Some(2500),
+                min_signers: Some(3),
+            })
+        );
+    }
+
+    #[test]
+    fn invalid_settings_are_refused() {
+        assert_eq!(
+            Config::parse("min_signers = 1"),
+            Err("min_signers must be at least 2".to_string())
+        );
+        assert!(Config::parse("prot = 3040")
+            .unwrap_err()
+            .contains("unknown field `prot`"));
+        assert!(Config::parse("port = \"3040\"").is_err());
+        assert!(Config::load(Path::new("/nonexistent/operator.toml")).is_err());
+    }
+}
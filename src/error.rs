@@ -1,38 +1,510 @@
+use crate::reply;
+use crate::types::Participant;
 use serde::Serialize;
+use std::fmt;
 use warp;
+use warp::http::StatusCode;
+
+/// Errors raised while decoding keys and signatures from their wire representation.
+#[derive(Debug)]
+pub enum Error {
+    Hex(hex::FromHexError),
+    Secp256k1(secp256k1::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Hex(e) => write!(f, "invalid hex encoding: {}", e),
+            Error::Secp256k1(e) => write!(f, "invalid secp256k1 data: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<hex::FromHexError> for Error {
+    fn from(e: hex::FromHexError) -> Self {
+        Error::Hex(e)
+    }
+}
+
+impl From<secp256k1::Error> for Error {
+    fn from(e: secp256k1::Error) -> Self {
+        Error::Secp256k1(e)
+    }
+}
 
 #[derive(Debug)]
 pub struct CustomError(pub String);
 
 impl warp::reject::Reject for CustomError {}
 
+#[derive(Debug)]
+pub struct Unauthorized;
+
+impl warp::reject::Reject for Unauthorized {}
+
+/// Why the operator couldn't serve a request. [`handle_operator_rejection`] answers each variant
+/// with its own status code and a machine-readable `code`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OperatorError {
+    /// The request is malformed or asks for something that can't be done
+    InvalidRequest(String),
+    /// The message exceeds the configured maximum length, in bytes
+    MessageTooLarge {
+        length: usize,
+        max: usize,
+    },
+    /// `action` is disabled because the operator uses a static signer set
+    RegistrationDisabled {
+        action: &'static str,
+    },
+    AlreadyRegistered,
+    /// No registered signer matches the request
+    UnknownSigner(String),
+    UnknownSession(String),
+    /// A client-supplied session ID is already in use
+    DuplicateSession(String),
+    /// The operator was started without `--session-db`
+    SessionsNotPersisted,
+    NotEnoughSigners {
+        required: usize,
+        available: usize,
+    },
+    /// The participant set can't be aggregated into a key
+    KeyAggregation(String),
+    /// A signer sent a nonce that was already used in `session_id`
+    NonceReuse {
+        index: usize,
+        address: String,
+        session_id: String,
+    },
+    /// Two signers of one ceremony returned the same nonce
+    DuplicateNonce {
+        first: SignerRef,
+        second: SignerRef,
+    },
+    /// No nonce was collected for the signer at this index
+    MissingNonce(usize),
+    /// Signers at these indices signed a different message than the operator
+    MessageDivergence(Vec<usize>),
+    /// No partial signatures were collected for the signers at these indices
+    MissingPartials(Vec<usize>),
+    /// The partial signatures couldn't be turned into one agreed final signature
+    Finalize(String),
+    /// The `divergent` signers returned a different final signature than `reference`
+    InconsistentFinalSignatures {
+        reference: SignerRef,
+        divergent: Vec<SignerRef>,
+    },
+    /// The signers listed as `<index> at <address>` didn't respond before `phase`'s deadline
+    DeadlineExceeded {
+        phase: String,
+        pending: Vec<String>,
+    },
+    /// The ceremony was stopped before it finished, e.g. by a state import
+    Cancelled(String),
+    /// The signer registry or session database failed
+    Storage(String),
+    Internal(String),
+}
+
+impl OperatorError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            OperatorError::InvalidRequest(_) => StatusCode::BAD_REQUEST,
+            OperatorError::MessageTooLarge { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+            OperatorError::RegistrationDisabled { .. } => StatusCode::FORBIDDEN,
+            OperatorError::UnknownSigner(_) | OperatorError::UnknownSession(_) => {
+                StatusCode::NOT_FOUND
+            }
+            OperatorError::AlreadyRegistered
+            | OperatorError::DuplicateSession(_)
+            | OperatorError::SessionsNotPersisted
+            | OperatorError::NotEnoughSigners { .. }
+            | OperatorError::KeyAggregation(_)
+            | OperatorError::Cancelled(_) => StatusCode::CONFLICT,
+            // The signers didn't hold up their end of the ceremony
+            OperatorError::NonceReuse { .. }
+            | OperatorError::DuplicateNonce { .. }
+            | OperatorError::MissingNonce(_)
+            | OperatorError::MessageDivergence(_)
+            | OperatorError::MissingPartials(_)
+            | OperatorError::Finalize(_)
+            | OperatorError::InconsistentFinalSignatures { .. } => StatusCode::BAD_GATEWAY,
+            OperatorError::DeadlineExceeded { .. } => StatusCode::GATEWAY_TIMEOUT,
+            OperatorError::Storage(_) | OperatorError::Internal(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            OperatorError::InvalidRequest(_) => "invalid_request",
+            OperatorError::MessageTooLarge { .. } => "message_too_large",
+            OperatorError::RegistrationDisabled { .. } => "registration_disabled",
+            OperatorError::AlreadyRegistered => "already_registered",
+            OperatorError::UnknownSigner(_) => "unknown_signer",
+            OperatorError::UnknownSession(_) => "unknown_session",
+            OperatorError::DuplicateSession(_) => "duplicate_session",
+            OperatorError::SessionsNotPersisted => "sessions_not_persisted",
+            OperatorError::NotEnoughSigners { .. } => "not_enough_signers",
+            OperatorError::KeyAggregation(_) => "key_aggregation_failed",
+            OperatorError::NonceReuse { .. } => "nonce_reuse",
+            OperatorError::DuplicateNonce { .. } => "duplicate_nonce",
+            OperatorError::MissingNonce(_) => "missing_nonce",
+            OperatorError::MessageDivergence(_) => "message_divergence",
+            OperatorError::MissingPartials(_) => "missing_partials",
+            OperatorError::Finalize(_) => "finalize_failed",
+            OperatorError::InconsistentFinalSignatures { .. } => "inconsistent_final_signatures",
+            OperatorError::DeadlineExceeded { .. } => "deadline_exceeded",
+            OperatorError::Cancelled(_) => "cancelled",
+            OperatorError::Storage(_) => "storage_error",
+            OperatorError::Internal(_) => "internal_error",
+        }
+    }
+}
+
+impl fmt::Display for OperatorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OperatorError::InvalidRequest(message)
+            | OperatorError::UnknownSigner(message)
+            | OperatorError::KeyAggregation(message)
+            | OperatorError::Finalize(message)
+            | OperatorError::Cancelled(message)
+            | OperatorError::Storage(message)
+            | OperatorError::Internal(message) => write!(f, "{}", message),
+            OperatorError::MessageTooLarge { length, max } => write!(
+                f,
+                "Message of {} bytes exceeds the maximum of {} bytes. Messages are hashed as a whole, not chunked; sign larger payloads through /sign/upload",
+                length, max
+            ),
+            OperatorError::RegistrationDisabled { action } => write!(
+                f,
+                "{} is disabled: the operator uses a static signer set",
+                action
+            ),
+            OperatorError::AlreadyRegistered => write!(f, "public key already registered"),
+            OperatorError::UnknownSession(session_id) => {
+                write!(f, "Unknown session {}", session_id)
+            }
+            OperatorError::DuplicateSession(session_id) => {
+                write!(f, "Session {} already exists", session_id)
+            }
+            OperatorError::SessionsNotPersisted => write!(
+                f,
+                "Sessions aren't persisted: start the operator with --session-db"
+            ),
+            OperatorError::NotEnoughSigners {
+                required,
+                available,
+            } => write!(
+                f,
+                "At least {} signers are required, {} registered",
+                required, available
+            ),
+            OperatorError::NonceReuse {
+                index,
+                address,
+                session_id,
+            } => write!(
+                f,
+                "Nonce from signer {} at {} was already used in session {}; refusing to reuse it",
+                index, address, session_id
+            ),
+            OperatorError::DuplicateNonce { first, second } => write!(
+                f,
+                "Signers {} and {} returned the same nonce; their nonce generation is broken, refusing to sign",
+                first, second
+            ),
+            OperatorError::MissingNonce(index) => write!(
+                f,
+                "Cannot distribute nonces: missing nonce for index {}",
+                index
+            ),
+            OperatorError::MessageDivergence(indices) => write!(
+                f,
+                "Message divergence: signers at indices {:?} signed a different message",
+                indices
+            ),
+            OperatorError::MissingPartials(indices) => write!(
+                f,
+                "Cannot finalize: missing partials for indices {:?}",
+                indices
+            ),
+            OperatorError::InconsistentFinalSignatures {
+                reference,
+                divergent,
+            } => {
+                let divergent: Vec<String> = divergent.iter().map(|s| s.to_string()).collect();
+                write!(
+                    f,
+                    "Inconsistent final signatures: signers {} returned a different one than signer {}",
+                    divergent.join(", "),
+                    reference
+                )
+            }
+            OperatorError::DeadlineExceeded { phase, pending } => write!(
+                f,
+                "{} deadline exceeded; no response from signers {}",
+                phase,
+                pending.join(", ")
+            ),
+        }
+    }
+}
+
+impl std::error::Error for OperatorError {}
+
+/// A signer as named in errors: its registration index and its address.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignerRef {
+    pub index: usize,
+    pub address: String,
+}
+
+impl From<&Participant> for SignerRef {
+    fn from(participant: &Participant) -> Self {
+        Self {
+            index: participant.index,
+            address: participant.address.clone(),
+        }
+    }
+}
+
+impl fmt::Display for SignerRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at {}", self.index, self.address)
+    }
+}
+
+impl warp::reject::Reject for OperatorError {}
+
+/// A ceremony step failed because of the signer at `address`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignerFailure {
+    pub address: String,
+    pub message: String,
+    /// The connection to the signer failed, rather than the signer answering badly
+    pub connection_lost: bool,
+}
+
+impl warp::reject::Reject for SignerFailure {}
+
+/// Some signers failed the nonce round of a session, but enough answered to sign without them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PartialQuorum {
+    pub session_id: String,
+    /// Addresses of the signers that answered
+    pub responsive: Vec<String>,
+    /// Why each of the others was given up on
+    pub failures: Vec<String>,
+}
+
+impl warp::reject::Reject for PartialQuorum {}
+
+/// Raised by a signer that is currently declining to take part in ceremonies.
+#[derive(Debug)]
+pub struct Declined;
+
+impl warp::reject::Reject for Declined {}
+
 #[derive(Debug, Serialize)]
 pub struct ErrorResponse {
+    /// Machine-readable kind of error, e.g. `not_enough_signers`
+    pub code: String,
     pub error: String,
 }
 
+/// Recovers the operator's rejections, answering [`OperatorError`]s and signer failures with
+/// their status and code; anything else is handled by [`handle_rejection`].
+pub async fn handle_operator_rejection(
+    err: warp::Rejection,
+) -> Result<warp::reply::Response, std::convert::Infallible> {
+    let (status, code, message) = if let Some(e) = err.find::<OperatorError>() {
+        (e.status(), e.code(), e.to_string())
+    } else if let Some(e) = err.find::<SignerFailure>() {
+        let code = if e.connection_lost {
+            "signer_unreachable"
+        } else {
+            "signer_failed"
+        };
+        (StatusCode::BAD_GATEWAY, code, e.message.clone())
+    } else if let Some(e) = err.find::<PartialQuorum>() {
+        (
+            StatusCode::BAD_GATEWAY,
+            "signers_failed",
+            e.failures.join("; "),
+        )
+    } else {
+        return handle_rejection(err).await.map(warp::Reply::into_response);
+    };
+    Ok(error_reply(status, code, message))
+}
+
 pub async fn handle_rejection(
     err: warp::Rejection,
 ) -> Result<impl warp::Reply, std::convert::Infallible> {
-    let code;
-    let message;
-
-    if err.is_not_found() {
-        code = warp::http::StatusCode::NOT_FOUND;
-        message = "Not Found";
+    let (status, code, message) = if err.is_not_found() {
+        (StatusCode::NOT_FOUND, "not_found", "Not Found".to_string())
     } else if let Some(e) = err.find::<CustomError>() {
-        code = warp::http::StatusCode::BAD_REQUEST;
-        message = e.0.as_str();
+        (StatusCode::BAD_REQUEST, "invalid_request", e.0.clone())
+    } else if let Some(e) = err.find::<warp::filters::body::BodyDeserializeError>() {
+        (StatusCode::BAD_REQUEST, "invalid_request", e.to_string())
+    } else if err.find::<Unauthorized>().is_some() {
+        (
+            StatusCode::UNAUTHORIZED,
+            "unauthorized",
+            "Unauthorized".to_string(),
+        )
+    } else if err.find::<Declined>().is_some() {
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            "declined",
+            "Signer declined to participate".to_string(),
+        )
     } else {
         eprintln!("unhandled error: {:?}", err);
-        code = warp::http::StatusCode::INTERNAL_SERVER_ERROR;
-        message = "Internal Server Error";
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "internal_error",
+            "Internal Server Error".to_string(),
+        )
+    };
+
+    Ok(error_reply(status, code, message))
+}
+
+fn error_reply(status: StatusCode, code: &str, message: String) -> warp::reply::Response {
+    let body = reply::json(&ErrorResponse {
+        code: code.to_string(),
+        error: message,
+    });
+    warp::Reply::into_response(warp::reply::with_status(body, status))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_nonces_blame_the_signers() {
+        let error = OperatorError::MissingNonce(1);
+        assert_eq!(error.status(), StatusCode::BAD_GATEWAY);
+        assert_eq!(error.code(), "missing_nonce");
+        assert_eq!(
+            error.to_string(),
+            "Cannot distribute nonces: missing nonce for index 1"
+        );
     }
 
-    Ok(warp::reply::with_status(
-        warp::reply::json(&ErrorResponse {
-            error: message.to_string(),
-        }),
-        code,
-    ))
+    #[test]
+    fn every_error_has_its_own_code() {
+        let signer = |index| SignerRef {
+            index,
+            address: format!("http://127.0.0.1:{}", 8081 + index),
+        };
+        let table = [
+            (OperatorError::InvalidRequest(String::new()), 400),
+            (OperatorError::MessageTooLarge { length: 2, max: 1 }, 413),
+            (
+                OperatorError::RegistrationDisabled {
+                    action: "Registration",
+                },
+                403,
+            ),
+            (OperatorError::AlreadyRegistered, 409),
+            (OperatorError::UnknownSigner(String::new()), 404),
+            (OperatorError::UnknownSession(String::new()), 404),
+            (OperatorError::DuplicateSession(String::new()), 409),
+            (OperatorError::SessionsNotPersisted, 409),
+            (
+                OperatorError::NotEnoughSigners {
+                    required: 2,
+                    available: 0,
+                },
+                409,
+            ),
+            (OperatorError::KeyAggregation(String::new()), 409),
+            (
+                OperatorError::NonceReuse {
+                    index: 0,
+                    address: String::new(),
+                    session_id: String::new(),
+                },
+                502,
+            ),
+            (
+                OperatorError::DuplicateNonce {
+                    first: signer(0),
+                    second: signer(1),
+                },
+                502,
+            ),
+            (OperatorError::MissingNonce(0), 502),
+            (OperatorError::MessageDivergence(vec![0]), 502),
+            (OperatorError::MissingPartials(vec![0]), 502),
+            (OperatorError::Finalize(String::new()), 502),
+            (
+                OperatorError::InconsistentFinalSignatures {
+                    reference: signer(0),
+                    divergent: vec![signer(1)],
+                },
+                502,
+            ),
+            (
+                OperatorError::DeadlineExceeded {
+                    phase: String::new(),
+                    pending: Vec::new(),
+                },
+                504,
+            ),
+            (OperatorError::Cancelled(String::new()), 409),
+            (OperatorError::Storage(String::new()), 500),
+            (OperatorError::Internal(String::new()), 500),
+        ];
+        let mut codes = std::collections::HashSet::new();
+        for (error, status) in &table {
+            assert_eq!(error.status().as_u16(), *status, "{:?}", error);
+            assert!(codes.insert(error.code()), "{} is used twice", error.code());
+        }
+    }
+
+    #[tokio::test]
+    async fn rejections_are_answered_with_a_code_and_a_message() {
+        let replies = [
+            (
+                warp::reject::custom(OperatorError::NotEnoughSigners {
+                    required: 2,
+                    available: 1,
+                }),
+                409,
+                "not_enough_signers",
+                "At least 2 signers are required, 1 registered",
+            ),
+            (
+                warp::reject::custom(SignerFailure {
+                    address: "http://127.0.0.1:8081".to_string(),
+                    message: "lost".to_string(),
+                    connection_lost: true,
+                }),
+                502,
+                "signer_unreachable",
+                "lost",
+            ),
+            (warp::reject::not_found(), 404, "not_found", "Not Found"),
+        ];
+        for (rejection, status, code, message) in replies {
+            let response = handle_operator_rejection(rejection).await.unwrap();
+            assert_eq!(response.status().as_u16(), status);
+            let body = warp::hyper::body::to_bytes(response.into_body())
+                .await
+                .unwrap();
+            let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!(body, serde_json::json!({ "code": code, "error": message }));
+        }
+    }
 }
@@ -0,0 +1,191 @@
+use crate::serde_utils::{deserialize_public_key, serialize_public_key};
+use crate::types::Participant;
+use secp256k1::PublicKey;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Weight of the newest sample in the moving average of a signer's latency.
+const LATENCY_SMOOTHING: f64 = 0.3;
+
+/// How a signer has been doing in the ceremonies the operator ran with it.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct SignerHealth {
+    /// Ceremonies the signer completed
+    pub successes: u64,
+    /// Errors attributed to the signer
+    pub failures: u64,
+    /// Unix time in milliseconds of the signer's last response
+    pub last_seen_ms: Option<u64>,
+    /// Moving average of the signer's nonce round-trip time
+    pub recent_latency_ms: Option<f64>,
+}
+
+impl SignerHealth {
+    pub fn record_response(&mut self, latency: Duration) {
+        let latency_ms = latency.as_secs_f64() * 1000.0;
+        self.recent_latency_ms = Some(match self.recent_latency_ms {
+            Some(average) => average + LATENCY_SMOOTHING * (latency_ms - average),
+            None => latency_ms,
+        });
+        self.last_seen_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .ok()
+            .map(|now| now.as_millis() as u64);
+    }
+
+    pub fn record_success(&mut self) {
+        self.successes += 1;
+    }
+
+    pub fn record_failure(&mut self) {
+        self.failures += 1;
+    }
+
+    /// Share of the signer's outcomes that were failures; zero for a signer not yet used.
+    pub fn failure_rate(&self) -> f64 {
+        let total = self.successes + self.failures;
+        if total == 0 {
+            0.0
+        } else {
+            self.failures as f64 / total as f64
+        }
+    }
+}
+
+/// A registered signer with its health, as listed by `GET /signers`.
+///
+/// `index` is the signer's registration index, which stays the same until it deregisters.
+/// `position` is its place among the currently registered signers sorted by public key, i.e. in
+/// the aggregated key a `/sign` over all of them would use; a registration or deregistration can
+/// shift it.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SignerStatus {
+    pub index: usize,
+    pub position: usize,
+    #[serde(
+        serialize_with = "serialize_public_key",
+        deserialize_with = "deserialize_public_key"
+    )]
+    pub public_key_hex: PublicKey,
+    pub address: String,
+    pub health: SignerHealth,
+}
+
+/// Picks the `count` healthiest of `participants`, keyed by address in `health`: lowest failure
+/// rate first, then lowest recent latency, with key order breaking ties. Signers
+/// without a latency sample yet rank ahead of slow ones so that they get a chance to be measured.
+/// The chosen signers keep their relative order and are repositioned to match the new key order.
+pub fn select_healthiest(
+    participants: &[Participant],
+    health: &HashMap<String, SignerHealth>,
+    count: usize,
+) -> Vec<Participant> {
+    let unknown = SignerHealth::default();
+    let mut ranked: Vec<&Participant> = participants.iter().collect();
+    ranked.sort_by(|a, b| {
+        let a_health = health.get(&a.address).unwrap_or(&unknown);
+        let b_health = health.get(&b.address).unwrap_or(&unknown);
+        a_health
+            .failure_rate()
+            .total_cmp(&b_health.failure_rate())
+            .then(
+                a_health
+                    .recent_latency_ms
+                    .unwrap_or(0.0)
+                    .total_cmp(&b_health.recent_latency_ms.unwrap_or(0.0)),
+            )
+            .then(a.position.cmp(&b.position))
+    });
+    ranked.truncate(count);
+    ranked.sort_by_key(|p| p.position);
+
+    ranked
+        .into_iter()
+        .enumerate()
+        .map(|(position, p)| Participant {
+            position,
+            ..p.clone()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::secp::SECP256K1;
+    use secp256k1::SecretKey;
+
+    fn participants(count: usize) -> Vec<Participant> {
+        (0..count)
+            .map(|index| Participant {
+                index,
+                position: index,
+                public_key: PublicKey::from_secret_key(
+                    &SECP256K1,
+                    &SecretKey::new(&mut rand::thread_rng()),
+                ),
+                address: format!("http://127.0.0.1:{}", 8081 + index),
+            })
+            .collect()
+    }
+
+    fn health(successes: u64, failures: u64, latency_ms: Option<f64>) -> SignerHealth {
+        SignerHealth {
+            successes,
+            failures,
+            last_seen_ms: None,
+            recent_latency_ms: latency_ms,
+        }
+    }
+
+    #[test]
+    fn latency_is_a_moving_average() {
+        let mut health = SignerHealth::default();
+        health.record_response(Duration::from_millis(100));
+        assert_eq!(health.recent_latency_ms, Some(100.0));
+        assert!(health.last_seen_ms.is_some());
+
+        health.record_response(Duration::from_millis(200));
+        assert_eq!(health.recent_latency_ms, Some(130.0));
+    }
+
+    #[test]
+    fn failure_rates_count_failures_among_all_outcomes() {
+        assert_eq!(SignerHealth::default().failure_rate(), 0.0);
+        assert_eq!(health(3, 1, None).failure_rate(), 0.25);
+    }
+
+    #[test]
+    fn failing_and_slow_signers_are_selected_last() {
+        let participants = participants(4);
+        let health = HashMap::from([
+            (participants[0].address.clone(), health(0, 2, Some(10.0))),
+            (participants[1].address.clone(), health(2, 0, Some(50.0))),
+            (participants[2].address.clone(), health(2, 0, Some(20.0))),
+        ]);
+
+        // The unmeasured signer ranks ahead of the slower one
+        let selected = select_healthiest(&participants, &health, 2);
+        assert_eq!(
+            selected,
+            vec![
+                Participant {
+                    position: 0,
+                    ..participants[2].clone()
+                },
+                Participant {
+                    position: 1,
+                    ..participants[3].clone()
+                },
+            ]
+        );
+
+        let selected = select_healthiest(&participants, &health, 3);
+        let addresses: Vec<&str> = selected.iter().map(|p| p.address.as_str()).collect();
+        assert_eq!(
+            addresses,
+            [1, 2, 3].map(|index| participants[index].address.as_str())
+        );
+    }
+}
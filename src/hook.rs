@@ -0,0 +1,127 @@
+use crate::client::HttpClient;
+use crate::types::SigningResponse;
+use futures::future::BoxFuture;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Bound on each webhook delivery, which holds up the `/sign` response it belongs to.
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What the operator does with each completed ceremony's signature, e.g. handing it to the
+/// process that broadcasts the transaction. Runs after the signature has been verified and
+/// before the response is returned; failures are the hook's to report, as the signature
+/// still reaches the caller.
+pub trait OnSignatureComplete: Send + Sync {
+    fn handle<'a>(&'a self, response: &'a SigningResponse) -> BoxFuture<'a, ()> {
+        let _ = response;
+        Box::pin(async {})
+    }
+}
+
+/// Selects the hook built by [`signature_hook`].
+///
+/// - `none` does nothing.
+/// - `log` prints each session's aggregated key and signature.
+/// - `webhook` POSTs each response, as the `/sign` JSON, to a URL.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum SignatureHookKind {
+    #[default]
+    None,
+    Log,
+    Webhook,
+}
+
+/// Builds the hook of the given kind; `webhook_url` is required by, and only used for, a
+/// webhook.
+pub fn signature_hook(
+    kind: SignatureHookKind,
+    webhook_url: Option<String>,
+) -> Result<Arc<dyn OnSignatureComplete>, String> {
+    match (kind, webhook_url) {
+        (SignatureHookKind::Webhook, Some(url)) => {
+            Ok(Arc::new(WebhookHook::new(HttpClient::new(), url)))
+        }
+        (SignatureHookKind::Webhook, None) => Err("the webhook hook needs a URL".to_string()),
+        (_, Some(_)) => Err("a webhook URL is only used by the webhook hook".to_string()),
+        (SignatureHookKind::Log, None) => Ok(Arc::new(LogHook)),
+        (SignatureHookKind::None, None) => Ok(Arc::new(NoHook)),
+    }
+}
+
+/// Hook that does nothing.
+pub struct NoHook;
+
+impl OnSignatureComplete for NoHook {}
+
+/// Hook that logs each completed ceremony.
+pub struct LogHook;
+
+impl OnSignatureComplete for LogHook {
+    fn handle<'a>(&'a self, response: &'a SigningResponse) -> BoxFuture<'a, ()> {
+        Box::pin(async move {
+            println!(
+                "🔔 Session {} signed under {}: {}",
+                response.session_id,
+                response.aggregated_pubkey,
+                hex::encode(response.aggregated_signature.serialize())
+            );
+        })
+    }
+}
+
+/// Hook that POSTs each completed ceremony's response to a URL.
+pub struct WebhookHook {
+    client: HttpClient,
+    url: String,
+}
+
+impl WebhookHook {
+    pub fn new(client: HttpClient, url: String) -> Self {
+        Self { client, url }
+    }
+}
+
+impl OnSignatureComplete for WebhookHook {
+    fn handle<'a>(&'a self, response: &'a SigningResponse) -> BoxFuture<'a, ()> {
+        Box::pin(async move {
+            let delivered = self
+                .client
+                .inner()
+                .post(&self.url)
+                .timeout(WEBHOOK_TIMEOUT)
+                .json(response)
+                .send()
+                .await
+                .and_then(|response| response.error_for_status());
+            if let Err(e) = delivered {
+                eprintln!(
+                    "Failed to deliver the result of {} to {}: {}",
+                    response.session_id, self.url, e
+                );
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn webhook_urls_go_with_webhooks_only() {
+        let url = || Some("http://127.0.0.1:1/hook".to_string());
+        assert!(signature_hook(SignatureHookKind::Webhook, url()).is_ok());
+        assert!(signature_hook(SignatureHookKind::Log, None).is_ok());
+        assert!(signature_hook(SignatureHookKind::None, None).is_ok());
+        assert_eq!(
+            signature_hook(SignatureHookKind::Webhook, None).err(),
+            Some("the webhook hook needs a URL".to_string())
+        );
+        for kind in [SignatureHookKind::None, SignatureHookKind::Log] {
+            assert_eq!(
+                signature_hook(kind, url()).err(),
+                Some("a webhook URL is only used by the webhook hook".to_string())
+            );
+        }
+    }
+}
@@ -0,0 +1,144 @@
+use crate::serde_utils::{
+    deserialize_partial_sig_map, deserialize_public_key, serialize_partial_sig_map,
+    serialize_public_key,
+};
+use crate::types::Participant;
+use musig2::PartialSignature;
+use secp256k1::PublicKey;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// A session whose partial signatures were collected but not yet distributed to the signers.
+///
+/// The signers hold a second round for it until they receive the others' partials, so an
+/// operator that dies before distributing them must pick the session up again on restart.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PendingDistribution {
+    pub session_id: String,
+    pub participants: Vec<Participant>,
+    #[serde(
+        serialize_with = "serialize_partial_sig_map",
+        deserialize_with = "deserialize_partial_sig_map"
+    )]
+    pub partial_signatures: HashMap<usize, PartialSignature>,
+    #[serde(
+        serialize_with = "serialize_public_key",
+        deserialize_with = "deserialize_public_key"
+    )]
+    pub aggregated_pubkey: PublicKey,
+    /// Hex-encoded bytes that were signed, to verify the resumed signature against
+    pub signed_message: String,
+}
+
+/// Directory holding one `<session id>.json` file per session awaiting distribution.
+#[derive(Clone, Debug)]
+pub struct SessionJournal {
+    dir: PathBuf,
+}
+
+impl SessionJournal {
+    pub fn open(dir: &Path) -> std::io::Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+        })
+    }
+
+    fn path(&self, session_id: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", session_id))
+    }
+
+    /// Persists a session, replacing its file atomically so a crash never leaves half of it.
+    pub fn record(&self, pending: &PendingDistribution) -> std::io::Result<()> {
+        let contents = serde_json::to_string_pretty(pending)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        let path = self.path(&pending.session_id);
+        let partial = path.with_extension("json.tmp");
+        std::fs::write(&partial, contents)?;
+        std::fs::rename(&partial, &path)
+    }
+
+    pub fn remove(&self, session_id: &str) -> std::io::Result<()> {
+        match std::fs::remove_file(self.path(session_id)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    /// Sessions left over by a previous run. Unreadable files are reported and skipped.
+    pub fn pending(&self) -> std::io::Result<Vec<PendingDistribution>> {
+        let mut pending = Vec::new();
+        for entry in std::fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+            let parsed = std::fs::read_to_string(&path).and_then(|contents| {
+                serde_json::from_str(&contents)
+                    .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+            });
+            match parsed {
+                Ok(session) => pending.push(session),
+                Err(e) => eprintln!(
+                    "Skipping unreadable journal entry {}: {}",
+                    path.display(),
+                    e
+                ),
+            }
+        }
+        Ok(pending)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::secp::SECP256K1;
+    use secp256k1::SecretKey;
+    use uuid::Uuid;
+
+    fn pending(session_id: &str) -> PendingDistribution {
+        let public_key =
+            PublicKey::from_secret_key(&SECP256K1, &SecretKey::new(&mut rand::thread_rng()));
+        PendingDistribution {
+            session_id: session_id.to_string(),
+            participants: vec![Participant {
+                index: 0,
+                position: 0,
+                public_key,
+                address: "http://127.0.0.1:8081".to_string(),
+            }],
+            partial_signatures: HashMap::from([(
+                0,
+                PartialSignature::from_slice(&[1; 32]).unwrap(),
+            )]),
+            aggregated_pubkey: public_key,
+            signed_message: "00ff".to_string(),
+        }
+    }
+
+    #[test]
+    fn sessions_stay_pending_until_removed() {
+        let dir = std::env::temp_dir().join(format!("journal-{}", Uuid::new_v4()));
+        let journal = SessionJournal::open(&dir).unwrap();
+        let session = pending("interrupted");
+        journal.record(&session).unwrap();
+        // Leftovers of an interrupted write and unreadable files are skipped
+        std::fs::write(dir.join("partial.json.tmp"), "{").unwrap();
+        std::fs::write(dir.join("garbage.json"), "not json").unwrap();
+
+        let recovered = journal.pending().unwrap();
+        assert_eq!(recovered.len(), 1);
+        assert_eq!(recovered[0].session_id, session.session_id);
+        assert_eq!(recovered[0].participants, session.participants);
+        assert_eq!(recovered[0].partial_signatures, session.partial_signatures);
+        assert_eq!(recovered[0].aggregated_pubkey, session.aggregated_pubkey);
+
+        journal.remove("interrupted").unwrap();
+        // Removing a session twice is harmless
+        journal.remove("interrupted").unwrap();
+        assert!(journal.pending().unwrap().is_empty());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,310 @@
+use crate::types::{TaprootCommitment, TaprootKeys};
+use musig2::secp::{Point, Scalar};
+use musig2::KeyAggContext;
+use secp256k1::{PublicKey, XOnlyPublicKey};
+use std::str::FromStr;
+
+/// How the operator sends the key aggregation context to signers.
+///
+/// - `full` sends the serialized context, including its cached per-key coefficients.
+/// - `pubkeys` sends only the expected aggregated key; each signer rebuilds the context from
+///   the ordered (already tweaked) participant keys it receives anyway, which makes nonce
+///   requests considerably smaller at the cost of one key aggregation per signer.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum KeyAggEncoding {
+    #[default]
+    Full,
+    Pubkeys,
+}
+
+/// Rebuilds a key aggregation context from its ordered pubkeys, committed to a Taproot
+/// `commitment` if given, and checks that it aggregates to `expected`.
+pub fn rebuild_key_agg_ctx(
+    pubkeys: Vec<PublicKey>,
+    commitment: Option<&TaprootCommitment>,
+    expected: PublicKey,
+) -> Result<KeyAggContext, String> {
+    let mut key_agg_ctx = KeyAggContext::new(pubkeys)
+        .map_err(|e| format!("Failed to rebuild key aggregation context: {}", e))?;
+    if let Some(commitment) = commitment {
+        key_agg_ctx = taproot_tweak(key_agg_ctx, commitment)?.0;
+    }
+    let aggregated_pubkey: PublicKey = key_agg_ctx.aggregated_pubkey();
+    if aggregated_pubkey != expected {
+        return Err(format!(
+            "Rebuilt key aggregation context aggregates to {}, expected {}",
+            aggregated_pubkey, expected
+        ));
+    }
+    Ok(key_agg_ctx)
+}
+
+/// Checks that hex-encoded `pubkeys` would form a valid key aggregation context, in that order,
+/// returning the aggregated key or the reason they wouldn't.
+pub fn validate_keyset(pubkeys: &[String]) -> Result<PublicKey, String> {
+    // `KeyAggContext::new` panics on an empty key set
+    if pubkeys.is_empty() {
+        return Err("Key set is empty".to_string());
+    }
+    let pubkeys = pubkeys
+        .iter()
+        .enumerate()
+        .map(|(index, pubkey)| {
+            PublicKey::from_str(pubkey)
+                .map_err(|e| format!("Public key at index {} is invalid: {}", index, e))
+        })
+        .collect::<Result<Vec<PublicKey>, String>>()?;
+    let key_agg_ctx = KeyAggContext::new(pubkeys)
+        .map_err(|e| format!("Failed to create key aggregation context: {}", e))?;
+    Ok(key_agg_ctx.aggregated_pubkey())
+}
+
+/// Commits an untweaked key aggregation context to a tapscript merkle root, or to none at all
+/// (BIP341), returning the tweaked context along with the internal key, the tweak and the
+/// output key.
+pub fn taproot_tweak(
+    key_agg_ctx: KeyAggContext,
+    commitment: &TaprootCommitment,
+) -> Result<(KeyAggContext, TaprootKeys), String> {
+    let internal_key: PublicKey = key_agg_ctx.aggregated_pubkey();
+    let key_agg_ctx = match commitment {
+        TaprootCommitment::KeyPathOnly => key_agg_ctx.with_unspendable_taproot_tweak(),
+        TaprootCommitment::ScriptTree(merkle_root) => key_agg_ctx.with_taproot_tweak(merkle_root),
+    }
+    .map_err(|_| "Taproot tweak is invalid for this key".to_string())?;
+    // The context was untweaked, so the accumulated tweak is exactly the Taproot tweak
+    let tweak = key_agg_ctx
+        .tweak_sum()
+        .ok_or_else(|| "Taproot tweak is zero".to_string())?;
+    let output_key: PublicKey = key_agg_ctx.aggregated_pubkey();
+    let taproot = TaprootKeys {
+        internal_key: XOnlyPublicKey::from(internal_key),
+        tweak,
+        output_key: XOnlyPublicKey::from(output_key),
+        output_key_parity: output_key.x_only_public_key().1.to_u8(),
+    };
+    Ok((key_agg_ctx, taproot))
+}
+
+/// Returns the individual pubkeys a key aggregation context was built from, in signer order.
+pub fn context_pubkeys(key_agg_ctx: &KeyAggContext) -> Vec<PublicKey> {
+    key_agg_ctx
+        .pubkeys()
+        .iter()
+        .map(|&point| PublicKey::from(point))
+        .collect()
+}
+
+/// Checks that a key aggregation context was built from exactly `expected`, in that order.
+pub fn check_context_pubkeys(
+    key_agg_ctx: &KeyAggContext,
+    expected: &[PublicKey],
+) -> Result<(), String> {
+    let actual = context_pubkeys(key_agg_ctx);
+    if actual.len() != expected.len() {
+        return Err(format!(
+            "Key aggregation context has {} pubkeys, expected {}",
+            actual.len(),
+            expected.len()
+        ));
+    }
+    match actual.iter().zip(expected).position(|(a, e)| a != e) {
+        Some(index) => Err(format!(
+            "Key aggregation context pubkey at index {} doesn't match the participant set",
+            index
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Applies an individual tweak to a participant key, giving `pubkey + tweak·G`.
+pub fn tweak_pubkey(pubkey: PublicKey, tweak: Scalar) -> Result<PublicKey, String> {
+    (Point::from(pubkey) + tweak.base_point_mul())
+        .not_inf()
+        .map(PublicKey::from)
+        .map_err(|_| format!("Tweak cancels out public key {}", pubkey))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::secp::SECP256K1;
+    use secp256k1::{Parity, SecretKey};
+    use sha2::{Digest, Sha256};
+
+    fn pubkeys(count: usize) -> Vec<PublicKey> {
+        (0..count)
+            .map(|_| {
+                PublicKey::from_secret_key(&SECP256K1, &SecretKey::new(&mut rand::thread_rng()))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn rebuilt_contexts_must_aggregate_to_the_expected_key() {
+        let pubkeys = pubkeys(3);
+        let full = KeyAggContext::new(pubkeys.clone()).unwrap();
+        let expected: PublicKey = full.aggregated_pubkey();
+        let rebuilt = rebuild_key_agg_ctx(pubkeys.clone(), None, expected).unwrap();
+        assert_eq!(rebuilt.serialize(), full.serialize());
+
+        // Another key order aggregates to another key
+        let reordered = vec![pubkeys[1], pubkeys[0], pubkeys[2]];
+        let aggregated: PublicKey = KeyAggContext::new(reordered.clone())
+            .unwrap()
+            .aggregated_pubkey();
+        assert_eq!(
+            rebuild_key_agg_ctx(reordered, None, expected).err(),
+            Some(format!(
+                "Rebuilt key aggregation context aggregates to {}, expected {}",
+                aggregated, expected
+            ))
+        );
+    }
+
+    #[test]
+    fn valid_keysets_report_their_aggregated_key() {
+        let pubkeys = pubkeys(2);
+        let hex: Vec<String> = pubkeys
+            .iter()
+            .map(|pk| hex::encode(pk.serialize()))
+            .collect();
+        let expected: PublicKey = KeyAggContext::new(pubkeys).unwrap().aggregated_pubkey();
+        assert_eq!(validate_keyset(&hex), Ok(expected));
+
+        // BIP327 allows repeated keys
+        let repeated = vec![hex[0].clone(), hex[0].clone()];
+        assert!(validate_keyset(&repeated).is_ok());
+    }
+
+    #[test]
+    fn invalid_keysets_report_why() {
+        assert_eq!(validate_keyset(&[]), Err("Key set is empty".to_string()));
+
+        let malformed = vec![hex::encode(pubkeys(1)[0].serialize()), "02ff".to_string()];
+        assert!(validate_keyset(&malformed)
+            .unwrap_err()
+            .starts_with("Public key at index 1 is invalid: "));
+    }
+
+    #[test]
+    fn contexts_match_the_keys_they_were_built_from() {
+        let pubkeys = pubkeys(3);
+        let key_agg_ctx = KeyAggContext::new(pubkeys.clone()).unwrap();
+        assert_eq!(context_pubkeys(&key_agg_ctx), pubkeys);
+        assert_eq!(check_context_pubkeys(&key_agg_ctx, &pubkeys), Ok(()));
+    }
+
+    #[test]
+    fn mismatched_participant_sets_are_pinpointed() {
+        let pubkeys = pubkeys(3);
+        let key_agg_ctx = KeyAggContext::new(pubkeys.clone()).unwrap();
+
+        let reordered = [pubkeys[0], pubkeys[2], pubkeys[1]];
+        assert_eq!(
+            check_context_pubkeys(&key_agg_ctx, &reordered),
+            Err(
+                "Key aggregation context pubkey at index 1 doesn't match the participant set"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            check_context_pubkeys(&key_agg_ctx, &pubkeys[..2]),
+            Err("Key aggregation context has 3 pubkeys, expected 2".to_string())
+        );
+    }
+
+    #[test]
+    fn tweaked_keys_belong_to_the_tweaked_secret() {
+        let secret_key = SecretKey::new(&mut rand::thread_rng());
+        let tweak = Scalar::from_slice(&[3; 32]).unwrap();
+        let tweaked_secret = (Scalar::from(secret_key) + tweak).not_zero().unwrap();
+        assert_eq!(
+            tweak_pubkey(PublicKey::from_secret_key(&SECP256K1, &secret_key), tweak),
+            Ok(PublicKey::from_secret_key(
+                &SECP256K1,
+                &SecretKey::from(tweaked_secret)
+            ))
+        );
+    }
+
+    #[test]
+    fn tweaks_cancelling_out_a_key_are_rejected() {
+        let secret_key = SecretKey::new(&mut rand::thread_rng());
+        let pubkey = PublicKey::from_secret_key(&SECP256K1, &secret_key);
+        let negated = -Scalar::from(secret_key);
+        assert_eq!(
+            tweak_pubkey(pubkey, negated),
+            Err(format!("Tweak cancels out public key {}", pubkey))
+        );
+    }
+
+    /// The BIP341 tweak and output key for `internal_key`, computed from the spec's definition.
+    fn bip341_output(internal_key: XOnlyPublicKey, merkle_root: &[u8]) -> (Scalar, PublicKey) {
+        let tag = Sha256::digest(b"TapTweak");
+        let digest = Sha256::new()
+            .chain_update(tag)
+            .chain_update(tag)
+            .chain_update(internal_key.serialize())
+            .chain_update(merkle_root)
+            .finalize();
+        let tweak = Scalar::from_slice(&digest).unwrap();
+        let output_key = tweak_pubkey(internal_key.public_key(Parity::Even), tweak).unwrap();
+        (tweak, output_key)
+    }
+
+    #[test]
+    fn bip341_outputs_match_the_test_vectors() {
+        let vectors = [
+            (
+                "d6889cb081036e0faefa3a35157ad71086b123b2b144b649798b494c300a961d",
+                "",
+                "b86e7be8f39bab32a6f2c0443abbc210f0edac0e2c53d501b36b64437d9c6c70",
+                "53a1f6e454df1aa2776a2814a721372d6258050de330b3c6d10ee8f4e0dda343",
+            ),
+            (
+                "187791b6f712a8ea41c8ecdd0ee77fab3e85263b37e1ec18a3651926b3a6cf27",
+                "5b75adecf53548f3ec6ad7d78383bf84cc57b55a3127c72b9a2481752dd88b21",
+                "cbd8679ba636c1110ea247542cfbd964131a6be84f873f7f3b62a777528ed001",
+                "147c9c57132f6e7ecddba9800bb0c4449251c92a1e60371ee77557b6620f3ea3",
+            ),
+        ];
+        for (internal_key, merkle_root, tweak, output_key) in vectors {
+            let internal_key = XOnlyPublicKey::from_str(internal_key).unwrap();
+            let (actual_tweak, actual_output) =
+                bip341_output(internal_key, &hex::decode(merkle_root).unwrap());
+            assert_eq!(hex::encode(actual_tweak.serialize()), tweak);
+            assert_eq!(
+                hex::encode(actual_output.x_only_public_key().0.serialize()),
+                output_key
+            );
+        }
+    }
+
+    #[test]
+    fn taproot_tweaks_follow_bip341() {
+        let commitments = [
+            (TaprootCommitment::KeyPathOnly, Vec::new()),
+            (TaprootCommitment::ScriptTree([7; 32]), vec![7; 32]),
+        ];
+        for (commitment, merkle_root) in commitments {
+            let pubkeys = pubkeys(2);
+            let untweaked = KeyAggContext::new(pubkeys.clone()).unwrap();
+            let internal_key: PublicKey = untweaked.aggregated_pubkey();
+            let (tweaked, taproot) = taproot_tweak(untweaked, &commitment).unwrap();
+
+            let (tweak, output_key) =
+                bip341_output(XOnlyPublicKey::from(internal_key), &merkle_root);
+            let (output_xonly, parity) = output_key.x_only_public_key();
+            assert_eq!(taproot.internal_key, XOnlyPublicKey::from(internal_key));
+            assert_eq!(taproot.tweak, tweak);
+            assert_eq!(taproot.output_key, output_xonly);
+            assert_eq!(taproot.output_key_parity, parity.to_u8());
+            assert_eq!(tweaked.aggregated_pubkey::<PublicKey>(), output_key);
+
+            // Signers rebuilding the context arrive at the same output key
+            let rebuilt = rebuild_key_agg_ctx(pubkeys, Some(&commitment), output_key).unwrap();
+            assert_eq!(rebuilt.serialize(), tweaked.serialize());
+        }
+    }
+}
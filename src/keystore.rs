@@ -0,0 +1,186 @@
+use crate::error::Error as DecodeError;
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use secp256k1::SecretKey;
+use serde::{Deserialize, Serialize};
+use std::io::{Error, ErrorKind, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+
+/// scrypt cost of newly written keystores (N = 2^15, r = 8, p = 1), as recommended for
+/// interactive logins. The parameters are stored, so they can be raised without breaking
+/// existing files.
+const SCRYPT_LOG_N: u8 = 15;
+const SCRYPT_R: u32 = 8;
+const SCRYPT_P: u32 = 1;
+
+/// Environment variable a signer's hex-encoded secret key can be passed in, e.g. from a
+/// container orchestrator's secret store.
+pub const SECRET_KEY_ENV: &str = "SIGNER_SECRET_KEY";
+
+/// A signer's secret key encrypted with AES-256-GCM under a key derived from a password with
+/// scrypt, as stored in the keystore file. Binary fields are hex-encoded.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct EncryptedKey {
+    scrypt_log_n: u8,
+    scrypt_r: u32,
+    scrypt_p: u32,
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+/// Decrypts the secret key stored at `path`. Fails with `InvalidData` when the password is
+/// wrong or the file has been tampered with.
+pub fn load(path: &Path, password: &str) -> std::io::Result<SecretKey> {
+    let contents = std::fs::read_to_string(path)?;
+    let stored: EncryptedKey = serde_json::from_str(&contents).map_err(invalid_data)?;
+    let salt = hex::decode(&stored.salt).map_err(invalid_data)?;
+    let nonce = hex::decode(&stored.nonce).map_err(invalid_data)?;
+    let ciphertext = hex::decode(&stored.ciphertext).map_err(invalid_data)?;
+    if nonce.len() != 12 {
+        return Err(invalid_data("nonce must be 12 bytes"));
+    }
+
+    let cipher = cipher(
+        password,
+        &salt,
+        stored.scrypt_log_n,
+        stored.scrypt_r,
+        stored.scrypt_p,
+    )?;
+    let plaintext = cipher
+        .decrypt(Nonce::from_slice(&nonce), ciphertext.as_slice())
+        .map_err(|_| invalid_data("wrong password or corrupted keystore"))?;
+    SecretKey::from_slice(&plaintext).map_err(invalid_data)
+}
+
+/// Encrypts `secret_key` under `password` into a new keystore file at `path`, readable only by
+/// its owner. Never overwrites an existing file.
+pub fn create(path: &Path, password: &str, secret_key: &SecretKey) -> std::io::Result<()> {
+    let salt: [u8; 32] = rand::random();
+    let nonce: [u8; 12] = rand::random();
+    let cipher = cipher(password, &salt, SCRYPT_LOG_N, SCRYPT_R, SCRYPT_P)?;
+    let ciphertext = cipher
+        .encrypt(
+            Nonce::from_slice(&nonce),
+            secret_key.secret_bytes().as_slice(),
+        )
+        .map_err(|_| Error::other("failed to encrypt the secret key"))?;
+
+    let stored = EncryptedKey {
+        scrypt_log_n: SCRYPT_LOG_N,
+        scrypt_r: SCRYPT_R,
+        scrypt_p: SCRYPT_P,
+        salt: hex::encode(salt),
+        nonce: hex::encode(nonce),
+        ciphertext: hex::encode(ciphertext),
+    };
+    let contents = serde_json::to_string_pretty(&stored).map_err(invalid_data)?;
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)?;
+    file.write_all(contents.as_bytes())?;
+    file.sync_all()
+}
+
+/// Loads the secret key from `path`, or generates one and stores it there when the file doesn't
+/// exist yet. Also returns whether the key was generated.
+pub fn load_or_create(path: &Path, password: &str) -> std::io::Result<(SecretKey, bool)> {
+    if path.exists() {
+        return load(path, password).map(|secret_key| (secret_key, false));
+    }
+    let secret_key = SecretKey::new(&mut rand::thread_rng());
+    create(path, password, &secret_key)?;
+    Ok((secret_key, true))
+}
+
+/// Reads the secret key from [`SECRET_KEY_ENV`], or `None` when it isn't set.
+pub fn secret_key_from_env() -> Result<Option<SecretKey>, DecodeError> {
+    let Some(encoded) = std::env::var_os(SECRET_KEY_ENV) else {
+        return Ok(None);
+    };
+    let encoded = encoded.to_string_lossy();
+    Ok(Some(SecretKey::from_slice(&hex::decode(encoded.trim())?)?))
+}
+
+fn cipher(password: &str, salt: &[u8], log_n: u8, r: u32, p: u32) -> std::io::Result<Aes256Gcm> {
+    let params = scrypt::Params::new(log_n, r, p, 32).map_err(|e| invalid_data(e.to_string()))?;
+    let mut key = [0u8; 32];
+    scrypt::scrypt(password.as_bytes(), salt, &params, &mut key)
+        .map_err(|e| invalid_data(e.to_string()))?;
+    Ok(Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key)))
+}
+
+fn invalid_data<E: Into<Box<dyn std::error::Error + Send + Sync>>>(error: E) -> Error {
+    Error::new(ErrorKind::InvalidData, error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::PathBuf;
+    use uuid::Uuid;
+
+    fn keystore() -> PathBuf {
+        std::env::temp_dir().join(format!("keystore-{}.json", Uuid::new_v4()))
+    }
+
+    #[test]
+    fn keys_only_decrypt_with_their_password() {
+        let path = keystore();
+        let secret_key = SecretKey::new(&mut rand::thread_rng());
+        create(&path, "correct horse", &secret_key).unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        assert_eq!(load(&path, "correct horse").unwrap(), secret_key);
+        let error = load(&path, "battery staple").unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        assert_eq!(error.to_string(), "wrong password or corrupted keystore");
+
+        // An existing keystore is never replaced
+        let other = SecretKey::new(&mut rand::thread_rng());
+        let error = create(&path, "correct horse", &other).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::AlreadyExists);
+        assert_eq!(load(&path, "correct horse").unwrap(), secret_key);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn tampered_keystores_are_rejected() {
+        let path = keystore();
+        create(&path, "password", &SecretKey::new(&mut rand::thread_rng())).unwrap();
+        let mut stored: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        let mut ciphertext = hex::decode(stored["ciphertext"].as_str().unwrap()).unwrap();
+        ciphertext[0] ^= 1;
+        stored["ciphertext"] = hex::encode(ciphertext).into();
+        std::fs::write(&path, stored.to_string()).unwrap();
+
+        let error = load(&path, "password").unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        assert_eq!(error.to_string(), "wrong password or corrupted keystore");
+
+        stored["nonce"] = "00".into();
+        std::fs::write(&path, stored.to_string()).unwrap();
+        let error = load(&path, "password").unwrap_err();
+        assert_eq!(error.to_string(), "nonce must be 12 bytes");
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn generated_keys_are_loaded_on_the_next_start() {
+        let path = keystore();
+        let (generated, created) = load_or_create(&path, "password").unwrap();
+        assert!(created);
+        let (loaded, created) = load_or_create(&path, "password").unwrap();
+        assert!(!created);
+        assert_eq!(loaded, generated);
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -1,4 +1,30 @@
+pub mod admin;
+pub mod auth;
+pub mod backend;
+pub mod bench;
 pub mod client;
+pub mod commitment;
+pub mod config;
 pub mod error;
+pub mod health;
+pub mod hook;
+pub mod journal;
+pub mod key_agg;
+pub mod keystore;
+pub mod metrics;
+pub mod nonce_log;
+pub mod operator;
+pub mod orchestrator;
+pub mod registry;
+pub mod reply;
+pub mod rng;
+pub mod runtime;
+pub mod schema;
+pub mod secp;
 pub mod serde_utils;
+pub mod session;
+pub mod signer;
+pub mod status;
+pub mod store;
 pub mod types;
+pub mod verify;
@@ -0,0 +1,111 @@
+use crate::types::PhaseTimings;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Running totals over all ceremonies handled by the operator.
+#[derive(Debug, Default)]
+pub struct CeremonyMetrics {
+    successes: u64,
+    failures: u64,
+    phase_totals: PhaseTimings,
+    signer_errors: BTreeMap<String, u64>,
+}
+
+/// Snapshot of [`CeremonyMetrics`] written out for offline analysis.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct MetricsSummary {
+    pub total_ceremonies: u64,
+    pub successes: u64,
+    pub failures: u64,
+    /// Averaged over successful ceremonies, as failed ones don't complete every phase
+    pub average_phase_latencies: PhaseTimings,
+    /// Error counts keyed by signer address
+    pub signer_errors: BTreeMap<String, u64>,
+}
+
+impl CeremonyMetrics {
+    pub fn record_success(&mut self, timings: &PhaseTimings) {
+        self.successes += 1;
+        self.phase_totals.nonce_collection_ms += timings.nonce_collection_ms;
+        self.phase_totals.partial_collection_ms += timings.partial_collection_ms;
+        self.phase_totals.distribution_ms += timings.distribution_ms;
+        self.phase_totals.verification_ms += timings.verification_ms;
+    }
+
+    pub fn record_failure(&mut self) {
+        self.failures += 1;
+    }
+
+    pub fn record_signer_error(&mut self, address: &str) {
+        *self.signer_errors.entry(address.to_string()).or_default() += 1;
+    }
+
+    pub fn summary(&self) -> MetricsSummary {
+        let average = |total: f64| {
+            if self.successes == 0 {
+                0.0
+            } else {
+                total / self.successes as f64
+            }
+        };
+
+        MetricsSummary {
+            total_ceremonies: self.successes + self.failures,
+            successes: self.successes,
+            failures: self.failures,
+            average_phase_latencies: PhaseTimings {
+                nonce_collection_ms: average(self.phase_totals.nonce_collection_ms),
+                partial_collection_ms: average(self.phase_totals.partial_collection_ms),
+                distribution_ms: average(self.phase_totals.distribution_ms),
+                verification_ms: average(self.phase_totals.verification_ms),
+            },
+            signer_errors: self.signer_errors.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timings(ms: f64) -> PhaseTimings {
+        PhaseTimings {
+            nonce_collection_ms: ms,
+            partial_collection_ms: 2.0 * ms,
+            distribution_ms: 3.0 * ms,
+            verification_ms: 4.0 * ms,
+        }
+    }
+
+    #[test]
+    fn latencies_are_averaged_over_successes() {
+        let mut metrics = CeremonyMetrics::default();
+        metrics.record_success(&timings(10.0));
+        metrics.record_success(&timings(20.0));
+        metrics.record_failure();
+
+        let summary = metrics.summary();
+        assert_eq!(summary.total_ceremonies, 3);
+        assert_eq!(summary.successes, 2);
+        assert_eq!(summary.failures, 1);
+        assert_eq!(summary.average_phase_latencies.nonce_collection_ms, 15.0);
+        assert_eq!(summary.average_phase_latencies.partial_collection_ms, 30.0);
+        assert_eq!(summary.average_phase_latencies.distribution_ms, 45.0);
+        assert_eq!(summary.average_phase_latencies.verification_ms, 60.0);
+    }
+
+    #[test]
+    fn failures_alone_average_to_zero() {
+        let mut metrics = CeremonyMetrics::default();
+        metrics.record_failure();
+        metrics.record_signer_error("http://127.0.0.1:8081");
+        metrics.record_signer_error("http://127.0.0.1:8081");
+
+        let summary = metrics.summary();
+        assert_eq!(summary.average_phase_latencies.nonce_collection_ms, 0.0);
+        assert_eq!(
+            summary.signer_errors,
+            BTreeMap::from([("http://127.0.0.1:8081".to_string(), 2)])
+        );
+    }
+}
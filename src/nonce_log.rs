@@ -0,0 +1,97 @@
+use std::collections::{HashMap, VecDeque};
+
+/// Number of distributed nonces the operator remembers by default.
+///
+/// Each entry holds a 66-byte public nonce and its session ID, so the log stays around 10 MB.
+pub const DEFAULT_NONCE_LOG_CAPACITY: usize = 100_000;
+
+/// Public nonces the operator has sent out to be signed with, each with the session it
+/// belonged to.
+///
+/// The log is bounded: once it holds `capacity` nonces, recording another one forgets the
+/// oldest. Replay protection therefore only covers the last `capacity` nonces distributed,
+/// i.e. roughly the last `capacity / n` ceremonies of `n` signers; a nonce replayed from
+/// further back is not caught here. Signers never reuse a secret nonce on their own, since each
+/// first round consumes its secret nonce, so the log guards against replays by a buggy or
+/// compromised path between them and the operator, which would typically be recent.
+#[derive(Debug)]
+pub struct NonceLog {
+    capacity: usize,
+    sessions: HashMap<Vec<u8>, String>,
+    order: VecDeque<Vec<u8>>,
+}
+
+impl NonceLog {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            sessions: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    /// Session in which `nonce` was distributed, if it is still remembered.
+    pub fn session_of(&self, nonce: &[u8]) -> Option<&str> {
+        self.sessions.get(nonce).map(String::as_str)
+    }
+
+    /// Remembers `nonce` as distributed in `session_id`, forgetting the oldest nonce when full.
+    pub fn record(&mut self, nonce: Vec<u8>, session_id: &str) {
+        if self.capacity == 0 || self.sessions.contains_key(&nonce) {
+            return;
+        }
+        if self.order.len() == self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.sessions.remove(&oldest);
+            }
+        }
+        self.order.push_back(nonce.clone());
+        self.sessions.insert(nonce, session_id.to_string());
+    }
+
+    pub fn len(&self) -> usize {
+        self.order.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.order.is_empty()
+    }
+}
+
+impl Default for NonceLog {
+    fn default() -> Self {
+        Self::new(DEFAULT_NONCE_LOG_CAPACITY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nonces_are_remembered_with_their_session() {
+        let mut log = NonceLog::new(4);
+        log.record(vec![1], "session-a");
+        log.record(vec![2], "session-b");
+        assert_eq!(log.session_of(&[1]), Some("session-a"));
+        assert_eq!(log.session_of(&[2]), Some("session-b"));
+        assert_eq!(log.session_of(&[3]), None);
+
+        // A nonce keeps the session it was first distributed in
+        log.record(vec![1], "session-c");
+        assert_eq!(log.session_of(&[1]), Some("session-a"));
+        assert_eq!(log.len(), 2);
+    }
+
+    #[test]
+    fn the_oldest_nonces_are_forgotten_first() {
+        let mut log = NonceLog::new(2);
+        for nonce in 1..=3 {
+            log.record(vec![nonce], "session");
+        }
+        assert_eq!(log.len(), 2);
+        assert_eq!(log.session_of(&[1]), None);
+        assert_eq!(log.session_of(&[2]), Some("session"));
+        assert_eq!(log.session_of(&[3]), Some("session"));
+    }
+}
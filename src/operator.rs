@@ -0,0 +1,1347 @@
+use crate::admin::admin_auth;
+use crate::auth::{sign_body, OPERATOR_SIGNATURE_HEADER};
+use crate::client::{send_with_retry, HttpClient, RetryPolicy};
+use crate::commitment::KeyCommitment;
+use crate::error::{handle_operator_rejection, OperatorError};
+use crate::health::{SignerHealth, SignerStatus};
+use crate::hook::{NoHook, OnSignatureComplete};
+use crate::journal::{PendingDistribution, SessionJournal};
+use crate::key_agg::{validate_keyset, KeyAggEncoding};
+use crate::metrics::CeremonyMetrics;
+use crate::nonce_log::NonceLog;
+use crate::orchestrator::{divergent_final_signatures, repositioned};
+use crate::registry::{MemorySignerRegistry, SignerRegistry};
+use crate::reply;
+use crate::secp::verify_schnorr;
+use crate::status::SessionTracker;
+use crate::store::{SessionStore, StoredPhase};
+use crate::types::{
+    ActivateResponse, AggregatedPubkeyResponse, FinalSignatureRequest, MessageEncoding,
+    MessageStatus, OperatorState, Participant, SessionMessage, SignBatchRequest, SignBatchResponse,
+    SignUploadResponse, SignatureEncoding, SignerDeregistrationRequest, SignerRegistrationRequest,
+    SigningDomain, SigningRequest, SigningResponse, ValidateKeysetRequest, ValidateKeysetResponse,
+};
+use crate::verify::VerifyPolicy;
+use futures::future::try_join_all;
+use futures::{Stream, StreamExt};
+use musig2::{CompactSignature, KeyAggContext};
+use secp256k1::PublicKey;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::future::Future;
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::{watch, Mutex};
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+use warp::{Buf, Filter};
+
+/// Where the final signature is produced once all partial signatures are collected.
+///
+/// - `signers` sends every signer the others' partials; each one finalizes and the operator
+///   checks that all final signatures agree. This costs an extra round trip per signer, but
+///   every signer sees and vouches for the final signature.
+/// - `operator` aggregates the verified partials itself and only sends signers the final
+///   signature, without waiting for them to vouch for it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum FinalizeAt {
+    Operator,
+    #[default]
+    Signers,
+}
+
+/// Port the operator listens on unless configured otherwise.
+pub const DEFAULT_PORT: u16 = 3030;
+
+/// Default for [`OperatorConfig::max_message_len`]: 1 MiB.
+pub const DEFAULT_MAX_MESSAGE_LEN: usize = 1024 * 1024;
+
+/// Settings the operator is started with.
+pub struct OperatorConfig {
+    pub port: u16,
+    /// Address the HTTP server binds to
+    pub bind_address: IpAddr,
+    pub deterministic_session_ids: bool,
+    pub max_batch_size: usize,
+    /// Largest message `/sign` and `/sign-batch` accept, in bytes after decoding
+    pub max_message_len: usize,
+    pub key_commitment_path: Option<PathBuf>,
+    pub verify_policy: VerifyPolicy,
+    /// Fixed participant set used instead of dynamic registration
+    pub static_signers: Option<Vec<SignerRegistrationRequest>>,
+    /// Drop a misbehaving signer and restart the ceremony with the rest
+    pub exclude_faulty_signers: bool,
+    /// Continue without signers that fail the nonce round, as long as this many answered
+    pub min_signers: Option<usize>,
+    /// Wall-clock budgets for the ceremony phases; unbounded when unset
+    pub nonce_deadline: Option<Duration>,
+    pub partial_deadline: Option<Duration>,
+    pub distribution_deadline: Option<Duration>,
+    /// Reject signer responses that don't match this protocol version's schema
+    pub strict_signer_responses: bool,
+    /// Token required on admin endpoints; admin endpoints are disabled when unset
+    pub admin_token: Option<String>,
+    pub finalize_at: FinalizeAt,
+    /// Times a ceremony is rerun after losing the connection to a signer
+    pub connection_retries: u32,
+    /// Retries of a single request that failed to connect to a signer, before the ceremony
+    /// treats the connection as lost
+    pub request_retries: RetryPolicy,
+    /// How the key aggregation context is sent with nonce requests
+    pub key_agg_encoding: KeyAggEncoding,
+    /// Bound on each request to a signer; unbounded when unset
+    pub signer_timeout: Option<Duration>,
+    /// Key shared with the signers to sign every request to them with; unsigned when unset
+    pub operator_auth_key: Option<String>,
+    /// Where sessions awaiting distribution are persisted, to recover them after a restart
+    pub session_journal: Option<SessionJournal>,
+    /// Database every session's progress is persisted to, listing interrupted ones after a restart
+    pub session_store: Option<SessionStore>,
+    /// Unix socket every completed ceremony's response is written to, for co-located consumers
+    pub result_socket: Option<PathBuf>,
+    /// Run on every completed ceremony's response
+    pub signature_hook: Arc<dyn OnSignatureComplete>,
+}
+
+/// An operator on localhost at [`DEFAULT_PORT`] with every optional feature off.
+impl Default for OperatorConfig {
+    fn default() -> Self {
+        Self {
+            port: DEFAULT_PORT,
+            bind_address: Ipv4Addr::LOCALHOST.into(),
+            deterministic_session_ids: false,
+            max_batch_size: 16,
+            max_message_len: DEFAULT_MAX_MESSAGE_LEN,
+            key_commitment_path: None,
+            verify_policy: VerifyPolicy::default(),
+            static_signers: None,
+            exclude_faulty_signers: false,
+            min_signers: None,
+            nonce_deadline: None,
+            partial_deadline: None,
+            distribution_deadline: None,
+            strict_signer_responses: false,
+            admin_token: None,
+            finalize_at: FinalizeAt::default(),
+            connection_retries: 0,
+            request_retries: RetryPolicy::NONE,
+            key_agg_encoding: KeyAggEncoding::default(),
+            signer_timeout: None,
+            operator_auth_key: None,
+            session_journal: None,
+            session_store: None,
+            result_socket: None,
+            signature_hook: Arc::new(NoHook),
+        }
+    }
+}
+
+/// Operator node coordinating signing ceremonies between the registered signers. The ceremonies
+/// themselves are run in [`crate::orchestrator`], which shares the fields they need.
+#[derive(Clone)]
+pub struct Operator {
+    pub(crate) client: HttpClient,
+    port: u16,
+    bind_address: IpAddr,
+    pub(crate) deterministic_session_ids: bool,
+    max_batch_size: usize,
+    max_message_len: usize,
+    key_commitment_path: Option<PathBuf>,
+    key_commitment: Arc<Mutex<Option<KeyCommitment>>>,
+    pub(crate) verify_policy: VerifyPolicy,
+    registration_enabled: bool,
+    pub(crate) exclude_faulty_signers: bool,
+    pub(crate) min_signers: Option<usize>,
+    pub(crate) nonce_deadline: Option<Duration>,
+    pub(crate) partial_deadline: Option<Duration>,
+    pub(crate) distribution_deadline: Option<Duration>,
+    pub(crate) strict_signer_responses: bool,
+    admin_token: Option<String>,
+    pub(crate) finalize_at: FinalizeAt,
+    pub(crate) connection_retries: u32,
+    request_retries: RetryPolicy,
+    pub(crate) key_agg_encoding: KeyAggEncoding,
+    pub(crate) signer_timeout: Option<Duration>,
+    operator_auth_key: Option<String>,
+    pub(crate) session_journal: Option<SessionJournal>,
+    pub(crate) session_store: Option<Arc<SessionStore>>,
+    result_socket: Option<PathBuf>,
+    pub(crate) signature_hook: Arc<dyn OnSignatureComplete>,
+    // Only touched synchronously, so a std mutex can be used from error-mapping closures
+    pub(crate) metrics: Arc<std::sync::Mutex<CeremonyMetrics>>,
+    // Recent nonces sent out to be signed with, mapped to their session; see `NonceLog` for
+    // how far back a reappearing nonce is caught
+    pub(crate) used_nonces: Arc<std::sync::Mutex<NonceLog>>,
+    // Keyed by signer address, like the metrics' error counts
+    pub(crate) health: Arc<std::sync::Mutex<HashMap<String, SignerHealth>>>,
+    // Shared with other operators when kept in the session database
+    signers: Arc<dyn SignerRegistry>,
+    // Warm standbys by the key of the signer they can take over for
+    standbys: Arc<Mutex<HashMap<PublicKey, String>>>,
+    // Participants of each in-flight ceremony by session, snapshotted when it started
+    pub(crate) ceremonies: Arc<Mutex<HashMap<String, Vec<Participant>>>>,
+    // Bumped by every state import, which stops the ceremonies running at the time
+    pub(crate) state_imports: Arc<watch::Sender<u64>>,
+    // Messages covered by each session, with their signing status
+    pub(crate) session_messages: Arc<Mutex<HashMap<String, Vec<SessionMessage>>>>,
+    pub(crate) session_tracker: SessionTracker,
+}
+
+impl Operator {
+    pub fn new(
+        client: HttpClient,
+        config: OperatorConfig,
+        key_commitment: Option<KeyCommitment>,
+    ) -> Self {
+        let registration_enabled = config.static_signers.is_none();
+        let session_store = config.session_store.map(Arc::new);
+        let signers: Arc<dyn SignerRegistry> = match (config.static_signers, &session_store) {
+            (Some(static_signers), _) => {
+                Arc::new(MemorySignerRegistry::with_signers(static_signers))
+            }
+            (None, Some(store)) => store.clone(),
+            (None, None) => Arc::new(MemorySignerRegistry::default()),
+        };
+
+        Self {
+            client,
+            port: config.port,
+            bind_address: config.bind_address,
+            deterministic_session_ids: config.deterministic_session_ids,
+            max_batch_size: config.max_batch_size,
+            max_message_len: config.max_message_len,
+            key_commitment_path: config.key_commitment_path,
+            key_commitment: Arc::new(Mutex::new(key_commitment)),
+            verify_policy: config.verify_policy,
+            registration_enabled,
+            exclude_faulty_signers: config.exclude_faulty_signers,
+            min_signers: config.min_signers,
+            nonce_deadline: config.nonce_deadline,
+            partial_deadline: config.partial_deadline,
+            distribution_deadline: config.distribution_deadline,
+            strict_signer_responses: config.strict_signer_responses,
+            admin_token: config.admin_token,
+            finalize_at: config.finalize_at,
+            connection_retries: config.connection_retries,
+            request_retries: config.request_retries,
+            key_agg_encoding: config.key_agg_encoding,
+            signer_timeout: config.signer_timeout,
+            operator_auth_key: config.operator_auth_key,
+            session_journal: config.session_journal,
+            session_store,
+            result_socket: config.result_socket,
+            signature_hook: config.signature_hook,
+            metrics: Arc::new(std::sync::Mutex::new(CeremonyMetrics::default())),
+            used_nonces: Arc::new(std::sync::Mutex::new(NonceLog::default())),
+            health: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            signers,
+            standbys: Arc::new(Mutex::new(HashMap::new())),
+            ceremonies: Arc::new(Mutex::new(HashMap::new())),
+            state_imports: Arc::new(watch::Sender::new(0)),
+            session_messages: Arc::new(Mutex::new(HashMap::new())),
+            session_tracker: SessionTracker::default(),
+        }
+    }
+
+    /// Serves the operator's endpoints until `shutdown` completes, then stops accepting
+    /// connections and returns once in-flight requests have been answered. Fails if the port
+    /// can't be bound; port 0 binds an ephemeral port.
+    pub async fn start_server(
+        &self,
+        shutdown: impl Future<Output = ()> + Send + 'static,
+    ) -> Result<(), warp::Error> {
+        let state = self.clone();
+        let state_filter = warp::any().map(move || state.clone());
+
+        // Register signer endpoint
+        let register = warp::post()
+            .and(warp::path("register"))
+            .and(warp::body::json())
+            .and(state_filter.clone())
+            .and_then(|req, state: Operator| async move { state.register_signer(req).await });
+
+        // Deregister signer endpoint
+        let deregister = warp::post()
+            .and(warp::path("deregister"))
+            .and(warp::body::json())
+            .and(state_filter.clone())
+            .and_then(|req, state: Operator| async move { state.deregister_signer(req).await });
+
+        // Aggregated key of the registered signers endpoint
+        let aggregated_pubkey = warp::get()
+            .and(warp::path("aggregated-pubkey"))
+            .and(state_filter.clone())
+            .and_then(|state: Operator| async move { state.handle_aggregated_pubkey().await });
+
+        // Candidate signer set validation endpoint
+        let validate_keyset = warp::post()
+            .and(warp::path("validate-keyset"))
+            .and(warp::body::json())
+            .and_then(|req| async move { handle_validate_keyset(req).await });
+
+        // Register standby signer endpoint
+        let register_standby = warp::post()
+            .and(warp::path("register-standby"))
+            .and(warp::body::json())
+            .and(state_filter.clone())
+            .and_then(|req, state: Operator| async move { state.register_standby(req).await });
+
+        // Streamed payload signing endpoint
+        let sign_upload = warp::post()
+            .and(warp::path!("sign" / "upload"))
+            .and(warp::body::stream())
+            .and(state_filter.clone())
+            .and_then(|body, state: Operator| async move { state.sign_upload(body).await });
+
+        // Signing endpoint
+        let sign = warp::post()
+            .and(warp::path("sign"))
+            .and(warp::body::json())
+            .and(state_filter.clone())
+            .and_then(|req, state: Operator| async move { state.sign_message(req).await });
+
+        // Batch signing endpoint
+        let sign_batch = warp::post()
+            .and(warp::path("sign-batch"))
+            .and(warp::body::json())
+            .and(state_filter.clone())
+            .and_then(|req, state: Operator| async move { state.sign_batch(req).await });
+
+        // Registered signers and their health endpoint
+        let list_signers = warp::get()
+            .and(warp::path("signers"))
+            .and(state_filter.clone())
+            .and_then(|state: Operator| async move { state.handle_list_signers().await });
+
+        // Messages of a session endpoint
+        let session_messages = warp::get()
+            .and(warp::path!("session" / String / "messages"))
+            .and(state_filter.clone())
+            .and_then(|session_id, state: Operator| async move {
+                state.handle_session_messages(session_id).await
+            });
+
+        // Progress of a session endpoint
+        let session_status = warp::get()
+            .and(warp::path!("status" / String))
+            .and(state_filter.clone())
+            .and_then(|session_id, state: Operator| async move {
+                state.handle_session_status(session_id).await
+            });
+
+        // Export state endpoint (admin only)
+        let export_state = warp::post()
+            .and(warp::path!("admin" / "export"))
+            .and(admin_auth(self.admin_token.clone()))
+            .and(state_filter.clone())
+            .and_then(|state: Operator| async move { state.handle_export_state().await });
+
+        // Import state endpoint (admin only)
+        let import_state = warp::post()
+            .and(warp::path!("admin" / "import"))
+            .and(admin_auth(self.admin_token.clone()))
+            .and(warp::body::json())
+            .and(state_filter.clone())
+            .and_then(|req, state: Operator| async move { state.handle_import_state(req).await });
+
+        // Sessions persisted but never finished endpoint
+        let incomplete_sessions = warp::get()
+            .and(warp::path("sessions"))
+            .and(state_filter.clone())
+            .and_then(|state: Operator| async move { state.handle_incomplete_sessions().await });
+
+        let routes = register
+            .or(deregister)
+            .or(register_standby)
+            .or(sign_upload)
+            .or(sign)
+            .or(sign_batch)
+            .or(list_signers)
+            .or(validate_keyset)
+            .or(aggregated_pubkey)
+            .or(session_messages)
+            .or(incomplete_sessions)
+            .or(session_status)
+            .or(export_state)
+            .or(import_state)
+            .recover(handle_operator_rejection);
+
+        let (address, server) = warp::serve(routes)
+            .try_bind_with_graceful_shutdown((self.bind_address, self.port), shutdown)?;
+        println!("Operator running on port {}...", address.port());
+        server.await;
+        println!("Operator on port {} stopped.", address.port());
+        Ok(())
+    }
+
+    async fn register_signer(
+        self,
+        registration: SignerRegistrationRequest,
+    ) -> Result<impl warp::Reply, warp::Rejection> {
+        if !self.registration_enabled {
+            return Err(warp::reject::custom(OperatorError::RegistrationDisabled {
+                action: "Registration",
+            }));
+        }
+
+        let index = self
+            .signers
+            .register(registration.public_key, &registration.address)
+            .map_err(registry_error)?
+            .ok_or_else(|| warp::reject::custom(OperatorError::AlreadyRegistered))?;
+        println!(
+            "🔑 Signer node with index {} and public key {} registered successfully.",
+            index, registration.public_key
+        );
+        Ok(reply::json(&"Registered successfully with public key"))
+    }
+
+    /// Removes a signer from the signer set, along with any standby for its key. The remaining
+    /// signers keep their registration indices; the next ceremony's key is built from them alone.
+    async fn deregister_signer(
+        self,
+        deregistration: SignerDeregistrationRequest,
+    ) -> Result<impl warp::Reply, warp::Rejection> {
+        if !self.registration_enabled {
+            return Err(warp::reject::custom(OperatorError::RegistrationDisabled {
+                action: "Deregistration",
+            }));
+        }
+
+        let index = self
+            .signers
+            .deregister(deregistration.public_key)
+            .map_err(registry_error)?
+            .ok_or_else(|| {
+                warp::reject::custom(OperatorError::UnknownSigner(format!(
+                    "No registered signer with public key {}",
+                    deregistration.public_key
+                )))
+            })?;
+
+        self.standbys
+            .lock()
+            .await
+            .remove(&deregistration.public_key);
+        println!(
+            "👋 Signer node with index {} and public key {} deregistered.",
+            index, deregistration.public_key
+        );
+        Ok(reply::json(&"Deregistered successfully"))
+    }
+
+    async fn register_standby(
+        self,
+        registration: SignerRegistrationRequest,
+    ) -> Result<impl warp::Reply, warp::Rejection> {
+        let signers = self.signers.signers().map_err(registry_error)?;
+        if !signers
+            .iter()
+            .any(|p| p.public_key == registration.public_key)
+        {
+            return Err(warp::reject::custom(OperatorError::UnknownSigner(format!(
+                "No registered signer with public key {} to stand by for",
+                registration.public_key
+            ))));
+        }
+
+        self.standbys
+            .lock()
+            .await
+            .insert(registration.public_key, registration.address);
+        println!(
+            "🛟 Standby signer registered for public key {}.",
+            registration.public_key
+        );
+        Ok(reply::json(&"Registered as standby"))
+    }
+
+    /// Hands the unreachable signer holding `public_key` over to its standby, if one is
+    /// registered. The standby is activated first and must report the primary's key; only then
+    /// is the key routed to it, so just one of the two is ever in use. Returns the standby's
+    /// address on success.
+    pub(crate) async fn fail_over(&self, public_key: PublicKey, address: &str) -> Option<String> {
+        let standby = self.standbys.lock().await.get(&public_key)?.clone();
+
+        let mut activate = self.client.inner().post(format!("{}/activate", standby));
+        if let Some(key) = &self.operator_auth_key {
+            // The activation request has no body, so the signature covers the empty body
+            activate = activate.header(OPERATOR_SIGNATURE_HEADER, sign_body(key, &[]));
+        }
+        let activated = activate
+            .send()
+            .await
+            .ok()?
+            .json::<ActivateResponse>()
+            .await
+            .ok()?;
+        if activated.public_key != public_key {
+            eprintln!(
+                "Standby at {} holds key {}, not {}; not failing over",
+                standby, activated.public_key, public_key
+            );
+            return None;
+        }
+
+        if let Err(e) = self.signers.set_address(public_key, &standby) {
+            eprintln!(
+                "Failed to route signer {} to its standby: {}",
+                public_key, e
+            );
+            return None;
+        }
+        self.standbys.lock().await.remove(&public_key);
+        println!(
+            "🛟 Signer {} at {} is unreachable, failed over to standby at {}.",
+            public_key, address, standby
+        );
+        Some(standby)
+    }
+
+    async fn sign_message(
+        self,
+        request: SigningRequest,
+    ) -> Result<impl warp::Reply, warp::Rejection> {
+        self.check_message(&request.message, request.message_encoding)
+            .map_err(warp::reject::custom)?;
+        let encodings = request.encodings.clone();
+        let response = self.run_ceremony(request).await?;
+        self.record_signed_message(&response, encodings).await;
+
+        Ok(reply::json(&response))
+    }
+
+    /// Signs the SHA-256 digest of a streamed payload, so that payloads too large for a JSON
+    /// string can be signed. The payload is hashed as it arrives and never held in memory; the
+    /// signers only ever see the 32-byte digest, which is signed as raw bytes.
+    async fn sign_upload(
+        self,
+        body: impl Stream<Item = Result<impl Buf, warp::Error>>,
+    ) -> Result<impl warp::Reply, warp::Rejection> {
+        futures::pin_mut!(body);
+        let mut hasher = Sha256::new();
+        let mut payload_size = 0u64;
+        while let Some(chunk) = body.next().await {
+            let mut chunk = chunk.map_err(|e| {
+                warp::reject::custom(OperatorError::InvalidRequest(format!(
+                    "Failed to read upload: {}",
+                    e
+                )))
+            })?;
+            while chunk.has_remaining() {
+                let bytes = chunk.chunk();
+                hasher.update(bytes);
+                payload_size += bytes.len() as u64;
+                let read = bytes.len();
+                chunk.advance(read);
+            }
+        }
+        let digest = hex::encode(hasher.finalize());
+        println!(
+            "📤 Received a {}-byte upload with SHA-256 digest {}",
+            payload_size, digest
+        );
+
+        let response = self
+            .run_ceremony(SigningRequest {
+                message: digest,
+                session_id: None,
+                attempt: 0,
+                encodings: Vec::new(),
+                tweaks: HashMap::new(),
+                signer_count: None,
+                message_encoding: MessageEncoding::Hex,
+                signing_domain: SigningDomain::Raw,
+                include_nonces: false,
+                include_timings: false,
+                taproot_merkle_root: None,
+            })
+            .await?;
+        self.record_signed_message(&response, Vec::new()).await;
+
+        Ok(reply::json(&SignUploadResponse {
+            payload_size,
+            response,
+        }))
+    }
+
+    /// Rejects empty messages, which are almost always a client bug, and messages longer than
+    /// the configured maximum.
+    pub(crate) fn check_message(
+        &self,
+        message: &str,
+        encoding: MessageEncoding,
+    ) -> Result<(), OperatorError> {
+        let length = encoding
+            .decode(message)
+            .map_err(OperatorError::InvalidRequest)?
+            .len();
+        if length == 0 {
+            return Err(OperatorError::InvalidRequest(
+                "Message is empty".to_string(),
+            ));
+        }
+        if length > self.max_message_len {
+            return Err(OperatorError::MessageTooLarge {
+                length,
+                max: self.max_message_len,
+            });
+        }
+        Ok(())
+    }
+
+    async fn record_signed_message(
+        &self,
+        response: &SigningResponse,
+        encodings: Vec<SignatureEncoding>,
+    ) {
+        self.session_messages.lock().await.insert(
+            response.session_id.clone(),
+            vec![SessionMessage {
+                message: response.message.clone(),
+                encodings,
+                status: MessageStatus::Signed,
+            }],
+        );
+    }
+
+    async fn sign_batch(
+        self,
+        request: SignBatchRequest,
+    ) -> Result<impl warp::Reply, warp::Rejection> {
+        if request.messages.is_empty() {
+            return Err(warp::reject::custom(OperatorError::InvalidRequest(
+                "Batch must contain at least one message".to_string(),
+            )));
+        }
+        if request.messages.len() > self.max_batch_size {
+            return Err(warp::reject::custom(OperatorError::InvalidRequest(
+                format!(
+                    "Batch of {} messages exceeds the maximum of {}",
+                    request.messages.len(),
+                    self.max_batch_size
+                ),
+            )));
+        }
+        for message in &request.messages {
+            self.check_message(message, MessageEncoding::Utf8)
+                .map_err(warp::reject::custom)?;
+        }
+
+        // Track the batch as one session, so clients can follow each message's status
+        let session_id = Uuid::new_v4().to_string();
+        let messages = request
+            .messages
+            .iter()
+            .map(|message| SessionMessage {
+                message: message.clone(),
+                encodings: request.encodings.clone(),
+                status: MessageStatus::Pending,
+            })
+            .collect();
+        self.session_messages
+            .lock()
+            .await
+            .insert(session_id.clone(), messages);
+
+        // Each message gets its own ceremony with fresh nonces
+        let mut responses = Vec::with_capacity(request.messages.len());
+        for (position, message) in request.messages.into_iter().enumerate() {
+            let result = self
+                .run_ceremony(SigningRequest {
+                    message,
+                    session_id: None,
+                    attempt: 0,
+                    encodings: request.encodings.clone(),
+                    tweaks: HashMap::new(),
+                    signer_count: None,
+                    message_encoding: MessageEncoding::Utf8,
+                    signing_domain: SigningDomain::Raw,
+                    include_nonces: false,
+                    include_timings: false,
+                    taproot_merkle_root: None,
+                })
+                .await;
+
+            let status = match result {
+                Ok(_) => MessageStatus::Signed,
+                Err(_) => MessageStatus::Failed,
+            };
+            if let Some(messages) = self.session_messages.lock().await.get_mut(&session_id) {
+                messages[position].status = status;
+            }
+            responses.push(result?);
+        }
+
+        Ok(reply::json(&SignBatchResponse {
+            session_id,
+            responses,
+        }))
+    }
+
+    async fn handle_export_state(self) -> Result<impl warp::Reply, warp::Rejection> {
+        let state = OperatorState {
+            signers: self.participants().await.map_err(registry_error)?,
+            session_messages: self.session_messages.lock().await.clone(),
+        };
+        println!(
+            "📦 Exported state with {} signer(s) and {} session(s).",
+            state.signers.len(),
+            state.session_messages.len()
+        );
+        Ok(reply::json(&state))
+    }
+
+    async fn handle_import_state(
+        self,
+        state: OperatorState,
+    ) -> Result<impl warp::Reply, warp::Rejection> {
+        // In-flight ceremonies can't continue against the imported signer set, so they are
+        // stopped before it replaces the registered signers
+        let in_flight: Vec<(String, Vec<Participant>)> =
+            self.ceremonies.lock().await.drain().collect();
+        self.state_imports.send_modify(|imports| *imports += 1);
+        for (session_id, participants) in &in_flight {
+            println!("Cancelling in-flight session {} on import.", session_id);
+            self.cancel_on_signers(session_id, participants).await;
+        }
+
+        let mut session_messages = self.session_messages.lock().await;
+        self.signers
+            .replace(&state.signers)
+            .map_err(registry_error)?;
+        *session_messages = state.session_messages;
+        println!(
+            "📦 Imported state with {} signer(s) and {} session(s).",
+            state.signers.len(),
+            session_messages.len()
+        );
+
+        Ok(warp::reply())
+    }
+
+    async fn handle_list_signers(self) -> Result<impl warp::Reply, warp::Rejection> {
+        let participants = self.participants().await.map_err(registry_error)?;
+        let health = self.health.lock().unwrap();
+        let statuses: Vec<SignerStatus> = participants
+            .into_iter()
+            .map(|p| SignerStatus {
+                health: health.get(&p.address).cloned().unwrap_or_default(),
+                index: p.index,
+                position: p.position,
+                public_key_hex: p.public_key,
+                address: p.address,
+            })
+            .collect();
+        Ok(reply::json(&statuses))
+    }
+
+    /// Aggregated key a `/sign` without tweaks or `signer_count` would sign under right now.
+    async fn handle_aggregated_pubkey(self) -> Result<impl warp::Reply, warp::Rejection> {
+        let (aggregated_pubkey, signer_count) = self
+            .aggregated_pubkey()
+            .await
+            .map_err(warp::reject::custom)?;
+        Ok(reply::json(&AggregatedPubkeyResponse {
+            aggregated_pubkey,
+            signer_count,
+        }))
+    }
+
+    async fn handle_session_messages(
+        self,
+        session_id: String,
+    ) -> Result<impl warp::Reply, warp::Rejection> {
+        let session_messages = self.session_messages.lock().await;
+        let messages = session_messages.get(&session_id).ok_or_else(|| {
+            warp::reject::custom(OperatorError::UnknownSession(session_id.clone()))
+        })?;
+        Ok(reply::json(messages))
+    }
+
+    async fn handle_session_status(
+        self,
+        session_id: String,
+    ) -> Result<impl warp::Reply, warp::Rejection> {
+        let status = self.session_tracker.get(&session_id).ok_or_else(|| {
+            warp::reject::custom(OperatorError::UnknownSession(session_id.clone()))
+        })?;
+        Ok(reply::json(&status))
+    }
+
+    async fn handle_incomplete_sessions(self) -> Result<impl warp::Reply, warp::Rejection> {
+        let store = self
+            .session_store
+            .as_ref()
+            .ok_or_else(|| warp::reject::custom(OperatorError::SessionsNotPersisted))?;
+        let sessions = store.incomplete().map_err(|e| {
+            warp::reject::custom(OperatorError::Storage(format!(
+                "Failed to read the session database: {}",
+                e
+            )))
+        })?;
+        Ok(reply::json(&sessions))
+    }
+
+    /// Drops a session from the journal once its distribution is over, however it ended.
+    pub(crate) fn forget_pending_distribution(&self, session_id: &str) {
+        if let Some(journal) = &self.session_journal {
+            if let Err(e) = journal.remove(session_id) {
+                eprintln!(
+                    "Failed to remove session {} from the journal: {}",
+                    session_id, e
+                );
+            }
+        }
+    }
+
+    /// Finishes the sessions a previous run left in the journal. Distribution is resumed with
+    /// the persisted partial signatures, which signers that already finalized simply answer
+    /// again; if any signer can't complete it, every signer is told to drop the session
+    /// instead, so that none keeps a second round that will never finalize.
+    ///
+    /// Sessions the database still lists as incomplete afterwards are reported, and stay listed
+    /// at `GET /sessions`.
+    pub async fn recover_sessions(&self) {
+        if let Some(journal) = &self.session_journal {
+            match journal.pending() {
+                Ok(pending) => {
+                    for session in pending {
+                        self.recover_session(&session).await;
+                    }
+                }
+                Err(e) => eprintln!("Failed to read the session journal: {}", e),
+            }
+        }
+
+        if let Some(store) = &self.session_store {
+            match store.incomplete() {
+                Ok(sessions) if !sessions.is_empty() => {
+                    let ids: Vec<&str> = sessions.iter().map(|s| s.session_id.as_str()).collect();
+                    println!(
+                        "📂 {} session(s) interrupted by a previous run, listed at /sessions: {}",
+                        sessions.len(),
+                        ids.join(", ")
+                    );
+                }
+                Ok(_) => {}
+                Err(e) => eprintln!("Failed to read the session database: {}", e),
+            }
+        }
+    }
+
+    async fn recover_session(&self, session: &PendingDistribution) {
+        let phase = match self.resume_distribution(session).await {
+            Ok(signature) => {
+                println!(
+                    "♻️ Recovered session {}: distribution completed with signature {}",
+                    session.session_id,
+                    hex::encode(signature.serialize())
+                );
+                StoredPhase::Completed
+            }
+            Err(e) => {
+                eprintln!(
+                    "Could not resume session {} ({}), cancelling it on all signers",
+                    session.session_id, e
+                );
+                self.cancel_on_signers(&session.session_id, &session.participants)
+                    .await;
+                StoredPhase::Cancelled
+            }
+        };
+        self.forget_pending_distribution(&session.session_id);
+
+        if let Some(store) = &self.session_store {
+            if let Err(e) = store.set_phase(&session.session_id, phase) {
+                eprintln!("Failed to persist session {}: {}", session.session_id, e);
+            }
+        }
+    }
+
+    async fn resume_distribution(
+        &self,
+        session: &PendingDistribution,
+    ) -> Result<CompactSignature, String> {
+        let requests = session.participants.iter().map(|p| {
+            self.send_partial_signatures(&session.session_id, p, &session.partial_signatures)
+        });
+        let final_signatures = try_join_all(requests).await.map_err(|e| e.to_string())?;
+
+        let signature = *final_signatures
+            .first()
+            .ok_or("No final signatures received from signers")?;
+        let message = hex::decode(&session.signed_message).map_err(|e| e.to_string())?;
+        if let Some((reference, divergent)) =
+            divergent_final_signatures(&session.participants, &final_signatures, |signature| {
+                verify_schnorr(session.aggregated_pubkey, signature, &message).is_ok()
+            })
+        {
+            let error = OperatorError::InconsistentFinalSignatures {
+                reference,
+                divergent,
+            };
+            return Err(error.to_string());
+        }
+        if verify_schnorr(session.aggregated_pubkey, signature, &message).is_err() {
+            return Err("Final signature doesn't verify".to_string());
+        }
+        Ok(signature)
+    }
+
+    /// Tells each of `participants` the final signature of a session finalized by the operator,
+    /// so that they learn what they signed. Failures are only logged, as the signature is
+    /// complete either way.
+    pub(crate) async fn send_final_signature(
+        &self,
+        session_id: &str,
+        participants: &[Participant],
+        final_signature: CompactSignature,
+    ) {
+        let request = FinalSignatureRequest {
+            session_id: session_id.to_string(),
+            final_signature,
+        };
+        let requests = participants.iter().map(|p| {
+            let request = &request;
+            async move {
+                let sent = self
+                    .send_to_signer(
+                        self.with_json_body(
+                            self.client
+                                .inner()
+                                .post(format!("{}/final-signature", p.address)),
+                            request,
+                        ),
+                    )
+                    .await;
+                match sent {
+                    Ok(response) if response.status().is_success() => {}
+                    Ok(response) => eprintln!(
+                        "Signer at {} refused the final signature of session {}: {}",
+                        p.address,
+                        session_id,
+                        response.text().await.unwrap_or_default()
+                    ),
+                    Err(e) => eprintln!(
+                        "Failed to send the final signature of session {} to {}: {}",
+                        session_id, p.address, e
+                    ),
+                }
+            }
+        });
+        futures::future::join_all(requests).await;
+    }
+
+    /// Asks each of `participants` to drop the session. Failures are only logged: an
+    /// unreachable signer is most likely gone, and its session state with it.
+    pub(crate) async fn cancel_on_signers(&self, session_id: &str, participants: &[Participant]) {
+        let requests = participants.iter().map(|p| async move {
+            let mut request = self
+                .client
+                .inner()
+                .delete(format!("{}/sessions/{}", p.address, session_id));
+            if let Some(key) = &self.operator_auth_key {
+                // The cancellation has no body, so the signature covers the empty body
+                request = request.header(OPERATOR_SIGNATURE_HEADER, sign_body(key, &[]));
+            }
+            match self.send_to_signer(request).await {
+                Ok(response) if response.status().is_success() => {}
+                Ok(response) => eprintln!(
+                    "Signer at {} refused to cancel session {}: {}",
+                    p.address,
+                    session_id,
+                    response.status()
+                ),
+                Err(e) => eprintln!(
+                    "Failed to cancel session {} on signer at {}: {}",
+                    session_id, p.address, e
+                ),
+            }
+        });
+        futures::future::join_all(requests).await;
+    }
+
+    /// Attaches `body` as JSON, signed with the operator auth key when one is configured.
+    pub(crate) fn with_json_body<T: Serialize>(
+        &self,
+        request: reqwest::RequestBuilder,
+        body: &T,
+    ) -> reqwest::RequestBuilder {
+        let Some(key) = &self.operator_auth_key else {
+            return request.json(body);
+        };
+        match serde_json::to_vec(body) {
+            Ok(bytes) => request
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .header(OPERATOR_SIGNATURE_HEADER, sign_body(key, &bytes))
+                .body(bytes),
+            // Leave reporting the serialization error to reqwest
+            Err(_) => request.json(body),
+        }
+    }
+
+    /// Sends a request to a signer, bounded by the per-signer timeout and retried on
+    /// connection failures as configured.
+    pub(crate) async fn send_to_signer(
+        &self,
+        request: reqwest::RequestBuilder,
+    ) -> reqwest::Result<reqwest::Response> {
+        send_with_retry(self.with_signer_timeout(request), self.request_retries).await
+    }
+
+    /// Bounds a request to a signer by the per-signer timeout, when one is configured.
+    fn with_signer_timeout(&self, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        match self.signer_timeout {
+            Some(timeout) => request.timeout(timeout),
+            None => request,
+        }
+    }
+
+    pub fn write_metrics(&self, path: &Path) -> std::io::Result<()> {
+        let summary = self.metrics.lock().unwrap().summary();
+        let contents = serde_json::to_string_pretty(&summary)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        std::fs::write(path, contents)
+    }
+
+    /// Persists the first aggregated key and warns when a later participant set diverges from it.
+    pub(crate) async fn check_key_commitment(
+        &self,
+        pubkeys: &[PublicKey],
+        aggregated_key: PublicKey,
+    ) {
+        let Some(path) = &self.key_commitment_path else {
+            return;
+        };
+        let mut key_commitment = self.key_commitment.lock().await;
+        let current = KeyCommitment::new(pubkeys, None, aggregated_key);
+
+        match key_commitment.as_ref() {
+            Some(persisted) if persisted.aggregated_key != current.aggregated_key => {
+                eprintln!(
+                    "⚠️ Registered signers produce aggregated key {} (set {}), but {} (set {}) was committed to {}",
+                    current.aggregated_key,
+                    current.participant_set_hash,
+                    persisted.aggregated_key,
+                    persisted.participant_set_hash,
+                    path.display()
+                );
+            }
+            Some(_) => {}
+            None => match current.save(path) {
+                Ok(()) => {
+                    println!(
+                        "📌 Committed aggregated key {} to {}",
+                        current.aggregated_key,
+                        path.display()
+                    );
+                    *key_commitment = Some(current);
+                }
+                Err(e) => eprintln!("Failed to write key commitment: {}", e),
+            },
+        }
+    }
+
+    /// Aggregated public key of the currently registered signers, with the signer count. The
+    /// signers are collected and ordered as for a ceremony, so the key matches the one signed for.
+    async fn aggregated_pubkey(&self) -> Result<(PublicKey, usize), OperatorError> {
+        let participants = self.participants().await.map_err(|e| {
+            OperatorError::Storage(format!("Failed to access the registered signers: {}", e))
+        })?;
+        if participants.len() < 2 {
+            return Err(OperatorError::NotEnoughSigners {
+                required: 2,
+                available: participants.len(),
+            });
+        }
+        let pubkeys: Vec<PublicKey> = participants.iter().map(|p| p.public_key).collect();
+        let key_agg_ctx = KeyAggContext::new(pubkeys).map_err(|e| {
+            OperatorError::KeyAggregation(format!(
+                "Failed to create key aggregation context: {}",
+                e
+            ))
+        })?;
+        Ok((key_agg_ctx.aggregated_pubkey(), participants.len()))
+    }
+
+    /// Registered signers sorted by their serialized public keys and positioned in that order,
+    /// which is also their order in the key context. Sorting makes the aggregated key and every
+    /// signer's position independent of the order the signers registered in; their registration
+    /// indices are kept.
+    pub(crate) async fn participants(&self) -> Result<Vec<Participant>, String> {
+        let mut signers = self.signers.signers()?;
+        signers.sort_by_key(|p| p.public_key.serialize());
+        Ok(repositioned(signers.iter()))
+    }
+
+    /// Drops the operator's record of an in-flight ceremony, returning the participants it
+    /// started with, if it exists.
+    pub(crate) async fn take_ceremony(&self, session_id: &str) -> Option<Vec<Participant>> {
+        self.ceremonies.lock().await.remove(session_id)
+    }
+
+    /// Reads commands from `input` and runs them against the operator until `quit` or EOF,
+    /// writing replies to `output`. Ceremonies run in the background, so a running one can be
+    /// cancelled from the prompt; on exit, the prompt waits for the ones still running.
+    pub async fn run_repl<R, W>(&self, input: R, output: W)
+    where
+        R: AsyncBufRead + Unpin,
+        W: AsyncWrite + Unpin + Send + 'static,
+    {
+        let output = Arc::new(Mutex::new(output));
+        write_line(
+            &output,
+            "Interactive mode, type `help` for the list of commands.",
+        )
+        .await;
+        let mut lines = input.lines();
+        // Ceremonies started from the prompt, by session ID
+        let mut ceremonies: HashMap<String, JoinHandle<()>> = HashMap::new();
+
+        while let Ok(Some(line)) = lines.next_line().await {
+            ceremonies.retain(|_, task| !task.is_finished());
+            let line = line.trim();
+            let (command, argument) = line.split_once(' ').unwrap_or((line, ""));
+            let argument = argument.trim();
+
+            match command {
+                "" => {}
+                "help" => write_line(
+                    &output,
+                    "Commands:\n  list             registered signers\n  sign <message>   start a signing ceremony\n  aggkey           aggregated public key\n  cancel <id>      cancel a ceremony on the operator and its signers\n  quit             exit",
+                )
+                .await,
+                "list" => match self.signers.signers() {
+                    Ok(signers) => {
+                        for p in &signers {
+                            write_line(
+                                &output,
+                                &format!("{} {} {}", p.index, p.public_key, p.address),
+                            )
+                            .await;
+                        }
+                        write_line(&output, &format!("{} signer(s) registered", signers.len()))
+                            .await;
+                    }
+                    Err(e) => {
+                        write_line(
+                            &output,
+                            &format!("Failed to read the registered signers: {}", e),
+                        )
+                        .await
+                    }
+                },
+                "sign" if !argument.is_empty() => {
+                    let session_id = Uuid::new_v4().to_string();
+                    let request = SigningRequest {
+                        message: argument.to_string(),
+                        session_id: Some(session_id.clone()),
+                        attempt: 0,
+                        encodings: Vec::new(),
+                        tweaks: HashMap::new(),
+                        signer_count: None,
+                        message_encoding: MessageEncoding::Utf8,
+                        signing_domain: SigningDomain::Raw,
+                        include_nonces: false,
+                        include_timings: false,
+                        taproot_merkle_root: None,
+                    };
+                    write_line(&output, &format!("Session {} started", session_id)).await;
+                    let (operator, output) = (self.clone(), output.clone());
+                    let task = tokio::spawn(async move {
+                        let line = match operator.run_ceremony(request).await {
+                            Ok(response) => serde_json::to_string_pretty(&response)
+                                .unwrap_or_else(|e| e.to_string()),
+                            Err(e) => format!("Signing failed: {}", e),
+                        };
+                        write_line(&output, &line).await;
+                    });
+                    ceremonies.insert(session_id, task);
+                }
+                "aggkey" => match self.aggregated_pubkey().await {
+                    Ok((pubkey, count)) => {
+                        write_line(&output, &format!("{} ({} signers)", pubkey, count)).await
+                    }
+                    Err(e) => write_line(&output, &e.to_string()).await,
+                },
+                "cancel" if !argument.is_empty() => {
+                    // Taken before the task stops, so that dropping it doesn't cancel it too
+                    let participants = self.take_ceremony(argument).await;
+                    let task = ceremonies.remove(argument);
+                    let running = task.is_some();
+                    if let Some(task) = task {
+                        task.abort();
+                        // Once it has stopped, no more requests for the session go out
+                        let _ = task.await;
+                    }
+                    if let Some(participants) = &participants {
+                        self.cancel_on_signers(argument, participants).await;
+                    }
+                    if running || participants.is_some() {
+                        write_line(&output, &format!("Session {} cancelled", argument)).await;
+                    } else {
+                        write_line(&output, &format!("No session {}", argument)).await;
+                    }
+                }
+                "quit" | "exit" => break,
+                _ => write_line(&output, &format!("Unknown command: {}", line)).await,
+            }
+        }
+
+        for (_, task) in ceremonies {
+            let _ = task.await;
+        }
+    }
+
+    /// Writes a completed ceremony's response to the result socket as one line of JSON, the
+    /// same JSON the HTTP response carries. An unavailable socket is only logged, as the result
+    /// still reaches the caller over HTTP.
+    pub(crate) async fn publish_result(&self, response: &SigningResponse) {
+        let Some(path) = &self.result_socket else {
+            return;
+        };
+        let mut line = match serde_json::to_vec(response) {
+            Ok(line) => line,
+            Err(e) => {
+                eprintln!(
+                    "Failed to serialize the result of {}: {}",
+                    response.session_id, e
+                );
+                return;
+            }
+        };
+        line.push(b'\n');
+
+        let written = async {
+            let mut stream = tokio::net::UnixStream::connect(path).await?;
+            stream.write_all(&line).await?;
+            stream.shutdown().await
+        };
+        if let Err(e) = written.await {
+            eprintln!(
+                "Failed to write the result of {} to {}: {}",
+                response.session_id,
+                path.display(),
+                e
+            );
+        }
+    }
+
+    /// Logs when the registered signers no longer match a ceremony's participant snapshot.
+    pub(crate) async fn warn_if_participants_changed(
+        &self,
+        session_id: &str,
+        participants: &[Participant],
+    ) {
+        let Ok(signers) = self.signers.signers() else {
+            return;
+        };
+        let unchanged = signers.len() == participants.len()
+            && participants
+                .iter()
+                .all(|p| signers.iter().any(|s| s.public_key == p.public_key));
+        if !unchanged {
+            eprintln!(
+                "⚠️ Registered signers changed during session {} ({} at start, {} now); the ceremony used the snapshot taken at start.",
+                session_id,
+                participants.len(),
+                signers.len()
+            );
+        }
+    }
+}
+
+/// Reports whether a candidate signer set would form a valid key aggregation context, without
+/// touching the registered signers.
+async fn handle_validate_keyset(
+    request: ValidateKeysetRequest,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let response = match validate_keyset(&request.public_keys) {
+        Ok(aggregated_pubkey) => ValidateKeysetResponse {
+            valid: true,
+            aggregated_pubkey: Some(aggregated_pubkey),
+            error: None,
+        },
+        Err(error) => ValidateKeysetResponse {
+            valid: false,
+            aggregated_pubkey: None,
+            error: Some(error),
+        },
+    };
+    Ok(reply::json(&response))
+}
+
+fn registry_error(e: String) -> warp::Rejection {
+    warp::reject::custom(OperatorError::Storage(format!(
+        "Failed to access the registered signers: {}",
+        e
+    )))
+}
+
+/// Writes a line of interactive output. Write errors are ignored: with the output gone, there is
+/// nobody left to report them to.
+async fn write_line<W: AsyncWrite + Unpin>(output: &Mutex<W>, line: &str) {
+    let mut output = output.lock().await;
+    let _ = output.write_all(format!("{}\n", line).as_bytes()).await;
+    let _ = output.flush().await;
+}
+
+/// Reads a JSON array of `{ "address", "public_key" }` signer entries.
+pub fn load_static_signers(path: &Path) -> Result<Vec<SignerRegistrationRequest>, String> {
+    let contents = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let signers: Vec<SignerRegistrationRequest> =
+        serde_json::from_str(&contents).map_err(|e| e.to_string())?;
+    if signers.is_empty() {
+        return Err("static signer list is empty".to_string());
+    }
+    Ok(signers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::secp::SECP256K1;
+    use secp256k1::SecretKey;
+
+    fn participant(index: usize) -> Participant {
+        let secret_key = SecretKey::new(&mut rand::thread_rng());
+        Participant {
+            index,
+            position: index,
+            public_key: PublicKey::from_secret_key(&SECP256K1, &secret_key),
+            address: format!("http://127.0.0.1:{}", 8080 + index),
+        }
+    }
+
+    #[test]
+    fn static_signers_are_read_from_a_json_file() {
+        let path = std::env::temp_dir().join(format!("static-signers-{}.json", Uuid::new_v4()));
+        let signer = participant(0);
+        std::fs::write(
+            &path,
+            format!(
+                r#"[{{"address": "{}", "public_key": "{}"}}]"#,
+                signer.address,
+                hex::encode(signer.public_key.serialize())
+            ),
+        )
+        .unwrap();
+        let signers = load_static_signers(&path).unwrap();
+        assert_eq!(signers.len(), 1);
+        assert_eq!(signers[0].address, signer.address);
+        assert_eq!(signers[0].public_key, signer.public_key);
+
+        std::fs::write(&path, "[]").unwrap();
+        assert_eq!(
+            load_static_signers(&path).err(),
+            Some("static signer list is empty".to_string())
+        );
+        std::fs::remove_file(&path).unwrap();
+        assert!(load_static_signers(&path).is_err());
+    }
+}
@@ -0,0 +1,1564 @@
+use crate::client::{is_connection_error, HttpClient};
+use crate::error::{OperatorError, PartialQuorum, SignerFailure, SignerRef};
+use crate::health::select_healthiest;
+use crate::journal::PendingDistribution;
+use crate::key_agg::{taproot_tweak, tweak_pubkey, KeyAggEncoding};
+use crate::operator::{FinalizeAt, Operator, OperatorConfig};
+use crate::schema::{
+    ResponseSchema, NONCE_RESPONSE, PROTOCOL_VERSION, RECEIVE_NONCES_RESPONSE,
+    RECEIVE_PARTIAL_SIGNATURES_RESPONSE,
+};
+use crate::secp::verify_schnorr;
+use crate::serde_utils::encode_signature;
+use crate::session::{deterministic_session_id, message_hash, signed_bytes};
+use crate::status::TrackedSession;
+use crate::store::SessionProgress;
+use crate::types::{
+    CeremonyPhase, GenerateNonceRequest, MessageEncoding, Participant, PhaseTimings,
+    ReceiveNoncesRequest, ReceiveNoncesResponse, ReceivePartialSignaturesRequest,
+    ReceivePartialSignaturesResponse, SigningDomain, SigningRequest, SigningResponse,
+    SigningSession, TaprootCommitment,
+};
+use futures::future::try_join_all;
+use musig2::secp::Scalar;
+use musig2::{AggNonce, CompactSignature, KeyAggContext, PartialSignature, PubNonce};
+use secp256k1::PublicKey;
+use serde::de::DeserializeOwned;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::future::Future;
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+use uuid::Uuid;
+
+/// Why a signing session failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SigningError {
+    /// The signer at the failure's address failed or misbehaved
+    Signer(SignerFailure),
+    /// Some signers failed the nonce round, but enough answered to sign without them
+    PartialQuorum(PartialQuorum),
+    /// The session couldn't be run, e.g. because the message or the signer set is invalid
+    Session(OperatorError),
+}
+
+impl fmt::Display for SigningError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SigningError::Signer(failure) => {
+                write!(
+                    f,
+                    "signer at {} failed: {}",
+                    failure.address, failure.message
+                )
+            }
+            SigningError::PartialQuorum(quorum) => write!(f, "{}", quorum.failures.join("; ")),
+            SigningError::Session(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for SigningError {}
+
+impl From<OperatorError> for SigningError {
+    fn from(e: OperatorError) -> Self {
+        SigningError::Session(e)
+    }
+}
+
+/// Lets the operator's handlers answer a failed session with the status and code
+/// [`handle_operator_rejection`](crate::error::handle_operator_rejection) gives it.
+impl From<SigningError> for warp::Rejection {
+    fn from(error: SigningError) -> Self {
+        match error {
+            SigningError::Signer(failure) => warp::reject::custom(failure),
+            SigningError::PartialQuorum(quorum) => warp::reject::custom(quorum),
+            SigningError::Session(e) => warp::reject::custom(e),
+        }
+    }
+}
+
+/// Runs one signing session over `message` with the given signers, as `(index, public key,
+/// address)`, without an operator HTTP server. The signers are reached over HTTP as usual, and
+/// errors name them by the given index. With `taproot_tweak`, the aggregated key is committed
+/// to it and the signature verifies under the resulting output key.
+///
+/// The session is coordinated by the same code as the operator's `/sign`, with the operator's
+/// defaults: signers finalize, the signature is verified once and nothing is persisted.
+pub async fn run_signing_session(
+    client: &HttpClient,
+    signers: &[(usize, PublicKey, String)],
+    message: &[u8],
+    taproot_tweak: Option<TaprootCommitment>,
+) -> Result<SigningResponse, SigningError> {
+    let operator = Operator::new(client.clone(), session_config(), None);
+    let mut participants: Vec<Participant> = signers
+        .iter()
+        .map(|(index, public_key, address)| Participant {
+            index: *index,
+            position: 0,
+            public_key: *public_key,
+            address: address.clone(),
+        })
+        .collect();
+    // In key order, as the operator's registered signers are
+    participants.sort_by_key(|p| p.public_key.serialize());
+    let participants = repositioned(participants.iter());
+
+    let message = hex::encode(message);
+    operator.check_message(&message, MessageEncoding::Hex)?;
+    let request = SigningRequest {
+        message,
+        session_id: None,
+        attempt: 0,
+        encodings: Vec::new(),
+        tweaks: HashMap::new(),
+        signer_count: None,
+        message_encoding: MessageEncoding::Hex,
+        signing_domain: SigningDomain::Raw,
+        include_nonces: false,
+        include_timings: false,
+        taproot_merkle_root: taproot_tweak,
+    };
+    let imports = operator.state_imports.subscribe();
+    operator.coordinate(request, &participants, imports).await
+}
+
+/// Configuration of the throwaway operator coordinating a [`run_signing_session`].
+fn session_config() -> OperatorConfig {
+    OperatorConfig {
+        port: 0,
+        max_batch_size: 1,
+        ..Default::default()
+    }
+}
+
+// The ceremony itself, as run for `/sign` and `run_signing_session` alike
+impl Operator {
+    /// Drops the signer that caused `failure` from `participants` when faulty signers are to be
+    /// excluded, returning the remaining signers, or `None` if the ceremony should fail instead.
+    fn exclude_faulty_signer(
+        &self,
+        participants: &[Participant],
+        failure: &SignerFailure,
+    ) -> Option<Vec<Participant>> {
+        if !self.exclude_faulty_signers {
+            return None;
+        }
+        if participants.len() <= 2 {
+            eprintln!(
+                "Not excluding signer at {}: too few signers would remain",
+                failure.address
+            );
+            return None;
+        }
+        // The remaining signers form a new key, signed for with fresh nonces
+        eprintln!(
+            "🚫 Excluding signer at {} ({}) and retrying with {} signer(s)",
+            failure.address,
+            failure.message,
+            participants.len() - 1
+        );
+        Some(excluding_participant(participants, &failure.address))
+    }
+
+    /// Runs a ceremony with the registered signers, as `/sign` does.
+    pub(crate) async fn run_ceremony(
+        &self,
+        request: SigningRequest,
+    ) -> Result<SigningResponse, SigningError> {
+        // Subscribed before the snapshot, so an import replacing it stops the ceremony
+        let imports = self.state_imports.subscribe();
+        // Snapshot the registered signers so registrations during the ceremony don't affect it
+        let snapshot = self.participants().await.map_err(|e| {
+            OperatorError::Storage(format!("Failed to access the registered signers: {}", e))
+        })?;
+        let response = self.coordinate(request, &snapshot, imports).await?;
+        self.warn_if_participants_changed(&response.session_id, &snapshot)
+            .await;
+        Ok(response)
+    }
+
+    /// Runs a ceremony with the signers of `snapshot`, ordered and positioned as for the key
+    /// context. Depending on the configuration, signers that fail are retried, failed over to
+    /// their standby or left out. A state import, announced on `imports`, stops the ceremony.
+    async fn coordinate(
+        &self,
+        request: SigningRequest,
+        snapshot: &[Participant],
+        mut imports: watch::Receiver<u64>,
+    ) -> Result<SigningResponse, SigningError> {
+        let mut participants = snapshot.to_vec();
+
+        // One signer's "multi-signature" is meaningless, and no signers can't form a key at all
+        if snapshot.len() < 2 {
+            return Err(SigningError::Session(OperatorError::NotEnoughSigners {
+                required: 2,
+                available: snapshot.len(),
+            }));
+        }
+
+        if let Some(pubkey) = request
+            .tweaks
+            .keys()
+            .find(|pubkey| !snapshot.iter().any(|p| p.public_key == **pubkey))
+        {
+            return Err(SigningError::Session(OperatorError::InvalidRequest(
+                format!("Tweak given for unregistered public key {}", pubkey),
+            )));
+        }
+
+        let mut connection_retries = 0;
+        if let Some(count) = request.signer_count {
+            if count < 2 || count > snapshot.len() {
+                return Err(SigningError::Session(OperatorError::InvalidRequest(
+                    format!(
+                        "Signer count must be between 2 and the {} registered signer(s)",
+                        snapshot.len()
+                    ),
+                )));
+            }
+            participants = select_healthiest(snapshot, &self.health.lock().unwrap(), count);
+            let addresses: Vec<&str> = participants.iter().map(|p| p.address.as_str()).collect();
+            println!(
+                "🩺 Selected the {} healthiest signer(s): {}",
+                count,
+                addresses.join(", ")
+            );
+        }
+
+        let message = signed_bytes(
+            &request.message,
+            request.message_encoding,
+            request.signing_domain,
+        )
+        .map_err(|e| SigningError::Session(OperatorError::InvalidRequest(e)))?;
+
+        // Claimed before the first attempt, so a concurrent request can't take the same ID;
+        // retries rerun the ceremony under it
+        let mut chosen = match &request.session_id {
+            Some(session_id) => Some(
+                self.claim_session_id(session_id, participants.len())
+                    .await?,
+            ),
+            None => None,
+        };
+
+        let mut reruns: u32 = 0;
+        let result = loop {
+            let tracked = match chosen.take() {
+                Some(tracked) => {
+                    tracked.restart(participants.len());
+                    tracked
+                }
+                // Every rerun counts as the next attempt, so it gets an ID of its own
+                None if self.deterministic_session_ids => {
+                    let pubkeys: Vec<PublicKey> =
+                        participants.iter().map(|p| p.public_key).collect();
+                    let session_id = deterministic_session_id(
+                        &pubkeys,
+                        &message,
+                        &request.tweaks,
+                        request.taproot_merkle_root.as_ref(),
+                        request.attempt.wrapping_add(reruns),
+                    );
+                    match self.claim_session_id(&session_id, participants.len()).await {
+                        Ok(tracked) => tracked,
+                        Err(error) => break Err(error),
+                    }
+                }
+                None => self
+                    .session_tracker
+                    .start(&Uuid::new_v4().to_string(), participants.len()),
+            };
+            reruns += 1;
+
+            let mut abandoned = AbandonedCeremony {
+                operator: self.clone(),
+                session_id: None,
+            };
+            let (outcome, stopped) = tokio::select! {
+                outcome = self.execute_ceremony(
+                    &request,
+                    &participants,
+                    &tracked,
+                    &mut abandoned.session_id,
+                ) => (outcome, false),
+                _ = imports.changed() => (
+                    Err(SigningError::Session(OperatorError::Cancelled(
+                        "The ceremony was stopped by a state import".to_string(),
+                    ))),
+                    true,
+                ),
+            };
+            // The ceremony ran to an end, so its signers are done with the session. One stopped
+            // by an import is left to `abandoned`, which cancels it on them unless the import
+            // got to it first.
+            if !stopped {
+                if let Some(session_id) = abandoned.session_id.take() {
+                    self.take_ceremony(&session_id).await;
+                }
+            }
+            let outcome = match outcome {
+                Ok((response, timings)) => {
+                    tracked.complete(response.aggregated_signature);
+                    Ok((response, timings))
+                }
+                Err(error) => {
+                    if request.session_id.is_some() {
+                        chosen = Some(tracked);
+                    }
+                    Err(error)
+                }
+            };
+            match outcome {
+                // The answering signers form a new key, signed for with fresh nonces
+                Err(SigningError::PartialQuorum(quorum)) => {
+                    eprintln!(
+                        "🧮 Continuing with the {} of {} signer(s) that answered the nonce round ({})",
+                        quorum.responsive.len(),
+                        participants.len(),
+                        quorum.failures.join("; ")
+                    );
+                    participants = retaining_participants(&participants, &quorum.responsive);
+                    // They still hold a round for the abandoned session
+                    self.cancel_on_signers(&quorum.session_id, &participants)
+                        .await;
+                }
+                // A dropped connection may be transient, so first retry with the same signers
+                Err(SigningError::Signer(failure))
+                    if failure.connection_lost && connection_retries < self.connection_retries =>
+                {
+                    connection_retries += 1;
+                    eprintln!(
+                        "🔁 Retrying ceremony with fresh nonces after a lost connection ({}/{})",
+                        connection_retries, self.connection_retries
+                    );
+                }
+                Err(SigningError::Signer(failure)) if failure.connection_lost => {
+                    let failed = &failure.address;
+                    // The registry may have moved on since the snapshot, so the key is what
+                    // identifies the failed signer there
+                    let failed_over = match participants.iter().find(|p| p.address == *failed) {
+                        Some(p) => self
+                            .fail_over(p.public_key, failed)
+                            .await
+                            .map(|standby| (p.public_key, standby)),
+                        None => None,
+                    };
+                    match failed_over {
+                        Some((public_key, standby)) => {
+                            for participant in participants.iter_mut() {
+                                if participant.public_key == public_key {
+                                    participant.address = standby.clone();
+                                }
+                            }
+                        }
+                        None => match self.exclude_faulty_signer(&participants, &failure) {
+                            Some(remaining) => participants = remaining,
+                            None => break Err(SigningError::Signer(failure)),
+                        },
+                    }
+                }
+                Err(SigningError::Signer(failure)) => {
+                    match self.exclude_faulty_signer(&participants, &failure) {
+                        Some(remaining) => participants = remaining,
+                        None => break Err(SigningError::Signer(failure)),
+                    }
+                }
+                Err(error) => break Err(error),
+                Ok(response) => break Ok(response),
+            }
+        };
+
+        if let Ok((response, _)) = &result {
+            self.publish_result(response).await;
+            self.signature_hook.handle(response).await;
+            let mut health = self.health.lock().unwrap();
+            for participant in &participants {
+                health
+                    .entry(participant.address.clone())
+                    .or_default()
+                    .record_success();
+            }
+        }
+
+        let mut metrics = self.metrics.lock().unwrap();
+        match &result {
+            Ok((_, timings)) => metrics.record_success(timings),
+            Err(_) => metrics.record_failure(),
+        }
+
+        result.map(|(response, _)| response)
+    }
+
+    /// Reserves a client-supplied session ID, rejecting it if a ceremony, batch or persisted
+    /// session already uses it rather than overwriting that session. The ID of a session that
+    /// failed is free again, so that the client can retry under it.
+    async fn claim_session_id(
+        &self,
+        session_id: &str,
+        signer_count: usize,
+    ) -> Result<TrackedSession, SigningError> {
+        // The ID ends up in URL paths on the operator and the signers
+        let well_formed = !session_id.is_empty()
+            && session_id.len() <= 64
+            && session_id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !well_formed {
+            return Err(SigningError::Session(OperatorError::InvalidRequest(
+                "Session ID must be 1 to 64 ASCII letters, digits, '-' or '_'".to_string(),
+            )));
+        }
+        let duplicate =
+            || SigningError::Session(OperatorError::DuplicateSession(session_id.to_string()));
+        if self.session_messages.lock().await.contains_key(session_id) {
+            return Err(duplicate());
+        }
+        // Sessions of earlier runs are kept in the database, which would overwrite them
+        if let Some(store) = &self.session_store {
+            let in_use = store.in_use(session_id).map_err(|e| {
+                SigningError::Session(OperatorError::Storage(format!(
+                    "Failed to read the session database: {}",
+                    e
+                )))
+            })?;
+            if in_use {
+                return Err(duplicate());
+            }
+        }
+        self.session_tracker
+            .claim(session_id, signer_count)
+            .ok_or_else(duplicate)
+    }
+
+    /// Runs one attempt at a ceremony. `started` is set to the session ID as soon as the signers
+    /// may hold state for it, see [`AbandonedCeremony`].
+    async fn execute_ceremony(
+        &self,
+        request: &SigningRequest,
+        participants: &[Participant],
+        tracked: &TrackedSession,
+        started: &mut Option<String>,
+    ) -> Result<(SigningResponse, PhaseTimings), SigningError> {
+        println!("Initiating signing of the message: {:?}", request.message);
+        let message = signed_bytes(
+            &request.message,
+            request.message_encoding,
+            request.signing_domain,
+        )
+        .map_err(|e| SigningError::Session(OperatorError::InvalidRequest(e)))?;
+
+        // Individual tweaks replace participant keys with their tweaked versions from here on
+        let (participants, signer_tweaks) = &apply_tweaks(participants, &request.tweaks)
+            .map_err(|e| SigningError::Session(OperatorError::InvalidRequest(e)))?;
+
+        // Create KeyAggContext from the participants
+        let pubkeys: Vec<PublicKey> = participants.iter().map(|p| p.public_key).collect();
+
+        // musig2 accepts repeated keys, but a signer can't tell which of the slots is its own
+        if let Some(duplicate) = first_duplicate(&pubkeys) {
+            return Err(SigningError::Session(OperatorError::KeyAggregation(
+                format!(
+                    "Public key {} appears more than once in the participant set",
+                    duplicate
+                ),
+            )));
+        }
+
+        let key_agg_ctx = KeyAggContext::new(pubkeys.clone()).map_err(|_| {
+            SigningError::Session(OperatorError::KeyAggregation(
+                "Failed to create key aggregation context".to_string(),
+            ))
+        })?;
+
+        // The commitment tracks the signer set's own key, whatever output it is tweaked into
+        self.check_key_commitment(&pubkeys, key_agg_ctx.aggregated_pubkey())
+            .await;
+
+        let (key_agg_ctx, taproot) = match &request.taproot_merkle_root {
+            Some(commitment) => {
+                let (key_agg_ctx, taproot) = taproot_tweak(key_agg_ctx, commitment)
+                    .map_err(|e| SigningError::Session(OperatorError::InvalidRequest(e)))?;
+                (key_agg_ctx, Some(taproot))
+            }
+            None => (key_agg_ctx, None),
+        };
+        let aggregated_pubkey: PublicKey = key_agg_ctx.aggregated_pubkey();
+
+        let session_id = tracked.session_id().to_string();
+
+        // Only public keys are logged, in the order they were aggregated
+        let participant_pubkeys: Vec<String> = pubkeys
+            .iter()
+            .map(|pk| hex::encode(pk.serialize()))
+            .collect();
+        tracing::info!(
+            session_id = %session_id,
+            participants = ?participant_pubkeys,
+            aggregated_pubkey = %hex::encode(aggregated_pubkey.serialize()),
+            "ceremony started"
+        );
+
+        let session = SigningSession {
+            session_id: session_id.clone(),
+            message: request.message.clone(),
+            message_encoding: request.message_encoding,
+            signing_domain: request.signing_domain,
+            key_agg_ctx: key_agg_ctx.clone(),
+        };
+
+        let progress = self
+            .session_store
+            .clone()
+            .map(|store| SessionProgress::begin(store, &session));
+
+        self.ceremonies
+            .lock()
+            .await
+            .insert(session_id.clone(), participants.to_vec());
+        *started = Some(session_id.clone());
+
+        let mut timings = PhaseTimings::default();
+        let mut phase_start = Instant::now();
+
+        // Request nonces from all signers at once
+        let client = self.client.inner();
+        let mut indexed_nonces = HashMap::new();
+        let mut pub_nonces = HashMap::new();
+
+        let responded = std::sync::Mutex::new(Vec::new());
+        let phase = async {
+            let (session_id, responded) = (&session_id, &responded);
+            let key_agg_ctx = &key_agg_ctx;
+            let requests = participants.iter().map(
+                |Participant {
+                     index,
+                     position: i,
+                     address,
+                     ..
+                 }| async move {
+                    let nonce_request = GenerateNonceRequest {
+                        session_id: session_id.clone(),
+                        message: request.message.clone(),
+                        message_encoding: request.message_encoding,
+                        signing_domain: request.signing_domain,
+                        key_agg_ctx: (self.key_agg_encoding == KeyAggEncoding::Full)
+                            .then(|| key_agg_ctx.clone()),
+                        aggregated_pubkey: Some(aggregated_pubkey),
+                        signer_index: *i,
+                        reservation_id: None,
+                        participants: participants.to_vec(),
+                        tweak: signer_tweaks.get(i).copied(),
+                        taproot_merkle_root: request.taproot_merkle_root,
+                    };
+
+                    let sent = Instant::now();
+                    let response = self
+                        .send_to_signer(self.with_json_body(
+                            client.post(format!("{}/nonce", address)),
+                            &nonce_request,
+                        ))
+                        .await
+                        .map_err(|e| {
+                            self.request_failure(*index, address, "Failed to request nonce", &e)
+                        })?;
+
+                    if response.status() == reqwest::StatusCode::SERVICE_UNAVAILABLE {
+                        eprintln!("Signer {} at {} declined to participate", index, address);
+                        return Err(self.signer_failure(
+                            address,
+                            format!("Signer {} at {} declined to participate", index, address),
+                        ));
+                    }
+
+                    if response.status() == reqwest::StatusCode::UNAUTHORIZED {
+                        return Err(self.signer_failure(
+                            address,
+                            format!(
+                                "Signer {} at {} rejected the request as unauthorized; check that both use the same operator auth key",
+                                index, address
+                            ),
+                        ));
+                    }
+
+                    let nonce: Vec<u8> = self
+                        .parse_signer_response(
+                            response,
+                            *index,
+                            &NONCE_RESPONSE,
+                            address,
+                            "Failed to parse nonce response",
+                        )
+                        .await?;
+
+                    // Catch malformed nonces here rather than when other signers try to use them
+                    let pub_nonce = PubNonce::from_bytes(&nonce).map_err(|_| {
+                        self.signer_failure(
+                            address,
+                            format!(
+                                "Signer {} at {} returned an invalid public nonce ({} bytes)",
+                                index,
+                                address,
+                                nonce.len()
+                            ),
+                        )
+                    })?;
+
+                    self.health
+                        .lock()
+                        .unwrap()
+                        .entry(address.clone())
+                        .or_default()
+                        .record_response(sent.elapsed());
+
+                    // Signing twice with one nonce leaks the signer's secret key
+                    if let Some(previous_session) =
+                        self.used_nonces.lock().unwrap().session_of(&nonce)
+                    {
+                        tracing::error!(
+                            session_id = %session_id,
+                            previous_session_id = %previous_session,
+                            signer_index = index,
+                            signer = %address,
+                            "nonce reuse detected, aborting ceremony"
+                        );
+                        return Err(SigningError::Session(OperatorError::NonceReuse {
+                            index: *index,
+                            address: address.clone(),
+                            session_id: previous_session.to_string(),
+                        }));
+                    }
+
+                    responded.lock().unwrap().push(*i);
+                    tracked.signer_responded();
+                    Ok::<_, SigningError>((*i, nonce, pub_nonce))
+                },
+            );
+
+            let results = match self.min_signers {
+                // With a quorum, every signer gets to answer before any is given up on
+                Some(_) => futures::future::join_all(requests).await,
+                None => try_join_all(requests).await?.into_iter().map(Ok).collect(),
+            };
+
+            let mut failures = Vec::new();
+            for result in results {
+                match result {
+                    Ok((i, nonce, pub_nonce)) => {
+                        indexed_nonces.insert(i, nonce);
+                        pub_nonces.insert(i, pub_nonce);
+                    }
+                    Err(error) => failures.push(error),
+                }
+            }
+            if failures.is_empty() {
+                return Ok(());
+            }
+            // Failures other than a signer's, such as a reused nonce, abort the ceremony
+            if let Some(i) = failures
+                .iter()
+                .position(|error| !matches!(error, SigningError::Signer(_)))
+            {
+                return Err(failures.swap_remove(i));
+            }
+            let reasons: Vec<String> = failures
+                .iter()
+                .filter_map(|error| match error {
+                    SigningError::Signer(failure) => Some(failure.message.clone()),
+                    _ => None,
+                })
+                .collect();
+            Err(self
+                .partial_quorum(
+                    session_id,
+                    participants,
+                    &responded.lock().unwrap(),
+                    reasons,
+                )
+                .unwrap_or_else(|| failures.swap_remove(0)))
+        };
+        let outcome = with_deadline(self.nonce_deadline, phase).await;
+        if outcome.is_none() {
+            let responded = responded.lock().unwrap();
+            let reasons = vec![format!(
+                "Nonce collection deadline exceeded after {} of {} signers answered",
+                responded.len(),
+                participants.len()
+            )];
+            if let Some(quorum) =
+                self.partial_quorum(&session_id, participants, &responded, reasons)
+            {
+                for p in participants
+                    .iter()
+                    .filter(|p| !responded.contains(&p.position))
+                {
+                    self.record_signer_error(&p.address);
+                }
+                return Err(quorum);
+            }
+        }
+        self.check_phase(
+            outcome,
+            "Nonce collection",
+            participants,
+            &responded.lock().unwrap(),
+        )?;
+
+        // A gap would leave the signers short of a nonce, only to fail at finalization
+        if let Some(index) = missing_indices(participants, &indexed_nonces).first() {
+            return Err(SigningError::Session(OperatorError::MissingNonce(*index)));
+        }
+        // Signers sharing a nonce have broken nonce generation, whatever else they return
+        if let Some((first, second)) = duplicate_nonce(participants, &indexed_nonces) {
+            tracing::error!(
+                session_id = %session_id,
+                first_signer = %first,
+                second_signer = %second,
+                "duplicate nonce detected, aborting ceremony"
+            );
+            self.record_signer_error(&first.address);
+            self.record_signer_error(&second.address);
+            return Err(SigningError::Session(OperatorError::DuplicateNonce {
+                first,
+                second,
+            }));
+        }
+        let aggregated_nonce: AggNonce = pub_nonces.values().sum();
+        if let Some(progress) = &progress {
+            progress.nonces_collected(&pub_nonces);
+        }
+        tracked.enter(CeremonyPhase::CollectingPartials);
+        timings.nonce_collection_ms = elapsed_ms(&mut phase_start);
+
+        // From here on signers may sign with these nonces, so they must never be used again
+        let mut used_nonces = self.used_nonces.lock().unwrap();
+        for nonce in indexed_nonces.values() {
+            used_nonces.record(nonce.clone(), &session_id);
+        }
+        drop(used_nonces);
+
+        // Distribute nonces to all signers at once and collect partial signatures
+        let client = self.client.inner();
+        let mut indexed_partial_sigs = HashMap::new();
+        let expected_message_hash = message_hash(&message);
+        let mut divergent = Vec::new();
+
+        let responded = std::sync::Mutex::new(Vec::new());
+        let phase = async {
+            let (session_id, responded) = (&session_id, &responded);
+            let (indexed_nonces, pub_nonces) = (&indexed_nonces, &pub_nonces);
+            let (expected_message_hash, message) = (&expected_message_hash, &message);
+            let (key_agg_ctx, aggregated_nonce) = (&key_agg_ctx, &aggregated_nonce);
+            let requests = participants.iter().map(
+                |Participant {
+                     index,
+                     position: i,
+                     public_key,
+                     address,
+                 }| async move {
+                    // Everyone else's nonces, without this signer's own
+                    let other_nonces = excluding_signer(indexed_nonces, *i)
+                        .map_err(|e| SigningError::Session(OperatorError::Internal(e)))?;
+
+                    let receive_nonces_request = ReceiveNoncesRequest {
+                        session_id: session_id.clone(),
+                        nonces: other_nonces,
+                    };
+
+                    let response = self
+                        .send_to_signer(self.with_json_body(
+                            client.put(format!("{}/nonces", address)),
+                            &receive_nonces_request,
+                        ))
+                        .await
+                        .map_err(|e| {
+                            self.request_failure(*index, address, "Failed to distribute nonces", &e)
+                        })?;
+                    let response: ReceiveNoncesResponse = self
+                        .parse_signer_response(
+                            response,
+                            *index,
+                            &RECEIVE_NONCES_RESPONSE,
+                            address,
+                            "Failed to parse response from /nonces",
+                        )
+                        .await?;
+                    responded.lock().unwrap().push(*i);
+                    tracked.signer_responded();
+
+                    // A signer driven with another message can't produce a valid partial for
+                    // ours; `None` marks it as divergent
+                    if response.message_hash != *expected_message_hash {
+                        eprintln!(
+                            "Signer {} at {} signed a message with hash {}, expected {}",
+                            index, address, response.message_hash, expected_message_hash
+                        );
+                        return Ok::<_, SigningError>((*i, *index, None));
+                    }
+
+                    // A well-formed but wrong partial signature would only surface at finalization
+                    musig2::verify_partial(
+                        key_agg_ctx,
+                        response.partial_signature,
+                        aggregated_nonce,
+                        *public_key,
+                        &pub_nonces[i],
+                        message,
+                    )
+                    .map_err(|_| {
+                        self.signer_failure(
+                            address,
+                            format!(
+                                "Signer {} at {} returned an invalid partial signature",
+                                index, address
+                            ),
+                        )
+                    })?;
+
+                    Ok((*i, *index, Some(response.partial_signature)))
+                },
+            );
+
+            for (i, index, partial_signature) in try_join_all(requests).await? {
+                match partial_signature {
+                    Some(partial_signature) => {
+                        indexed_partial_sigs.insert(i, partial_signature);
+                    }
+                    None => divergent.push(index),
+                }
+            }
+            Ok::<(), SigningError>(())
+        };
+        let outcome = with_deadline(self.partial_deadline, phase).await;
+        self.check_phase(
+            outcome,
+            "Partial signature collection",
+            participants,
+            &responded.lock().unwrap(),
+        )?;
+        timings.partial_collection_ms = elapsed_ms(&mut phase_start);
+
+        if !divergent.is_empty() {
+            return Err(SigningError::Session(OperatorError::MessageDivergence(
+                divergent,
+            )));
+        }
+
+        // Every signer needs all other partials to finalize, so don't start with gaps
+        let missing = missing_indices(participants, &indexed_partial_sigs);
+        if !missing.is_empty() {
+            return Err(SigningError::Session(OperatorError::MissingPartials(
+                missing,
+            )));
+        }
+
+        let aggregated_signature = match self.finalize_at {
+            // All partials are at hand and verified, so they can be aggregated right here
+            FinalizeAt::Operator => {
+                let aggregated_signature = musig2::aggregate_partial_signatures(
+                    &key_agg_ctx,
+                    &aggregated_nonce,
+                    indexed_partial_sigs.values().copied(),
+                    &message,
+                )
+                .map_err(|_| {
+                    SigningError::Session(OperatorError::Finalize(
+                        "Failed to aggregate partial signatures".to_string(),
+                    ))
+                })?;
+                self.send_final_signature(&session_id, participants, aggregated_signature)
+                    .await;
+                timings.distribution_ms = elapsed_ms(&mut phase_start);
+                aggregated_signature
+            }
+            FinalizeAt::Signers => {
+                // Journal the partials first, so a restarted operator can still distribute them
+                if let Some(journal) = &self.session_journal {
+                    let pending = PendingDistribution {
+                        session_id: session_id.clone(),
+                        participants: participants.to_vec(),
+                        partial_signatures: indexed_partial_sigs.clone(),
+                        aggregated_pubkey,
+                        signed_message: hex::encode(&message),
+                    };
+                    if let Err(e) = journal.record(&pending) {
+                        eprintln!("Failed to journal session {}: {}", session_id, e);
+                    }
+                }
+
+                if let Some(progress) = &progress {
+                    progress.distributing();
+                }
+                tracked.enter(CeremonyPhase::Distributing);
+
+                // Distribute partial signatures to all signers at once
+                let mut final_signatures = Vec::new();
+
+                let responded = std::sync::Mutex::new(Vec::new());
+                let phase = async {
+                    let (session_id, responded) = (&session_id, &responded);
+                    let indexed_partial_sigs = &indexed_partial_sigs;
+                    let requests = participants.iter().map(|participant| async move {
+                        let final_signature = self
+                            .send_partial_signatures(session_id, participant, indexed_partial_sigs)
+                            .await?;
+                        responded.lock().unwrap().push(participant.position);
+                        tracked.signer_responded();
+                        Ok::<_, SigningError>(final_signature)
+                    });
+
+                    final_signatures = try_join_all(requests).await?;
+                    Ok::<(), SigningError>(())
+                };
+                let outcome = with_deadline(self.distribution_deadline, phase).await;
+                self.forget_pending_distribution(&session_id);
+                self.check_phase(
+                    outcome,
+                    "Distribution",
+                    participants,
+                    &responded.lock().unwrap(),
+                )?;
+                timings.distribution_ms = elapsed_ms(&mut phase_start);
+
+                // Verify all signers produced the same final signature
+                if let Some((reference, divergent)) =
+                    divergent_final_signatures(participants, &final_signatures, |signature| {
+                        verify_schnorr(aggregated_pubkey, signature, &message).is_ok()
+                    })
+                {
+                    for signer in &divergent {
+                        self.record_signer_error(&signer.address);
+                    }
+                    return Err(SigningError::Session(
+                        OperatorError::InconsistentFinalSignatures {
+                            reference,
+                            divergent,
+                        },
+                    ));
+                }
+
+                // Every participant answered, and a ceremony has at least two, so there is a
+                // first signature; since all signers produced the same one, it can be used
+                final_signatures[0]
+            }
+        };
+
+        // Verify the signature as often as the policy asks for
+        let is_signature_valid = self
+            .verify_policy
+            .apply(|| verify_schnorr(aggregated_pubkey, aggregated_signature, &message).is_ok());
+        timings.verification_ms = elapsed_ms(&mut phase_start);
+        if let Some(progress) = progress {
+            progress.complete();
+        }
+
+        let response = SigningResponse {
+            session_id,
+            message: request.message.clone(),
+            message_encoding: request.message_encoding,
+            aggregated_pubkey,
+            aggregated_signature,
+            is_signature_valid,
+            encoded_signatures: request
+                .encodings
+                .iter()
+                .map(|&encoding| (encoding, encode_signature(&aggregated_signature, encoding)))
+                .collect(),
+            public_nonces: if request.include_nonces {
+                pub_nonces.into_iter().collect()
+            } else {
+                BTreeMap::new()
+            },
+            timings: request.include_timings.then_some(timings),
+            taproot,
+        };
+
+        Ok((response, timings))
+    }
+
+    /// Sends `participant` everyone else's partial signatures, keyed by position, and returns
+    /// the final signature it produces.
+    pub(crate) async fn send_partial_signatures(
+        &self,
+        session_id: &str,
+        participant: &Participant,
+        indexed_partial_sigs: &HashMap<usize, PartialSignature>,
+    ) -> Result<CompactSignature, SigningError> {
+        let (index, address) = (participant.index, participant.address.as_str());
+        let client = self.client.inner();
+        // Everyone else's partial signatures, without this signer's own
+        let other_sigs = excluding_signer(indexed_partial_sigs, participant.position)
+            .map_err(|e| SigningError::Session(OperatorError::Internal(e)))?;
+
+        let partial_sigs_request = ReceivePartialSignaturesRequest {
+            session_id: session_id.to_string(),
+            partial_signatures: other_sigs,
+        };
+
+        let response = self
+            .send_to_signer(self.with_json_body(
+                client.put(format!("{}/partial-signatures", address)),
+                &partial_sigs_request,
+            ))
+            .await
+            .map_err(|e| {
+                eprintln!("Failed to send request to {}: {:?}", address, e);
+                self.request_failure(index, address, "Failed to send request", &e)
+            })?;
+
+        // Handle non-success status codes
+        self.check_redirect(&response, index, address)?;
+        if !response.status().is_success() {
+            let error_text = response.text().await.map_err(|e| {
+                eprintln!("Failed to get error response text: {:?}", e);
+                self.request_failure(index, address, "Failed to get error response", &e)
+            })?;
+            eprintln!("Error response from {}: {}", address, error_text);
+            return Err(self.signer_failure(address, format!("Signer error: {}", error_text)));
+        }
+
+        // Try to parse the response
+        let parsed_response: ReceivePartialSignaturesResponse = self
+            .parse_signer_response(
+                response,
+                index,
+                &RECEIVE_PARTIAL_SIGNATURES_RESPONSE,
+                address,
+                "Failed to parse response",
+            )
+            .await?;
+        Ok(parsed_response.final_signature)
+    }
+
+    /// With `--min-signers`, turns a nonce round that only some of the participants answered
+    /// into a [`PartialQuorum`], as long as enough of them did.
+    fn partial_quorum(
+        &self,
+        session_id: &str,
+        participants: &[Participant],
+        responded: &[usize],
+        failures: Vec<String>,
+    ) -> Option<SigningError> {
+        let min_signers = self.min_signers?;
+        if responded.len() < min_signers || responded.len() == participants.len() {
+            return None;
+        }
+        Some(SigningError::PartialQuorum(PartialQuorum {
+            session_id: session_id.to_string(),
+            responsive: participants
+                .iter()
+                .filter(|p| responded.contains(&p.position))
+                .map(|p| p.address.clone())
+                .collect(),
+            failures,
+        }))
+    }
+
+    /// Turns the outcome of a phase run under a deadline into the phase result, naming the
+    /// signers that hadn't responded if the deadline passed.
+    fn check_phase(
+        &self,
+        outcome: Option<Result<(), SigningError>>,
+        phase: &str,
+        participants: &[Participant],
+        responded: &[usize],
+    ) -> Result<(), SigningError> {
+        if let Some(result) = outcome {
+            return result;
+        }
+
+        let pending: Vec<&Participant> = participants
+            .iter()
+            .filter(|p| !responded.contains(&p.position))
+            .collect();
+        for participant in &pending {
+            self.record_signer_error(&participant.address);
+        }
+        let pending: Vec<String> = pending
+            .iter()
+            .map(|p| format!("{} at {}", p.index, p.address))
+            .collect();
+        Err(SigningError::Session(OperatorError::DeadlineExceeded {
+            phase: phase.to_string(),
+            pending,
+        }))
+    }
+
+    /// Parses a signer's JSON response, in strict mode first checking it against `schema` so that
+    /// a signer speaking another protocol version is caught before its fields are misread.
+    async fn parse_signer_response<T: DeserializeOwned>(
+        &self,
+        response: reqwest::Response,
+        index: usize,
+        schema: &ResponseSchema,
+        address: &str,
+        parse_error: &str,
+    ) -> Result<T, SigningError> {
+        self.check_redirect(&response, index, address)?;
+        let value: serde_json::Value = response.json().await.map_err(|e| {
+            eprintln!("Failed to parse response JSON from {}: {:?}", address, e);
+            self.request_failure(index, address, parse_error, &e)
+        })?;
+
+        if self.strict_signer_responses {
+            schema.validate(&value).map_err(|e| {
+                self.signer_failure(
+                    address,
+                    format!(
+                        "Signer at {} is incompatible with protocol version {}: {}",
+                        address, PROTOCOL_VERSION, e
+                    ),
+                )
+            })?;
+        }
+
+        serde_json::from_value(value).map_err(|e| {
+            eprintln!("Failed to parse response JSON from {}: {:?}", address, e);
+            self.signer_failure(address, parse_error)
+        })
+    }
+
+    /// Fails on a redirect the HTTP client handed back rather than following, which would
+    /// otherwise surface as an unparsable response.
+    fn check_redirect(
+        &self,
+        response: &reqwest::Response,
+        index: usize,
+        address: &str,
+    ) -> Result<(), SigningError> {
+        let status = response.status();
+        if !status.is_redirection() {
+            return Ok(());
+        }
+        let location = response
+            .headers()
+            .get(reqwest::header::LOCATION)
+            .and_then(|location| location.to_str().ok())
+            .unwrap_or("nowhere");
+        Err(self.signer_failure(
+            address,
+            format!(
+                "Signer {} at {} redirected with {} to {}; only 307 and 308 redirects are followed",
+                index, address, status, location
+            ),
+        ))
+    }
+
+    /// Counts an error against the signer at `address` in both the metrics and its health.
+    fn record_signer_error(&self, address: &str) {
+        self.metrics.lock().unwrap().record_signer_error(address);
+        self.health
+            .lock()
+            .unwrap()
+            .entry(address.to_string())
+            .or_default()
+            .record_failure();
+    }
+
+    /// Counts an error against the signer at `address` and turns `message` into a failure.
+    fn signer_failure(&self, address: &str, message: impl Into<String>) -> SigningError {
+        self.record_signer_error(address);
+        SigningError::Signer(SignerFailure {
+            address: address.to_string(),
+            message: message.into(),
+            connection_lost: false,
+        })
+    }
+
+    /// Like [`Self::signer_failure`] for a failed HTTP exchange, but telling a lost connection
+    /// (refused, reset or closed mid-response) apart from a signer that answered badly.
+    fn request_failure(
+        &self,
+        index: usize,
+        address: &str,
+        message: &str,
+        error: &reqwest::Error,
+    ) -> SigningError {
+        if error.is_redirect() {
+            return self.signer_failure(
+                address,
+                format!(
+                    "Signer {} at {} redirected more than {} times",
+                    index,
+                    address,
+                    self.client.max_redirects()
+                ),
+            );
+        }
+        if !is_connection_error(error) {
+            return self.signer_failure(address, message);
+        }
+        self.record_signer_error(address);
+        // A timeout is handled like a lost connection, but reported as what it is
+        let message = match self.signer_timeout {
+            Some(timeout) if error.is_timeout() => format!(
+                "Signer {} at {} timed out after {} ms",
+                index,
+                address,
+                timeout.as_millis()
+            ),
+            _ => format!(
+                "Lost connection to signer at {}: {}",
+                address,
+                root_cause(error)
+            ),
+        };
+        SigningError::Signer(SignerFailure {
+            address: address.to_string(),
+            message,
+            connection_lost: true,
+        })
+    }
+}
+
+/// Runs a ceremony phase, giving up once `deadline` has passed. `None` means it timed out.
+async fn with_deadline(
+    deadline: Option<Duration>,
+    phase: impl Future<Output = Result<(), SigningError>>,
+) -> Option<Result<(), SigningError>> {
+    match deadline {
+        Some(deadline) => tokio::time::timeout(deadline, phase).await.ok(),
+        None => Some(phase.await),
+    }
+}
+
+/// Innermost cause of an error, e.g. the I/O error behind a failed request.
+fn root_cause(error: &dyn std::error::Error) -> String {
+    let mut error = error;
+    while let Some(source) = error.source() {
+        error = source;
+    }
+    error.to_string()
+}
+
+/// First public key that occurs more than once in `pubkeys`.
+fn first_duplicate(pubkeys: &[PublicKey]) -> Option<PublicKey> {
+    let mut seen = HashSet::new();
+    pubkeys.iter().copied().find(|pubkey| !seen.insert(*pubkey))
+}
+
+/// Replaces the keys of participants that have a tweak with `pubkey + tweak·G`, also returning
+/// each such participant's tweak by position.
+fn apply_tweaks(
+    participants: &[Participant],
+    tweaks: &HashMap<PublicKey, Scalar>,
+) -> Result<(Vec<Participant>, HashMap<usize, Scalar>), String> {
+    let mut tweaked = Vec::with_capacity(participants.len());
+    let mut signer_tweaks = HashMap::new();
+    for participant in participants {
+        let mut participant = participant.clone();
+        if let Some(&tweak) = tweaks.get(&participant.public_key) {
+            participant.public_key = tweak_pubkey(participant.public_key, tweak)?;
+            signer_tweaks.insert(participant.position, tweak);
+        }
+        tweaked.push(participant);
+    }
+    Ok((tweaked, signer_tweaks))
+}
+
+/// The participants at `addresses`, repositioned to match the new key order.
+fn retaining_participants(participants: &[Participant], addresses: &[String]) -> Vec<Participant> {
+    repositioned(
+        participants
+            .iter()
+            .filter(|p| addresses.contains(&p.address)),
+    )
+}
+
+/// `participants` with positions following their order.
+pub(crate) fn repositioned<'a>(
+    participants: impl Iterator<Item = &'a Participant>,
+) -> Vec<Participant> {
+    participants
+        .enumerate()
+        .map(|(position, p)| Participant {
+            position,
+            ..p.clone()
+        })
+        .collect()
+}
+
+/// `participants` without the signer at `address`, repositioned to match the new key order.
+fn excluding_participant(participants: &[Participant], address: &str) -> Vec<Participant> {
+    repositioned(participants.iter().filter(|p| p.address != address))
+}
+
+/// Cancels a ceremony that is dropped midway, as hyper drops the `/sign` handler when its
+/// client disconnects, so that the signers don't keep its rounds around. A ceremony that ends,
+/// successfully or not, clears `session_id` first.
+struct AbandonedCeremony {
+    operator: Operator,
+    session_id: Option<String>,
+}
+
+impl Drop for AbandonedCeremony {
+    fn drop(&mut self) {
+        let Some(session_id) = self.session_id.take() else {
+            return;
+        };
+        println!(
+            "🚫 Ceremony {} was abandoned, cancelling it on the signers",
+            session_id
+        );
+        let operator = self.operator.clone();
+        tokio::spawn(async move {
+            // The participants it started with, even if the registered signers changed since
+            if let Some(participants) = operator.take_ceremony(&session_id).await {
+                operator.cancel_on_signers(&session_id, &participants).await;
+            }
+        });
+    }
+}
+
+/// Milliseconds since `start`, resetting it to now for the next phase.
+fn elapsed_ms(start: &mut Instant) -> f64 {
+    let elapsed = start.elapsed().as_secs_f64() * 1000.0;
+    *start = Instant::now();
+    elapsed
+}
+
+/// Compares the signers' final signatures, given in the order of `participants`, returning the
+/// signer whose signature was taken as the reference and those whose signature differs from
+/// it, if any. The reference is the first signature that `verifies`, or the first one if none
+/// does, so that a bad signer that happens to come first doesn't get the others blamed.
+pub(crate) fn divergent_final_signatures(
+    participants: &[Participant],
+    final_signatures: &[CompactSignature],
+    verifies: impl Fn(CompactSignature) -> bool,
+) -> Option<(SignerRef, Vec<SignerRef>)> {
+    let first = final_signatures.first()?;
+    if final_signatures.iter().all(|signature| signature == first) {
+        return None;
+    }
+    let (reference, reference_signature) = participants
+        .iter()
+        .zip(final_signatures)
+        .find(|(_, signature)| verifies(**signature))
+        .unwrap_or((&participants[0], first));
+    let divergent = participants
+        .iter()
+        .zip(final_signatures)
+        .filter(|(_, signature)| *signature != reference_signature)
+        .map(|(participant, _)| SignerRef::from(participant))
+        .collect();
+    Some((SignerRef::from(reference), divergent))
+}
+
+/// The first two participants, in order, that returned the same nonce.
+fn duplicate_nonce(
+    participants: &[Participant],
+    indexed_nonces: &HashMap<usize, Vec<u8>>,
+) -> Option<(SignerRef, SignerRef)> {
+    let mut seen: HashMap<&[u8], &Participant> = HashMap::new();
+    for participant in participants {
+        let Some(nonce) = indexed_nonces.get(&participant.position) else {
+            continue;
+        };
+        if let Some(first) = seen.insert(nonce, participant) {
+            return Some((SignerRef::from(first), SignerRef::from(participant)));
+        }
+    }
+    None
+}
+
+/// Registration indices of the participants without an entry in `indexed`, which is keyed by
+/// position, in the participants' order.
+fn missing_indices<T>(participants: &[Participant], indexed: &HashMap<usize, T>) -> Vec<usize> {
+    participants
+        .iter()
+        .filter(|p| !indexed.contains_key(&p.position))
+        .map(|p| p.index)
+        .collect()
+}
+
+/// Copies the per-signer map without `index`'s own entry.
+///
+/// Signers must receive exactly the n-1 contributions of the others; getting their own back
+/// makes musig2 reject or double count it, so anything else aborts the ceremony.
+fn excluding_signer<T: Clone>(
+    indexed: &HashMap<usize, T>,
+    index: usize,
+) -> Result<HashMap<usize, T>, String> {
+    let mut others = indexed.clone();
+    others.remove(&index);
+
+    if others.contains_key(&index) || others.len() + 1 != indexed.len() {
+        return Err(format!(
+            "Invalid distribution set for signer {}: expected {} entries excluding its own, got {}",
+            index,
+            indexed.len().saturating_sub(1),
+            others.len()
+        ));
+    }
+    Ok(others)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::secp::SECP256K1;
+    use secp256k1::SecretKey;
+
+    fn participant(index: usize) -> Participant {
+        let secret_key = SecretKey::new(&mut rand::thread_rng());
+        Participant {
+            index,
+            position: index,
+            public_key: PublicKey::from_secret_key(&SECP256K1, &secret_key),
+            address: format!("http://127.0.0.1:{}", 8080 + index),
+        }
+    }
+
+    #[test]
+    fn no_final_signatures_have_no_reference() {
+        let participants = vec![participant(0), participant(1)];
+        assert_eq!(
+            divergent_final_signatures(&participants, &[], |_| true),
+            None
+        );
+    }
+
+    #[test]
+    fn divergent_signers_are_named_against_a_verifying_reference() {
+        let participants: Vec<Participant> = (0..3).map(participant).collect();
+        let good = CompactSignature::from_bytes(&[7; 64]).unwrap();
+        let bad = CompactSignature::from_bytes(&[8; 64]).unwrap();
+        assert_eq!(
+            divergent_final_signatures(&participants, &[good, good, good], |_| true),
+            None
+        );
+
+        // The bad signer comes first, but the reference is the first signature that verifies
+        let verifies = |signature| signature == good;
+        assert_eq!(
+            divergent_final_signatures(&participants, &[bad, good, good], verifies),
+            Some((
+                SignerRef::from(&participants[1]),
+                vec![SignerRef::from(&participants[0])]
+            ))
+        );
+        // Without a verifying signature, the first one is the reference
+        assert_eq!(
+            divergent_final_signatures(&participants, &[bad, good, bad], |_| false),
+            Some((
+                SignerRef::from(&participants[0]),
+                vec![SignerRef::from(&participants[1])]
+            ))
+        );
+    }
+
+    #[test]
+    fn shared_nonces_name_the_first_pair() {
+        let participants: Vec<Participant> = (0..4).map(participant).collect();
+        let nonces = |values: [u8; 4]| -> HashMap<usize, Vec<u8>> {
+            values
+                .iter()
+                .enumerate()
+                .map(|(index, value)| (index, vec![*value; 66]))
+                .collect()
+        };
+        assert_eq!(duplicate_nonce(&participants, &nonces([1, 2, 3, 4])), None);
+        assert_eq!(
+            duplicate_nonce(&participants, &nonces([1, 2, 3, 2])),
+            Some((
+                SignerRef::from(&participants[1]),
+                SignerRef::from(&participants[3])
+            ))
+        );
+
+        // Signers without a nonce are skipped rather than compared
+        let mut partial = nonces([1, 2, 1, 2]);
+        partial.remove(&0);
+        assert_eq!(
+            duplicate_nonce(&participants, &partial),
+            Some((
+                SignerRef::from(&participants[1]),
+                SignerRef::from(&participants[3])
+            ))
+        );
+    }
+
+    #[test]
+    fn root_causes_are_the_innermost_error() {
+        #[derive(Debug)]
+        struct Wrapper(std::io::Error);
+        impl std::fmt::Display for Wrapper {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                write!(f, "request failed")
+            }
+        }
+        impl std::error::Error for Wrapper {
+            fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+                Some(&self.0)
+            }
+        }
+
+        let reset = std::io::Error::from(std::io::ErrorKind::ConnectionReset);
+        let expected = reset.to_string();
+        assert_eq!(root_cause(&Wrapper(reset)), expected);
+    }
+
+    #[test]
+    fn repeated_pubkeys_are_found_in_order() {
+        let [a, b, c] = [0, 1, 2].map(|index| participant(index).public_key);
+        assert_eq!(first_duplicate(&[a, b, c]), None);
+        assert_eq!(first_duplicate(&[a, b, c, b, a]), Some(b));
+    }
+
+    #[test]
+    fn missing_contributions_are_listed_by_index() {
+        let participants: Vec<Participant> = (0..4).map(participant).collect();
+        let indexed: HashMap<usize, ()> = [(0, ()), (2, ())].into();
+        assert_eq!(missing_indices(&participants, &indexed), vec![1, 3]);
+
+        let complete: HashMap<usize, ()> = (0..4).map(|index| (index, ())).collect();
+        assert!(missing_indices(&participants, &complete).is_empty());
+
+        // Contributions are keyed by position, but signers are named by registration index
+        let registered_later = Participant {
+            index: 7,
+            ..participants[1].clone()
+        };
+        let participants = [participants[0].clone(), registered_later];
+        let indexed: HashMap<usize, ()> = [(0, ())].into();
+        assert_eq!(missing_indices(&participants, &indexed), vec![7]);
+    }
+
+    #[test]
+    fn excluded_signers_leave_the_rest_repositioned() {
+        let participants: Vec<Participant> = (0..3).map(participant).collect();
+        let remaining = excluding_participant(&participants, &participants[1].address);
+        assert_eq!(
+            remaining,
+            vec![
+                participants[0].clone(),
+                Participant {
+                    position: 1,
+                    ..participants[2].clone()
+                },
+            ]
+        );
+        assert_eq!(remaining[1].index, 2);
+    }
+
+    #[test]
+    fn signers_receive_everyone_elses_contributions() {
+        let indexed: HashMap<usize, &str> = [(0, "a"), (1, "b"), (2, "c")].into();
+        let others = excluding_signer(&indexed, 1).unwrap();
+        assert_eq!(others, [(0, "a"), (2, "c")].into());
+    }
+
+    #[test]
+    fn signers_without_a_contribution_abort_the_distribution() {
+        let indexed: HashMap<usize, &str> = [(0, "a"), (2, "c")].into();
+        assert_eq!(
+            excluding_signer(&indexed, 1),
+            Err(
+                "Invalid distribution set for signer 1: expected 1 entries excluding its own, got 2"
+                    .to_string()
+            )
+        );
+    }
+}
@@ -0,0 +1,187 @@
+use crate::types::{Participant, SignerRegistrationRequest};
+use secp256k1::PublicKey;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+/// Where an operator keeps its registered signers, each under a registration index that stays
+/// with it until it deregisters. Operators sharing a registry, such as one
+/// [`SessionStore`](crate::store::SessionStore) database, see each other's registrations and
+/// can each serve any request.
+pub trait SignerRegistry: Send + Sync {
+    /// Adds a signer under the next registration index and returns it, or `None` if
+    /// `public_key` is already registered: the signer holding it couldn't sign for both entries.
+    /// Indices freed by deregistration aren't reused.
+    fn register(&self, public_key: PublicKey, address: &str) -> Result<Option<usize>, String>;
+
+    /// Removes the signer holding `public_key`, returning its index if there was one.
+    fn deregister(&self, public_key: PublicKey) -> Result<Option<usize>, String>;
+
+    /// Routes the signer holding `public_key` to another address, e.g. that of its standby.
+    fn set_address(&self, public_key: PublicKey, address: &str) -> Result<(), String>;
+
+    /// Replaces all signers, keeping their indices; registration continues after the highest.
+    fn replace(&self, signers: &[Participant]) -> Result<(), String>;
+
+    /// Every registered signer, by registration index and positioned in that order.
+    fn signers(&self) -> Result<Vec<Participant>, String>;
+}
+
+/// Registry private to one operator process.
+#[derive(Default)]
+pub struct MemorySignerRegistry {
+    state: Mutex<MemoryState>,
+}
+
+#[derive(Default)]
+struct MemoryState {
+    signers: BTreeMap<usize, (PublicKey, String)>,
+    next_index: usize,
+}
+
+impl MemorySignerRegistry {
+    /// A registry holding a fixed signer set, indexed in the given order.
+    pub fn with_signers(signers: Vec<SignerRegistrationRequest>) -> Self {
+        let signers: BTreeMap<usize, (PublicKey, String)> = signers
+            .into_iter()
+            .enumerate()
+            .map(|(index, signer)| (index, (signer.public_key, signer.address)))
+            .collect();
+        Self {
+            state: Mutex::new(MemoryState {
+                next_index: signers.len(),
+                signers,
+            }),
+        }
+    }
+}
+
+impl SignerRegistry for MemorySignerRegistry {
+    fn register(&self, public_key: PublicKey, address: &str) -> Result<Option<usize>, String> {
+        let mut state = self.state.lock().unwrap();
+        if state.signers.values().any(|(key, _)| *key == public_key) {
+            return Ok(None);
+        }
+        let index = state.next_index;
+        state.next_index += 1;
+        state
+            .signers
+            .insert(index, (public_key, address.to_string()));
+        Ok(Some(index))
+    }
+
+    fn deregister(&self, public_key: PublicKey) -> Result<Option<usize>, String> {
+        let mut state = self.state.lock().unwrap();
+        let index = state
+            .signers
+            .iter()
+            .find(|(_, (key, _))| *key == public_key)
+            .map(|(index, _)| *index);
+        if let Some(index) = index {
+            state.signers.remove(&index);
+        }
+        Ok(index)
+    }
+
+    fn set_address(&self, public_key: PublicKey, address: &str) -> Result<(), String> {
+        let mut state = self.state.lock().unwrap();
+        let (_, current) = state
+            .signers
+            .values_mut()
+            .find(|(key, _)| *key == public_key)
+            .ok_or_else(|| format!("No registered signer with public key {}", public_key))?;
+        *current = address.to_string();
+        Ok(())
+    }
+
+    fn replace(&self, signers: &[Participant]) -> Result<(), String> {
+        let mut state = self.state.lock().unwrap();
+        state.signers = signers
+            .iter()
+            .map(|p| (p.index, (p.public_key, p.address.clone())))
+            .collect();
+        state.next_index = signers.iter().map(|p| p.index + 1).max().unwrap_or(0);
+        Ok(())
+    }
+
+    fn signers(&self) -> Result<Vec<Participant>, String> {
+        let state = self.state.lock().unwrap();
+        Ok(state
+            .signers
+            .iter()
+            .enumerate()
+            .map(|(position, (index, (public_key, address)))| Participant {
+                index: *index,
+                position,
+                public_key: *public_key,
+                address: address.clone(),
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::secp::SECP256K1;
+    use secp256k1::SecretKey;
+
+    fn public_key() -> PublicKey {
+        PublicKey::from_secret_key(&SECP256K1, &SecretKey::new(&mut rand::thread_rng()))
+    }
+
+    #[test]
+    fn freed_indices_are_not_reused() {
+        let registry = MemorySignerRegistry::default();
+        let keys = [public_key(), public_key(), public_key()];
+        for (index, key) in keys.iter().enumerate() {
+            assert_eq!(registry.register(*key, "http://a"), Ok(Some(index)));
+        }
+        assert_eq!(registry.register(keys[0], "http://b"), Ok(None));
+
+        assert_eq!(registry.deregister(keys[1]), Ok(Some(1)));
+        assert_eq!(registry.deregister(keys[1]), Ok(None));
+        let indices: Vec<usize> = registry
+            .signers()
+            .unwrap()
+            .iter()
+            .map(|p| p.index)
+            .collect();
+        assert_eq!(indices, [0, 2]);
+        assert_eq!(registry.register(public_key(), "http://c"), Ok(Some(3)));
+    }
+
+    #[test]
+    fn replaced_signer_sets_continue_after_their_highest_index() {
+        let registry = MemorySignerRegistry::default();
+        let kept = Participant {
+            index: 5,
+            position: 0,
+            public_key: public_key(),
+            address: "http://a".to_string(),
+        };
+        registry.replace(std::slice::from_ref(&kept)).unwrap();
+        assert_eq!(registry.signers(), Ok(vec![kept]));
+        assert_eq!(registry.register(public_key(), "http://b"), Ok(Some(6)));
+    }
+
+    #[test]
+    fn addresses_are_routed_by_public_key() {
+        let registry = MemorySignerRegistry::default();
+        let keys = [public_key(), public_key()];
+        registry.register(keys[0], "http://a").unwrap();
+        registry.register(keys[1], "http://b").unwrap();
+        registry.deregister(keys[0]).unwrap();
+
+        // The signer's registration index (1) differs from its position among the signers (0)
+        registry.set_address(keys[1], "http://standby").unwrap();
+        let signers = registry.signers().unwrap();
+        assert_eq!((signers[0].index, signers[0].position), (1, 0));
+        assert_eq!(signers[0].address, "http://standby");
+
+        let unknown = public_key();
+        assert_eq!(
+            registry.set_address(unknown, "http://c"),
+            Err(format!("No registered signer with public key {}", unknown))
+        );
+    }
+}
@@ -0,0 +1,81 @@
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+use warp::http::header::{HeaderValue, CONTENT_TYPE};
+use warp::http::StatusCode;
+use warp::reply::Response;
+
+// Process-wide, so that rejection handlers format their replies the same way as the endpoints
+static PRETTY: AtomicBool = AtomicBool::new(false);
+
+/// Makes every JSON reply of this process pretty-printed, e.g. to read responses in a terminal.
+/// Replies are compact by default.
+pub fn set_pretty(pretty: bool) {
+    PRETTY.store(pretty, Ordering::Relaxed);
+}
+
+/// A JSON reply, formatted as chosen with [`set_pretty`]. Used in place of `warp::reply::json`.
+pub struct Json {
+    body: Result<Vec<u8>, serde_json::Error>,
+}
+
+pub fn json<T: Serialize>(value: &T) -> Json {
+    Json {
+        body: to_vec(value, PRETTY.load(Ordering::Relaxed)),
+    }
+}
+
+fn to_vec<T: Serialize>(value: &T, pretty: bool) -> Result<Vec<u8>, serde_json::Error> {
+    if pretty {
+        serde_json::to_vec_pretty(value)
+    } else {
+        serde_json::to_vec(value)
+    }
+}
+
+impl warp::Reply for Json {
+    fn into_response(self) -> Response {
+        match self.body {
+            Ok(body) => {
+                let mut response = Response::new(body.into());
+                response
+                    .headers_mut()
+                    .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+                response
+            }
+            Err(e) => {
+                eprintln!("Failed to serialize reply: {}", e);
+                warp::Reply::into_response(StatusCode::INTERNAL_SERVER_ERROR)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use warp::Reply;
+
+    #[test]
+    fn pretty_replies_only_differ_in_whitespace() {
+        let value = json!({ "session_id": "abc", "signers": [0, 1] });
+        let compact = to_vec(&value, false).unwrap();
+        let pretty = to_vec(&value, true).unwrap();
+        assert_eq!(compact, br#"{"session_id":"abc","signers":[0,1]}"#);
+        assert!(pretty.contains(&b'\n'));
+        let reparsed: serde_json::Value = serde_json::from_slice(&pretty).unwrap();
+        assert_eq!(reparsed, value);
+    }
+
+    #[tokio::test]
+    async fn replies_are_json_responses() {
+        let response = json(&json!({ "valid": true })).into_response();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[CONTENT_TYPE], "application/json");
+        let body = warp::hyper::body::to_bytes(response.into_body())
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body, json!({ "valid": true }));
+    }
+}
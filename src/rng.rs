@@ -0,0 +1,132 @@
+use rand::RngCore;
+
+/// Draws a fresh 32-byte nonce seed from `rng`.
+///
+/// An all-zero seed is refused: it is far more likely to come from a broken or misconfigured
+/// RNG than by chance, and signing with a predictable nonce would leak the secret key.
+pub fn nonce_seed(rng: &mut impl RngCore) -> Result<[u8; 32], String> {
+    let mut seed = [0u8; 32];
+    rng.fill_bytes(&mut seed);
+    if seed == [0u8; 32] {
+        return Err(
+            "RNG produced an all-zero nonce seed; refusing to generate a nonce".to_string(),
+        );
+    }
+    Ok(seed)
+}
+
+/// Most draws `nonce_seed_with_retries` may be configured for. A weak seed in a row more often
+/// than this points at a broken RNG, which retrying won't fix.
+pub const MAX_NONCE_SEED_ATTEMPTS: u32 = 5;
+
+/// Draws a nonce seed like [`nonce_seed`], retrying while the RNG produces weak seeds, up to
+/// `attempts` draws in all (at most [`MAX_NONCE_SEED_ATTEMPTS`]). Every retry is logged.
+pub fn nonce_seed_with_retries(rng: &mut impl RngCore, attempts: u32) -> Result<[u8; 32], String> {
+    let attempts = attempts.clamp(1, MAX_NONCE_SEED_ATTEMPTS);
+    let mut attempt = 1;
+    loop {
+        match nonce_seed(rng) {
+            Ok(seed) => return Ok(seed),
+            Err(e) if attempt < attempts => {
+                eprintln!(
+                    "⚠️ Weak nonce seed on attempt {}/{}, drawing again: {}",
+                    attempt, attempts, e
+                );
+                attempt += 1;
+            }
+            Err(e) => {
+                return Err(format!(
+                    "{} (all {} attempts produced weak seeds)",
+                    e, attempts
+                ))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An RNG filling its first `zero_draws` buffers with zeros, then with a fixed byte.
+    struct ScriptedRng {
+        zero_draws: usize,
+    }
+
+    impl RngCore for ScriptedRng {
+        fn next_u32(&mut self) -> u32 {
+            let mut bytes = [0; 4];
+            self.fill_bytes(&mut bytes);
+            u32::from_le_bytes(bytes)
+        }
+
+        fn next_u64(&mut self) -> u64 {
+            let mut bytes = [0; 8];
+            self.fill_bytes(&mut bytes);
+            u64::from_le_bytes(bytes)
+        }
+
+        fn fill_bytes(&mut self, dest: &mut [u8]) {
+            if self.zero_draws > 0 {
+                self.zero_draws -= 1;
+                dest.fill(0);
+            } else {
+                dest.fill(0x42);
+            }
+        }
+
+        fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+            self.fill_bytes(dest);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn all_zero_seeds_are_refused() {
+        let mut dead = ScriptedRng {
+            zero_draws: usize::MAX,
+        };
+        assert_eq!(
+            nonce_seed(&mut dead),
+            Err("RNG produced an all-zero nonce seed; refusing to generate a nonce".to_string())
+        );
+        let mut working = ScriptedRng { zero_draws: 0 };
+        assert_eq!(nonce_seed(&mut working), Ok([0x42; 32]));
+    }
+
+    #[test]
+    fn seeds_from_the_thread_rng_are_fresh() {
+        let mut rng = rand::thread_rng();
+        assert_ne!(nonce_seed(&mut rng).unwrap(), nonce_seed(&mut rng).unwrap());
+    }
+
+    #[test]
+    fn weak_seeds_are_drawn_again_until_attempts_run_out() {
+        let mut flaky = ScriptedRng { zero_draws: 2 };
+        assert_eq!(nonce_seed_with_retries(&mut flaky, 3), Ok([0x42; 32]));
+
+        let mut flaky = ScriptedRng { zero_draws: 3 };
+        assert_eq!(
+            nonce_seed_with_retries(&mut flaky, 3),
+            Err(
+                "RNG produced an all-zero nonce seed; refusing to generate a nonce \
+                 (all 3 attempts produced weak seeds)"
+                    .to_string()
+            )
+        );
+        // Once the RNG recovers, the next nonce gets a seed again
+        assert_eq!(nonce_seed_with_retries(&mut flaky, 3), Ok([0x42; 32]));
+    }
+
+    #[test]
+    fn attempts_are_capped() {
+        let mut flaky = ScriptedRng {
+            zero_draws: MAX_NONCE_SEED_ATTEMPTS as usize,
+        };
+        assert!(nonce_seed_with_retries(&mut flaky, 100)
+            .unwrap_err()
+            .contains("all 5 attempts"));
+        let mut flaky = ScriptedRng { zero_draws: 1 };
+        assert!(nonce_seed_with_retries(&mut flaky, 0).is_err());
+    }
+}
@@ -0,0 +1,26 @@
+use std::num::NonZeroUsize;
+use tokio::runtime::{Builder, Runtime};
+
+/// Builds the multi-threaded runtime the binaries run on.
+///
+/// Without `worker_threads`, tokio's default of one worker per CPU core is used, matching
+/// `#[tokio::main]`. Signing is CPU-bound, so nodes sharing a host may want fewer.
+pub fn build_runtime(worker_threads: Option<NonZeroUsize>) -> std::io::Result<Runtime> {
+    let mut builder = Builder::new_multi_thread();
+    if let Some(worker_threads) = worker_threads {
+        builder.worker_threads(worker_threads.get());
+    }
+    builder.enable_all().build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn worker_threads_are_configurable() {
+        let runtime = build_runtime(NonZeroUsize::new(2)).unwrap();
+        assert_eq!(runtime.metrics().num_workers(), 2);
+        assert_eq!(runtime.block_on(async { 1 + 1 }), 2);
+    }
+}
@@ -0,0 +1,85 @@
+use serde_json::Value;
+
+/// Version of the operator-signer protocol the schemas below describe.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Expected shape of a signer response.
+pub enum ResponseSchema {
+    /// A JSON array of bytes
+    Bytes,
+    /// A JSON object with exactly these fields
+    Object(&'static [&'static str]),
+}
+
+/// `POST /nonce`: the serialized public nonce.
+pub const NONCE_RESPONSE: ResponseSchema = ResponseSchema::Bytes;
+/// `PUT /nonces`: see [`crate::types::ReceiveNoncesResponse`].
+pub const RECEIVE_NONCES_RESPONSE: ResponseSchema =
+    ResponseSchema::Object(&["partial_signature", "message_hash"]);
+/// `PUT /partial-signatures`: see [`crate::types::ReceivePartialSignaturesResponse`].
+pub const RECEIVE_PARTIAL_SIGNATURES_RESPONSE: ResponseSchema =
+    ResponseSchema::Object(&["final_signature"]);
+
+impl ResponseSchema {
+    /// Checks `value` against the schema, naming the first mismatch.
+    pub fn validate(&self, value: &Value) -> Result<(), String> {
+        match self {
+            ResponseSchema::Bytes => {
+                let items = value.as_array().ok_or("expected an array of bytes")?;
+                if items
+                    .iter()
+                    .any(|item| item.as_u64().is_none_or(|b| b > 255))
+                {
+                    return Err("expected an array of bytes".to_string());
+                }
+                Ok(())
+            }
+            ResponseSchema::Object(fields) => {
+                let object = value.as_object().ok_or("expected an object")?;
+                if let Some(field) = fields.iter().find(|f| !object.contains_key(**f)) {
+                    return Err(format!("missing field `{}`", field));
+                }
+                if let Some(field) = object.keys().find(|k| !fields.contains(&k.as_str())) {
+                    return Err(format!("unexpected field `{}`", field));
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn byte_arrays_must_hold_bytes_only() {
+        assert_eq!(NONCE_RESPONSE.validate(&json!([0, 1, 255])), Ok(()));
+        for value in [json!([0, 256]), json!([-1]), json!("00ff"), json!([0.5])] {
+            assert_eq!(
+                NONCE_RESPONSE.validate(&value),
+                Err("expected an array of bytes".to_string())
+            );
+        }
+    }
+
+    #[test]
+    fn objects_must_have_exactly_the_schema_fields() {
+        let schema = &RECEIVE_NONCES_RESPONSE;
+        let valid = json!({ "partial_signature": [], "message_hash": "" });
+        assert_eq!(schema.validate(&valid), Ok(()));
+        assert_eq!(
+            schema.validate(&json!({ "partial_signature": [] })),
+            Err("missing field `message_hash`".to_string())
+        );
+        assert_eq!(
+            schema.validate(&json!({ "partial_signature": [], "message_hash": "", "v": 2 })),
+            Err("unexpected field `v`".to_string())
+        );
+        assert_eq!(
+            schema.validate(&json!([])),
+            Err("expected an object".to_string())
+        );
+    }
+}
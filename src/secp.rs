@@ -0,0 +1,56 @@
+use musig2::CompactSignature;
+use once_cell::sync::Lazy;
+use secp256k1::{schnorr, All, PublicKey, Secp256k1};
+
+/// Shared secp256k1 context used for key derivation and for verifying final signatures across
+/// the crate.
+///
+/// Building a context allocates and randomizes precomputation tables, so every node in a
+/// process reuses this one instead of calling `Secp256k1::new()`. Partial signatures and batches
+/// are verified by `musig2`, which doesn't take a context.
+pub static SECP256K1: Lazy<Secp256k1<All>> = Lazy::new(Secp256k1::new);
+
+/// Verifies a BIP340 signature under the x-only form of `pubkey` with the shared context, like
+/// `musig2::verify_single` does.
+pub fn verify_schnorr(
+    pubkey: PublicKey,
+    signature: CompactSignature,
+    message: &[u8],
+) -> Result<(), secp256k1::Error> {
+    let signature = schnorr::Signature::from_byte_array(signature.serialize());
+    SECP256K1.verify_schnorr(&signature, message, &pubkey.x_only_public_key().0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use secp256k1::{Keypair, SecretKey};
+
+    #[test]
+    fn shared_context_derives_the_same_keys_as_its_own_context() {
+        let own_context = Secp256k1::new();
+        for _ in 0..8 {
+            let secret_key = SecretKey::new(&mut rand::thread_rng());
+            assert_eq!(
+                PublicKey::from_secret_key(&SECP256K1, &secret_key),
+                PublicKey::from_secret_key(&own_context, &secret_key)
+            );
+        }
+    }
+
+    #[test]
+    fn verification_agrees_with_musig2() {
+        let keypair = Keypair::new(&SECP256K1, &mut rand::thread_rng());
+        let pubkey = keypair.public_key();
+        let message = b"shared context";
+        let signature = SECP256K1.sign_schnorr_no_aux_rand(message, &keypair);
+        let signature = CompactSignature::from_bytes(&signature.serialize()).unwrap();
+
+        assert!(verify_schnorr(pubkey, signature, message).is_ok());
+        assert!(musig2::verify_single(pubkey, signature, message).is_ok());
+
+        let other = b"another message";
+        assert!(verify_schnorr(pubkey, signature, other).is_err());
+        assert!(musig2::verify_single(pubkey, signature, other).is_err());
+    }
+}
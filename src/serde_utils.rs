@@ -1,6 +1,17 @@
-use musig2::{CompactSignature, KeyAggContext, PartialSignature};
-use secp256k1::PublicKey;
-use std::collections::HashMap;
+use crate::error::Error;
+use crate::types::{SignatureEncoding, TaprootCommitment};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use musig2::secp::Scalar;
+use musig2::{CompactSignature, KeyAggContext, PartialSignature, PubNonce};
+use secp256k1::{Parity, PublicKey, XOnlyPublicKey};
+use std::collections::{BTreeMap, HashMap};
+
+/// Parses a hex-encoded compressed public key.
+pub fn public_key_from_hex(s: &str) -> Result<PublicKey, Error> {
+    let bytes = hex::decode(s)?;
+    Ok(PublicKey::from_slice(&bytes)?)
+}
 
 pub fn serialize_public_key<S>(key: &PublicKey, serializer: S) -> Result<S::Ok, S::Error>
 where
@@ -14,8 +25,7 @@ where
     D: serde::Deserializer<'de>,
 {
     let s: String = serde::Deserialize::deserialize(deserializer)?;
-    let bytes = hex::decode(s).map_err(serde::de::Error::custom)?;
-    PublicKey::from_slice(&bytes).map_err(serde::de::Error::custom)
+    public_key_from_hex(&s).map_err(serde::de::Error::custom)
 }
 
 pub fn serialize_pubkey_map<S>(
@@ -42,8 +52,7 @@ where
     let string_map: HashMap<String, Vec<u8>> = serde::Deserialize::deserialize(deserializer)?;
     let mut result = HashMap::new();
     for (k, v) in string_map {
-        let bytes = hex::decode(k).map_err(serde::de::Error::custom)?;
-        let pubkey = PublicKey::from_slice(&bytes).map_err(serde::de::Error::custom)?;
+        let pubkey = public_key_from_hex(&k).map_err(serde::de::Error::custom)?;
         result.insert(pubkey, v);
     }
     Ok(result)
@@ -65,6 +74,114 @@ where
     KeyAggContext::from_bytes(&bytes).map_err(serde::de::Error::custom)
 }
 
+pub fn serialize_optional_key_agg_ctx<S>(
+    ctx: &Option<KeyAggContext>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    match ctx {
+        Some(ctx) => serializer.serialize_some(&ctx.serialize()),
+        None => serializer.serialize_none(),
+    }
+}
+
+pub fn deserialize_optional_key_agg_ctx<'de, D>(
+    deserializer: D,
+) -> Result<Option<KeyAggContext>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let bytes: Option<Vec<u8>> = serde::Deserialize::deserialize(deserializer)?;
+    bytes
+        .map(|bytes| KeyAggContext::from_bytes(&bytes).map_err(serde::de::Error::custom))
+        .transpose()
+}
+
+/// Encodes an aggregated key for clients: 33-byte compressed, or 32-byte x-only (BIP340) with
+/// the `xonly-pubkeys` feature. Signer keys are always compressed, since key aggregation
+/// depends on their parity.
+fn aggregated_pubkey_to_hex(key: &PublicKey) -> String {
+    if cfg!(feature = "xonly-pubkeys") {
+        hex::encode(key.x_only_public_key().0.serialize())
+    } else {
+        hex::encode(key.serialize())
+    }
+}
+
+/// Parses an aggregated key in either form; an x-only key is taken to have even parity.
+fn aggregated_pubkey_from_hex(s: &str) -> Result<PublicKey, Error> {
+    let bytes = hex::decode(s)?;
+    if bytes.len() == 32 {
+        return Ok(XOnlyPublicKey::from_slice(&bytes)?.public_key(Parity::Even));
+    }
+    Ok(PublicKey::from_slice(&bytes)?)
+}
+
+pub fn serialize_aggregated_pubkey<S>(key: &PublicKey, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    serializer.serialize_str(&aggregated_pubkey_to_hex(key))
+}
+
+pub fn deserialize_aggregated_pubkey<'de, D>(deserializer: D) -> Result<PublicKey, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let s: String = serde::Deserialize::deserialize(deserializer)?;
+    aggregated_pubkey_from_hex(&s).map_err(serde::de::Error::custom)
+}
+
+pub fn serialize_optional_aggregated_pubkey<S>(
+    key: &Option<PublicKey>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    match key {
+        Some(key) => serializer.serialize_some(&aggregated_pubkey_to_hex(key)),
+        None => serializer.serialize_none(),
+    }
+}
+
+pub fn deserialize_optional_aggregated_pubkey<'de, D>(
+    deserializer: D,
+) -> Result<Option<PublicKey>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let s: Option<String> = serde::Deserialize::deserialize(deserializer)?;
+    s.map(|s| aggregated_pubkey_from_hex(&s).map_err(serde::de::Error::custom))
+        .transpose()
+}
+
+pub fn serialize_optional_public_key<S>(
+    key: &Option<PublicKey>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    match key {
+        Some(key) => serializer.serialize_some(&hex::encode(key.serialize())),
+        None => serializer.serialize_none(),
+    }
+}
+
+pub fn deserialize_optional_public_key<'de, D>(
+    deserializer: D,
+) -> Result<Option<PublicKey>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let s: Option<String> = serde::Deserialize::deserialize(deserializer)?;
+    s.map(|s| public_key_from_hex(&s).map_err(serde::de::Error::custom))
+        .transpose()
+}
+
 pub fn serialize_partial_signature<S>(
     sig: &PartialSignature,
     serializer: S,
@@ -102,6 +219,64 @@ where
     CompactSignature::from_bytes(&bytes).map_err(serde::de::Error::custom)
 }
 
+pub fn serialize_optional_compact_signature<S>(
+    sig: &Option<CompactSignature>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    match sig {
+        Some(sig) => serializer.serialize_some(&hex::encode(sig.serialize())),
+        None => serializer.serialize_none(),
+    }
+}
+
+pub fn deserialize_optional_compact_signature<'de, D>(
+    deserializer: D,
+) -> Result<Option<CompactSignature>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let s: Option<String> = serde::Deserialize::deserialize(deserializer)?;
+    s.map(|s| {
+        let bytes = hex::decode(s).map_err(serde::de::Error::custom)?;
+        CompactSignature::from_bytes(&bytes).map_err(serde::de::Error::custom)
+    })
+    .transpose()
+}
+
+/// Encodes a signature as a JSON value in the given encoding.
+pub fn encode_signature(sig: &CompactSignature, encoding: SignatureEncoding) -> serde_json::Value {
+    let bytes = sig.serialize();
+    match encoding {
+        SignatureEncoding::Hex => hex::encode(bytes).into(),
+        SignatureEncoding::Base64 => BASE64.encode(bytes).into(),
+        SignatureEncoding::Bytes => bytes.to_vec().into(),
+    }
+}
+
+/// Decodes a signature produced by [`encode_signature`] with the same encoding.
+pub fn decode_signature(
+    value: &serde_json::Value,
+    encoding: SignatureEncoding,
+) -> Result<CompactSignature, String> {
+    let bytes = match encoding {
+        SignatureEncoding::Hex => {
+            let s = value.as_str().ok_or("expected a hex string")?;
+            hex::decode(s).map_err(|e| e.to_string())?
+        }
+        SignatureEncoding::Base64 => {
+            let s = value.as_str().ok_or("expected a base64 string")?;
+            BASE64.decode(s).map_err(|e| e.to_string())?
+        }
+        SignatureEncoding::Bytes => {
+            serde_json::from_value(value.clone()).map_err(|e| e.to_string())?
+        }
+    };
+    CompactSignature::from_bytes(&bytes).map_err(|e| e.to_string())
+}
+
 pub fn serialize_partial_sig_map<S>(
     map: &HashMap<usize, PartialSignature>,
     serializer: S,
@@ -131,3 +306,250 @@ where
     }
     Ok(result)
 }
+
+pub fn serialize_nonce_map<S>(
+    map: &BTreeMap<usize, PubNonce>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    use serde::ser::SerializeMap;
+    let mut map_ser = serializer.serialize_map(Some(map.len()))?;
+    for (k, v) in map {
+        map_ser.serialize_entry(k, &hex::encode(v.serialize()))?;
+    }
+    map_ser.end()
+}
+
+pub fn deserialize_nonce_map<'de, D>(deserializer: D) -> Result<BTreeMap<usize, PubNonce>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let hex_map: BTreeMap<usize, String> = serde::Deserialize::deserialize(deserializer)?;
+    let mut result = BTreeMap::new();
+    for (k, v) in hex_map {
+        let bytes = hex::decode(&v).map_err(serde::de::Error::custom)?;
+        let nonce = PubNonce::from_bytes(&bytes).map_err(serde::de::Error::custom)?;
+        result.insert(k, nonce);
+    }
+    Ok(result)
+}
+
+/// Parses a hex-encoded 32-byte non-zero scalar, such as a tweak.
+pub fn scalar_from_hex(s: &str) -> Result<Scalar, String> {
+    let bytes = hex::decode(s).map_err(|e| e.to_string())?;
+    Scalar::from_slice(&bytes).map_err(|e| e.to_string())
+}
+
+pub fn serialize_tweak_map<S>(
+    map: &HashMap<PublicKey, Scalar>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    use serde::ser::SerializeMap;
+    let mut map_ser = serializer.serialize_map(Some(map.len()))?;
+    for (k, v) in map {
+        map_ser.serialize_entry(&hex::encode(k.serialize()), &hex::encode(v.serialize()))?;
+    }
+    map_ser.end()
+}
+
+pub fn deserialize_tweak_map<'de, D>(
+    deserializer: D,
+) -> Result<HashMap<PublicKey, Scalar>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let string_map: HashMap<String, String> = serde::Deserialize::deserialize(deserializer)?;
+    let mut result = HashMap::new();
+    for (k, v) in string_map {
+        let pubkey = public_key_from_hex(&k).map_err(serde::de::Error::custom)?;
+        let tweak = scalar_from_hex(&v).map_err(serde::de::Error::custom)?;
+        result.insert(pubkey, tweak);
+    }
+    Ok(result)
+}
+
+pub fn serialize_optional_scalar<S>(
+    scalar: &Option<Scalar>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    match scalar {
+        Some(scalar) => serializer.serialize_some(&hex::encode(scalar.serialize())),
+        None => serializer.serialize_none(),
+    }
+}
+
+pub fn deserialize_optional_scalar<'de, D>(deserializer: D) -> Result<Option<Scalar>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let s: Option<String> = serde::Deserialize::deserialize(deserializer)?;
+    s.map(|s| scalar_from_hex(&s).map_err(serde::de::Error::custom))
+        .transpose()
+}
+
+pub fn serialize_scalar<S>(scalar: &Scalar, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    serializer.serialize_str(&hex::encode(scalar.serialize()))
+}
+
+pub fn deserialize_scalar<'de, D>(deserializer: D) -> Result<Scalar, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let s: String = serde::Deserialize::deserialize(deserializer)?;
+    scalar_from_hex(&s).map_err(serde::de::Error::custom)
+}
+
+pub fn serialize_xonly_public_key<S>(key: &XOnlyPublicKey, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    serializer.serialize_str(&hex::encode(key.serialize()))
+}
+
+pub fn deserialize_xonly_public_key<'de, D>(deserializer: D) -> Result<XOnlyPublicKey, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let s: String = serde::Deserialize::deserialize(deserializer)?;
+    let bytes = hex::decode(&s).map_err(serde::de::Error::custom)?;
+    XOnlyPublicKey::from_slice(&bytes).map_err(serde::de::Error::custom)
+}
+
+/// Serializes an optional Taproot commitment as the hex merkle root, or an empty string for a
+/// key-path-only output.
+pub fn serialize_optional_taproot_commitment<S>(
+    commitment: &Option<TaprootCommitment>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    match commitment {
+        Some(TaprootCommitment::KeyPathOnly) => serializer.serialize_some(""),
+        Some(TaprootCommitment::ScriptTree(merkle_root)) => {
+            serializer.serialize_some(&hex::encode(merkle_root))
+        }
+        None => serializer.serialize_none(),
+    }
+}
+
+pub fn deserialize_optional_taproot_commitment<'de, D>(
+    deserializer: D,
+) -> Result<Option<TaprootCommitment>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let s: Option<String> = serde::Deserialize::deserialize(deserializer)?;
+    s.map(|s| {
+        if s.is_empty() {
+            return Ok(TaprootCommitment::KeyPathOnly);
+        }
+        let bytes = hex::decode(&s).map_err(serde::de::Error::custom)?;
+        <[u8; 32]>::try_from(bytes.as_slice())
+            .map(TaprootCommitment::ScriptTree)
+            .map_err(|_| serde::de::Error::custom("expected a 32-byte merkle root or \"\""))
+    })
+    .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::secp::SECP256K1;
+    use secp256k1::SecretKey;
+
+    #[test]
+    fn public_keys_round_trip_through_hex() {
+        let key = PublicKey::from_secret_key(&SECP256K1, &SecretKey::new(&mut rand::thread_rng()));
+        let decoded = public_key_from_hex(&hex::encode(key.serialize())).unwrap();
+        assert_eq!(decoded, key);
+    }
+
+    #[test]
+    fn malformed_public_keys_are_typed_errors() {
+        assert!(matches!(public_key_from_hex("not hex"), Err(Error::Hex(_))));
+        // A 33-byte string whose prefix is neither 02 nor 03
+        let bad_prefix = format!("05{}", "11".repeat(32));
+        assert!(matches!(
+            public_key_from_hex(&bad_prefix),
+            Err(Error::Secp256k1(_))
+        ));
+        assert!(matches!(
+            public_key_from_hex("02"),
+            Err(Error::Secp256k1(_))
+        ));
+    }
+
+    #[test]
+    fn aggregated_keys_accept_both_forms() {
+        let key = PublicKey::from_secret_key(&SECP256K1, &SecretKey::new(&mut rand::thread_rng()));
+        let (x_only, _) = key.x_only_public_key();
+        assert_eq!(
+            aggregated_pubkey_from_hex(&hex::encode(key.serialize())).unwrap(),
+            key
+        );
+        assert_eq!(
+            aggregated_pubkey_from_hex(&hex::encode(x_only.serialize())).unwrap(),
+            x_only.public_key(Parity::Even)
+        );
+        // Not an x coordinate on the curve
+        assert!(matches!(
+            aggregated_pubkey_from_hex(&"ff".repeat(32)),
+            Err(Error::Secp256k1(_))
+        ));
+    }
+
+    #[test]
+    fn aggregated_keys_are_encoded_as_the_feature_selects() {
+        let key = PublicKey::from_secret_key(&SECP256K1, &SecretKey::new(&mut rand::thread_rng()));
+        let encoded = aggregated_pubkey_to_hex(&key);
+        if cfg!(feature = "xonly-pubkeys") {
+            assert_eq!(encoded, hex::encode(key.x_only_public_key().0.serialize()));
+        } else {
+            assert_eq!(encoded, hex::encode(key.serialize()));
+        }
+        // Either form reads back as the same x coordinate
+        let decoded = aggregated_pubkey_from_hex(&encoded).unwrap();
+        assert_eq!(decoded.x_only_public_key().0, key.x_only_public_key().0);
+    }
+
+    #[test]
+    fn signatures_round_trip_through_every_encoding() {
+        let signature = CompactSignature::from_bytes(&[7; 64]).unwrap();
+        for encoding in [
+            SignatureEncoding::Hex,
+            SignatureEncoding::Base64,
+            SignatureEncoding::Bytes,
+        ] {
+            let encoded = encode_signature(&signature, encoding);
+            assert_eq!(decode_signature(&encoded, encoding), Ok(signature));
+        }
+        assert_eq!(
+            encode_signature(&signature, SignatureEncoding::Hex),
+            serde_json::json!("07".repeat(64))
+        );
+    }
+
+    #[test]
+    fn signatures_in_the_wrong_encoding_are_rejected() {
+        let signature = CompactSignature::from_bytes(&[7; 64]).unwrap();
+        let bytes = encode_signature(&signature, SignatureEncoding::Bytes);
+        assert_eq!(
+            decode_signature(&bytes, SignatureEncoding::Hex),
+            Err("expected a hex string".to_string())
+        );
+        let base64 = encode_signature(&signature, SignatureEncoding::Base64);
+        assert!(decode_signature(&base64, SignatureEncoding::Hex).is_err());
+    }
+}
@@ -0,0 +1,223 @@
+use crate::types::{MessageEncoding, SigningDomain, TaprootCommitment};
+use musig2::secp::Scalar;
+use secp256k1::PublicKey;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+const SESSION_ID_TAG: &[u8] = b"musig2-example/session-id";
+
+/// Prefix of messages signed under Bitcoin's `signmessage` convention.
+const BITCOIN_MESSAGE_PREFIX: &[u8] = b"\x18Bitcoin Signed Message:\n";
+
+/// Derives a session ID from everything that determines the signature: the participant set,
+/// the message, the individual tweaks and the Taproot commitment, plus an attempt counter.
+///
+/// Participants and tweaks are sorted first, so the ID doesn't depend on registration order.
+/// Identical requests map to the same ID, while bumping `attempt` yields a fresh one for a retry.
+pub fn deterministic_session_id(
+    pubkeys: &[PublicKey],
+    message: &[u8],
+    tweaks: &HashMap<PublicKey, Scalar>,
+    taproot: Option<&TaprootCommitment>,
+    attempt: u32,
+) -> String {
+    let mut sorted: Vec<[u8; 33]> = pubkeys.iter().map(|pk| pk.serialize()).collect();
+    sorted.sort();
+    let mut sorted_tweaks: Vec<([u8; 33], [u8; 32])> = tweaks
+        .iter()
+        .map(|(pk, tweak)| (pk.serialize(), tweak.serialize()))
+        .collect();
+    sorted_tweaks.sort();
+
+    let mut hasher = Sha256::new();
+    hasher.update(SESSION_ID_TAG);
+    hasher.update((sorted.len() as u32).to_be_bytes());
+    for pubkey in &sorted {
+        hasher.update(pubkey);
+    }
+    hasher.update((message.len() as u64).to_be_bytes());
+    hasher.update(message);
+    hasher.update((sorted_tweaks.len() as u32).to_be_bytes());
+    for (pubkey, tweak) in &sorted_tweaks {
+        hasher.update(pubkey);
+        hasher.update(tweak);
+    }
+    match taproot {
+        None => hasher.update([0]),
+        Some(TaprootCommitment::KeyPathOnly) => hasher.update([1]),
+        Some(TaprootCommitment::ScriptTree(merkle_root)) => {
+            hasher.update([2]);
+            hasher.update(merkle_root);
+        }
+    }
+    hasher.update(attempt.to_be_bytes());
+    hex::encode(hasher.finalize())
+}
+
+/// The bytes actually signed for `message`: decoded according to `encoding`, then mapped into
+/// `domain`. Operator and signers both derive them here, so they can't disagree on the digest.
+pub fn signed_bytes(
+    message: &str,
+    encoding: MessageEncoding,
+    domain: SigningDomain,
+) -> Result<Vec<u8>, String> {
+    let message = encoding.decode(message)?;
+    Ok(match domain {
+        SigningDomain::Raw => message,
+        SigningDomain::BitcoinMessage => bitcoin_message_digest(&message).to_vec(),
+    })
+}
+
+/// Double SHA-256 of the `signmessage` prefix, the message length as a compact size and the
+/// message itself.
+fn bitcoin_message_digest(message: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(BITCOIN_MESSAGE_PREFIX);
+    hasher.update(compact_size(message.len() as u64));
+    hasher.update(message);
+    Sha256::digest(hasher.finalize()).into()
+}
+
+/// Bitcoin's variable-length integer encoding.
+fn compact_size(n: u64) -> Vec<u8> {
+    match n {
+        0..=0xfc => vec![n as u8],
+        0xfd..=0xffff => [&[0xfd][..], &(n as u16).to_le_bytes()].concat(),
+        0x1_0000..=0xffff_ffff => [&[0xfe][..], &(n as u32).to_le_bytes()].concat(),
+        _ => [&[0xff][..], &n.to_le_bytes()].concat(),
+    }
+}
+
+/// Hex-encoded SHA-256 of a message, reported by signers to show what they actually signed.
+pub fn message_hash(message: &[u8]) -> String {
+    hex::encode(Sha256::digest(message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::secp::SECP256K1;
+    use secp256k1::SecretKey;
+
+    fn pubkeys(count: usize) -> Vec<PublicKey> {
+        (0..count)
+            .map(|_| {
+                PublicKey::from_secret_key(&SECP256K1, &SecretKey::new(&mut rand::thread_rng()))
+            })
+            .collect()
+    }
+
+    fn id(pubkeys: &[PublicKey], message: &[u8], attempt: u32) -> String {
+        deterministic_session_id(pubkeys, message, &HashMap::new(), None, attempt)
+    }
+
+    #[test]
+    fn session_ids_ignore_participant_order() {
+        let keys = pubkeys(3);
+        let mut reversed = keys.clone();
+        reversed.reverse();
+        assert_eq!(id(&keys, b"hello", 0), id(&reversed, b"hello", 0));
+    }
+
+    #[test]
+    fn session_ids_change_with_every_input() {
+        let keys = pubkeys(3);
+        let session_id = id(&keys, b"hello", 0);
+        assert_eq!(session_id.len(), 64);
+        assert_ne!(session_id, id(&keys, b"hello", 1));
+        assert_ne!(session_id, id(&keys, b"hellp", 0));
+        assert_ne!(session_id, id(&keys[..2], b"hello", 0));
+        assert_ne!(session_id, id(&pubkeys(3), b"hello", 0));
+    }
+
+    #[test]
+    fn session_ids_cover_tweaks_and_the_taproot_commitment() {
+        let keys = pubkeys(2);
+        let untweaked = HashMap::new();
+        let plain = deterministic_session_id(&keys, b"hello", &untweaked, None, 0);
+
+        let tweaks = HashMap::from([(keys[0], Scalar::one())]);
+        let tweaked = deterministic_session_id(&keys, b"hello", &tweaks, None, 0);
+        assert_ne!(plain, tweaked);
+        let other_key = HashMap::from([(keys[1], Scalar::one())]);
+        assert_ne!(
+            tweaked,
+            deterministic_session_id(&keys, b"hello", &other_key, None, 0)
+        );
+
+        let commitments = [
+            TaprootCommitment::KeyPathOnly,
+            TaprootCommitment::ScriptTree([0; 32]),
+            TaprootCommitment::ScriptTree([1; 32]),
+        ];
+        let mut ids: Vec<String> = commitments
+            .iter()
+            .map(|commitment| {
+                deterministic_session_id(&keys, b"hello", &untweaked, Some(commitment), 0)
+            })
+            .collect();
+        ids.push(plain);
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids.len(), 4);
+    }
+
+    #[test]
+    fn compact_sizes_grow_at_bitcoins_boundaries() {
+        assert_eq!(compact_size(0xfc), vec![0xfc]);
+        assert_eq!(compact_size(0xfd), vec![0xfd, 0xfd, 0x00]);
+        assert_eq!(compact_size(0x1_0000), vec![0xfe, 0x00, 0x00, 0x01, 0x00]);
+        assert_eq!(compact_size(0x1_0000_0000).len(), 9);
+    }
+
+    #[test]
+    fn bitcoin_messages_are_signed_by_their_signmessage_digest() {
+        let signed = signed_bytes(
+            "hello",
+            MessageEncoding::Utf8,
+            SigningDomain::BitcoinMessage,
+        );
+        assert_eq!(
+            signed.map(hex::encode),
+            Ok("cf0447ec85f0ce7150a257db32ebfcb7523dae17c36dbd1be598779fec0484f4".to_string())
+        );
+        assert_eq!(
+            signed_bytes("hello", MessageEncoding::Utf8, SigningDomain::Raw),
+            Ok(b"hello".to_vec())
+        );
+    }
+
+    #[test]
+    fn hex_messages_round_trip_to_their_bytes() {
+        let bytes = vec![0x00, 0x01, 0xab, 0xff];
+        for message in [hex::encode(&bytes), hex::encode_upper(&bytes)] {
+            assert_eq!(
+                signed_bytes(&message, MessageEncoding::Hex, SigningDomain::Raw),
+                Ok(bytes.clone())
+            );
+        }
+        // The digest covers the decoded bytes, not the hex string
+        assert_eq!(
+            signed_bytes(
+                "68656c6c6f",
+                MessageEncoding::Hex,
+                SigningDomain::BitcoinMessage
+            ),
+            signed_bytes(
+                "hello",
+                MessageEncoding::Utf8,
+                SigningDomain::BitcoinMessage
+            )
+        );
+
+        assert_eq!(
+            signed_bytes("abc", MessageEncoding::Hex, SigningDomain::Raw),
+            Err("Message is not valid hex: Odd number of digits".to_string())
+        );
+        assert!(
+            signed_bytes("zz", MessageEncoding::Hex, SigningDomain::BitcoinMessage)
+                .unwrap_err()
+                .starts_with("Message is not valid hex: ")
+        );
+    }
+}
@@ -0,0 +1,1063 @@
+use crate::admin::admin_auth;
+use crate::auth::{authenticated_json, operator_auth};
+use crate::backend::PartialSigner;
+use crate::client::HttpClient;
+use crate::error::{handle_rejection, CustomError as SignerError, Declined};
+use crate::key_agg::{check_context_pubkeys, rebuild_key_agg_ctx, tweak_pubkey};
+use crate::reply;
+use crate::rng::nonce_seed_with_retries;
+use crate::secp::verify_schnorr;
+use crate::session::{message_hash, signed_bytes};
+#[cfg(feature = "fault-injection")]
+use crate::types::FaultRequest;
+use crate::types::{
+    ActivateResponse, ClearSessionsResponse, DeclineRequest, Fault, FinalSignatureRequest,
+    GenerateNonceRequest, HealthResponse, ReceiveNoncesRequest, ReceiveNoncesResponse,
+    ReceivePartialSignaturesRequest, ReceivePartialSignaturesResponse, ReserveNoncesRequest,
+    ReservedNonce, SessionInfo, SessionPhase, SignerDeregistrationRequest,
+    SignerRegistrationRequest, SigningSession,
+};
+use musig2::secp::Scalar;
+use musig2::{CompactSignature, FirstRound, PartialSignature, PubNonce, SecondRound};
+use secp256k1::PublicKey;
+use warp::Filter;
+
+use std::collections::HashMap;
+use std::net::Ipv4Addr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::net::TcpListener;
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+/// Upper bound on the number of nonces reserved by a single request.
+const MAX_NONCE_RESERVATIONS: usize = 100;
+
+/// Wait between attempts to register with an operator that isn't reachable yet.
+const REGISTRATION_RETRY_INTERVAL: Duration = Duration::from_secs(2);
+
+/// How long a finalized session's signature is kept to answer repeated finalization requests.
+const FINALIZED_RETENTION: Duration = Duration::from_secs(10 * 60);
+
+/// Lifecycle of a session's second round: Pending -> Finalizing -> Done.
+enum Finalization {
+    /// Waiting for the other signers' partial signatures
+    Pending(Box<SecondRound<Vec<u8>>>),
+    /// The round has been taken out and is being finalized
+    Finalizing,
+    /// Finalized; the signature is kept for [`FINALIZED_RETENTION`] to answer repeated requests
+    Done {
+        signature: CompactSignature,
+        finished_at: Instant,
+    },
+}
+
+impl Finalization {
+    fn done(signature: CompactSignature) -> Self {
+        Finalization::Done {
+            signature,
+            finished_at: Instant::now(),
+        }
+    }
+
+    /// Whether the session still holds a round, as opposed to a cached signature.
+    fn is_active(&self) -> bool {
+        !matches!(self, Finalization::Done { .. })
+    }
+}
+
+/// Drops the signatures of sessions finalized more than [`FINALIZED_RETENTION`] before `now`.
+fn prune_finalized(second_rounds: &mut HashMap<String, Finalization>, now: Instant) {
+    second_rounds.retain(|_, finalization| match finalization {
+        Finalization::Done { finished_at, .. } => {
+            now.duration_since(*finished_at) < FINALIZED_RETENTION
+        }
+        _ => true,
+    });
+}
+
+/// Number of sessions in `second_rounds` that still hold a round.
+fn count_active(second_rounds: &HashMap<String, Finalization>) -> usize {
+    second_rounds.values().filter(|f| f.is_active()).count()
+}
+
+/// Feeds the other signers' partial signatures into `second_round` and finalizes it.
+fn finalize_second_round(
+    mut second_round: SecondRound<Vec<u8>>,
+    partial_signatures: HashMap<usize, PartialSignature>,
+) -> Result<CompactSignature, warp::Rejection> {
+    // One partial from each other participant, next to our own that the round already holds;
+    // a missing one would otherwise only surface as an opaque finalize failure
+    let expected = second_round.holdouts().len() + 1;
+    let received = partial_signatures.len() + 1;
+    if received != expected {
+        return Err(warp::reject::custom(SignerError(format!(
+            "Expected {} partials, got {}",
+            expected, received
+        ))));
+    }
+
+    for (index, sig) in partial_signatures {
+        if let Err(e) = second_round.receive_signature(index, sig) {
+            eprintln!("Failed to receive signature from index {}: {:?}", index, e);
+            return Err(warp::reject::custom(SignerError(format!(
+                "Failed to receive partial signature from index {}",
+                index
+            ))));
+        }
+    }
+
+    second_round.finalize().map_err(|e| {
+        eprintln!("Failed to finalize signature: {:?}", e);
+        warp::reject::custom(SignerError("Failed to finalize signature".to_string()))
+    })
+}
+
+/// A pre-generated first round waiting to be bound to a signing session.
+struct NonceReservation {
+    aggregated_pubkey: PublicKey,
+    signer_index: usize,
+    first_round: FirstRound,
+}
+
+/// Signer node holding one MuSig2 key and answering the operator's signing requests.
+pub struct Signer<B: PartialSigner> {
+    client: HttpClient,
+    operator_url: String,
+    url: String,
+    backend: Arc<B>,
+    public_key: PublicKey,
+    admin_token: Option<String>,
+    // Key the operator signs its requests with; requests are accepted unsigned when unset
+    operator_auth_key: Option<String>,
+    // Draws of the RNG allowed per nonce while it keeps producing weak seeds
+    nonce_seed_attempts: u32,
+    declined: Arc<Mutex<bool>>,
+    // Set while waiting as a standby for another signer with our key
+    standby: Arc<Mutex<bool>>,
+    // Set while background registration with the operator hasn't succeeded yet
+    registration_pending: Arc<Mutex<bool>>,
+    // Only ever set through the `fault-injection` feature's /fault endpoint
+    fault: Arc<Mutex<Option<Fault>>>,
+    // What each in-flight session signs, by session ID
+    sessions: Arc<Mutex<HashMap<String, SigningSession>>>,
+    first_rounds: Arc<Mutex<HashMap<String, FirstRound>>>,
+    // Per-session tweaks on our key, needed again when signing
+    tweaks: Arc<Mutex<HashMap<String, Scalar>>>,
+    second_rounds: Arc<Mutex<HashMap<String, Finalization>>>,
+    reservations: Arc<Mutex<HashMap<String, NonceReservation>>>,
+}
+
+// Manual impl, since a derive would require `B: Clone` even though only the `Arc` is cloned
+impl<B: PartialSigner> Clone for Signer<B> {
+    fn clone(&self) -> Self {
+        Self {
+            client: self.client.clone(),
+            operator_url: self.operator_url.clone(),
+            url: self.url.clone(),
+            backend: self.backend.clone(),
+            public_key: self.public_key,
+            admin_token: self.admin_token.clone(),
+            operator_auth_key: self.operator_auth_key.clone(),
+            nonce_seed_attempts: self.nonce_seed_attempts,
+            declined: self.declined.clone(),
+            standby: self.standby.clone(),
+            registration_pending: self.registration_pending.clone(),
+            fault: self.fault.clone(),
+            sessions: self.sessions.clone(),
+            first_rounds: self.first_rounds.clone(),
+            tweaks: self.tweaks.clone(),
+            second_rounds: self.second_rounds.clone(),
+            reservations: self.reservations.clone(),
+        }
+    }
+}
+
+impl<B: PartialSigner + 'static> Signer<B> {
+    pub fn new(
+        client: HttpClient,
+        operator_url: String,
+        port: u16,
+        admin_token: Option<String>,
+        operator_auth_key: Option<String>,
+        nonce_seed_attempts: u32,
+        backend: B,
+    ) -> Self {
+        let address = format!("http://127.0.0.1:{}", port);
+        let public_key = backend.public_key();
+        println!("Public key: {}", public_key);
+        Self {
+            client,
+            operator_url,
+            url: address,
+            backend: Arc::new(backend),
+            public_key,
+            admin_token,
+            operator_auth_key,
+            nonce_seed_attempts,
+            declined: Arc::new(Mutex::new(false)),
+            standby: Arc::new(Mutex::new(false)),
+            registration_pending: Arc::new(Mutex::new(false)),
+            fault: Arc::new(Mutex::new(None)),
+            sessions: Arc::new(Mutex::new(HashMap::new())),
+            first_rounds: Arc::new(Mutex::new(HashMap::new())),
+            tweaks: Arc::new(Mutex::new(HashMap::new())),
+            second_rounds: Arc::new(Mutex::new(HashMap::new())),
+            reservations: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub async fn register(&self) -> Result<impl warp::Reply, warp::Rejection> {
+        self.submit_registration("register").await?;
+        println!("✅ Signer node registered successfully.");
+        Ok(warp::reply())
+    }
+
+    /// Registers as a warm standby for the signer holding the same key. The standby refuses
+    /// to take part in ceremonies until the operator activates it in place of the primary.
+    pub async fn register_standby(&self) -> Result<impl warp::Reply, warp::Rejection> {
+        *self.standby.lock().await = true;
+        self.submit_registration("register-standby").await?;
+        println!("✅ Signer node registered as a standby.");
+        Ok(warp::reply())
+    }
+
+    /// Registers with the operator (as a standby if `standby` is set) from a background task,
+    /// retrying until it succeeds, so that a signer started before the operator joins once the
+    /// operator is up. Ceremonies are refused as not registered until then.
+    pub async fn register_in_background(&self, standby: bool) -> tokio::task::JoinHandle<()> {
+        *self.registration_pending.lock().await = true;
+        let signer = self.clone();
+        tokio::spawn(async move {
+            loop {
+                let result = if standby {
+                    signer.register_standby().await.map(|_| ())
+                } else {
+                    signer.register().await.map(|_| ())
+                };
+                match result {
+                    Ok(()) => break,
+                    Err(rejection) => {
+                        eprintln!(
+                            "Registration with the operator at {} failed ({:?}), retrying in {:?}",
+                            signer.operator_url, rejection, REGISTRATION_RETRY_INTERVAL
+                        );
+                        tokio::time::sleep(REGISTRATION_RETRY_INTERVAL).await;
+                    }
+                }
+            }
+            *signer.registration_pending.lock().await = false;
+        })
+    }
+
+    /// Asks the operator to drop this signer from its signer set, e.g. before shutting down, so
+    /// that later ceremonies don't wait on a key that will never sign. A standby, or a signer
+    /// whose registration never went through, has nothing to remove and is left alone.
+    pub async fn deregister(&self) -> Result<(), warp::Rejection> {
+        if *self.standby.lock().await || *self.registration_pending.lock().await {
+            return Ok(());
+        }
+
+        let response = self
+            .client
+            .inner()
+            .post(format!("{}/deregister", self.operator_url))
+            .json(&SignerDeregistrationRequest {
+                public_key: self.public_key,
+            })
+            .send()
+            .await
+            .map_err(|e| warp::reject::custom(SignerError(e.to_string())))?;
+
+        if response.status().is_success() {
+            println!("👋 Signer node deregistered from the operator.");
+            Ok(())
+        } else {
+            let error = response
+                .text()
+                .await
+                .map_err(|e| warp::reject::custom(SignerError(e.to_string())))?;
+            Err(warp::reject::custom(SignerError(error)))
+        }
+    }
+
+    /// Rejects ceremony requests while background registration is still pending.
+    async fn check_registered(&self) -> Result<(), warp::Rejection> {
+        if *self.registration_pending.lock().await {
+            return Err(warp::reject::custom(SignerError(
+                "Signer is not registered with the operator yet".to_string(),
+            )));
+        }
+        Ok(())
+    }
+
+    async fn submit_registration(&self, path: &str) -> Result<(), warp::Rejection> {
+        // Submit public key to operator
+        let registration = SignerRegistrationRequest {
+            address: self.url.clone(),
+            public_key: self.public_key,
+        };
+
+        let response = self
+            .client
+            .inner()
+            .post(format!("{}/{}", self.operator_url, path))
+            .json(&registration)
+            .send()
+            .await
+            .map_err(|e| warp::reject::custom(SignerError(e.to_string())))?;
+
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            let error = response
+                .text()
+                .await
+                .map_err(|e| warp::reject::custom(SignerError(e.to_string())))?;
+            Err(warp::reject::custom(SignerError(error)))
+        }
+    }
+
+    pub async fn start_server(&self) {
+        let port: u16 = self.url.split(':').next_back().unwrap().parse().unwrap();
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))
+            .await
+            .unwrap_or_else(|e| panic!("Failed to bind port {}: {}", port, e));
+        self.serve(listener).await
+    }
+
+    /// Serves the endpoints on `listener`, which must be bound to the signer's port. Binding
+    /// it beforehand lets the caller register with the operator knowing that connections to
+    /// the signer are already accepted.
+    pub async fn serve(&self, listener: TcpListener) {
+        let state = self.clone();
+        let state_filter = warp::any().map(move || state.clone());
+
+        // Generate nonce endpoint
+        let generate_nonce = warp::post()
+            .and(warp::path("nonce"))
+            .and(authenticated_json(self.operator_auth_key.clone()))
+            .and(state_filter.clone())
+            .and_then(
+                |req, state: Signer<B>| async move { state.handle_generate_nonce(req).await },
+            );
+
+        // Receive nonces endpoint
+        let receive_nonces = warp::put()
+            .and(warp::path("nonces"))
+            .and(authenticated_json(self.operator_auth_key.clone()))
+            .and(state_filter.clone())
+            .and_then(
+                |req, state: Signer<B>| async move { state.handle_receive_nonces(req).await },
+            );
+
+        // Receive partial signatures endpoint
+        let receive_partial_signatures = warp::put()
+            .and(warp::path("partial-signatures"))
+            .and(authenticated_json(self.operator_auth_key.clone()))
+            .and(state_filter.clone())
+            .and_then(|req, state: Signer<B>| async move {
+                state.handle_receive_partial_signatures(req).await
+            });
+
+        // Receive the final signature of a session the operator finalized endpoint
+        let receive_final_signature = warp::post()
+            .and(warp::path("final-signature"))
+            .and(authenticated_json(self.operator_auth_key.clone()))
+            .and(state_filter.clone())
+            .and_then(|req, state: Signer<B>| async move {
+                state.handle_receive_final_signature(req).await
+            });
+
+        // Reserve nonces for future sessions endpoint
+        let reserve_nonces = warp::post()
+            .and(warp::path("reserve-nonces"))
+            .and(authenticated_json(self.operator_auth_key.clone()))
+            .and(state_filter.clone())
+            .and_then(
+                |req, state: Signer<B>| async move { state.handle_reserve_nonces(req).await },
+            );
+
+        // List in-flight sessions endpoint
+        let list_sessions = warp::get()
+            .and(warp::path("sessions"))
+            .and(warp::path::end())
+            .and(state_filter.clone())
+            .and_then(|state: Signer<B>| async move { state.handle_list_sessions().await });
+
+        // Health endpoint for readiness probes
+        let health = warp::get()
+            .and(warp::path("health"))
+            .and(warp::path::end())
+            .and(state_filter.clone())
+            .and_then(|state: Signer<B>| async move { state.handle_health().await });
+
+        // Cancel a session endpoint, used by an operator recovering from a restart
+        let cancel_session = warp::delete()
+            .and(warp::path!("sessions" / String))
+            .and(operator_auth(self.operator_auth_key.clone()))
+            .and(state_filter.clone())
+            .and_then(|session_id, _body, state: Signer<B>| async move {
+                state.handle_cancel_session(session_id).await
+            });
+
+        // Clear all sessions endpoint (admin only)
+        let clear_sessions = warp::post()
+            .and(warp::path!("sessions" / "clear"))
+            .and(admin_auth(self.admin_token.clone()))
+            .and(state_filter.clone())
+            .and_then(|state: Signer<B>| async move { state.handle_clear_sessions().await });
+
+        // Toggle declining to participate endpoint (admin only)
+        let decline = warp::post()
+            .and(warp::path("decline"))
+            .and(admin_auth(self.admin_token.clone()))
+            .and(warp::body::json())
+            .and(state_filter.clone())
+            .and_then(|req, state: Signer<B>| async move { state.handle_decline(req).await });
+
+        // Standby activation endpoint
+        let activate = warp::post()
+            .and(warp::path("activate"))
+            .and(operator_auth(self.operator_auth_key.clone()))
+            .and(state_filter.clone())
+            .and_then(|_body, state: Signer<B>| async move { state.handle_activate().await });
+
+        let routes = generate_nonce
+            .or(receive_nonces)
+            .or(receive_partial_signatures)
+            .or(receive_final_signature)
+            .or(reserve_nonces)
+            .or(list_sessions)
+            .or(health)
+            .or(cancel_session)
+            .or(clear_sessions)
+            .or(decline)
+            .or(activate);
+
+        // Fault injection endpoint, for testing the operator's fault handling
+        #[cfg(feature = "fault-injection")]
+        let routes = routes.or(warp::post()
+            .and(warp::path("fault"))
+            .and(warp::body::json())
+            .and(state_filter.clone())
+            .and_then(|req, state: Signer<B>| async move { state.handle_fault(req).await }));
+
+        let routes = routes.recover(handle_rejection);
+
+        println!(
+            "Signer running on port {}...",
+            self.url.split(':').next_back().unwrap()
+        );
+        let incoming = futures::stream::unfold(listener, |listener| async move {
+            let accepted = listener.accept().await.map(|(stream, _)| stream);
+            Some((accepted, listener))
+        });
+        warp::serve(routes).run_incoming(incoming).await;
+    }
+
+    async fn handle_generate_nonce(
+        self,
+        mut request: GenerateNonceRequest,
+    ) -> Result<impl warp::Reply, warp::Rejection> {
+        if *self.declined.lock().await {
+            println!(
+                "🙅 Declining to take part in session {}.",
+                request.session_id
+            );
+            return Err(warp::reject::custom(Declined));
+        }
+        // Two signers must never use the same key at once, so a standby waits for activation
+        if *self.standby.lock().await {
+            println!(
+                "🙅 Standing by, not taking part in session {}.",
+                request.session_id
+            );
+            return Err(warp::reject::custom(Declined));
+        }
+        self.check_registered().await?;
+
+        let key_agg_ctx = match (request.key_agg_ctx.take(), request.aggregated_pubkey) {
+            (Some(key_agg_ctx), _) => key_agg_ctx,
+            // Only the expected key was sent, so rebuild the context from the participants
+            (None, Some(expected)) if !request.participants.is_empty() => {
+                let pubkeys = request
+                    .participants
+                    .iter()
+                    .map(|participant| participant.public_key)
+                    .collect();
+                rebuild_key_agg_ctx(pubkeys, request.taproot_merkle_root.as_ref(), expected)
+                    .map_err(|e| warp::reject::custom(SignerError(e)))?
+            }
+            (None, _) => {
+                return Err(warp::reject::custom(SignerError(
+                    "Request carries neither a key aggregation context nor the participants and aggregated key to rebuild it from".to_string(),
+                )));
+            }
+        };
+
+        // With a tweak, the context holds our tweaked key rather than our own
+        let public_key = match request.tweak {
+            Some(tweak) => tweak_pubkey(self.public_key, tweak)
+                .map_err(|e| warp::reject::custom(SignerError(e)))?,
+            None => self.public_key,
+        };
+
+        // Refuse to contribute to a context that doesn't include our key at the given index
+        match key_agg_ctx.pubkey_index(public_key) {
+            Some(index) if index == request.signer_index => {}
+            Some(index) => {
+                return Err(warp::reject::custom(SignerError(format!(
+                    "Signer index mismatch: our key is at index {}, request says {}",
+                    index, request.signer_index
+                ))));
+            }
+            None => {
+                return Err(warp::reject::custom(SignerError(
+                    "Not a participant in the key aggregation context".to_string(),
+                )));
+            }
+        }
+
+        // Make sure the context really was built from the participants we're told about
+        if !request.participants.is_empty() {
+            let expected: Vec<PublicKey> = request
+                .participants
+                .iter()
+                .map(|participant| participant.public_key)
+                .collect();
+            check_context_pubkeys(&key_agg_ctx, &expected)
+                .map_err(|e| warp::reject::custom(SignerError(e)))?;
+        }
+
+        let message = signed_bytes(
+            &request.message,
+            request.message_encoding,
+            request.signing_domain,
+        )
+        .map_err(|e| warp::reject::custom(SignerError(e)))?;
+
+        let first_round = match &request.reservation_id {
+            Some(reservation_id) => {
+                self.take_reservation(
+                    reservation_id,
+                    key_agg_ctx.aggregated_pubkey(),
+                    request.signer_index,
+                )
+                .await?
+            }
+            None => self
+                .backend
+                .first_round(
+                    key_agg_ctx.clone(),
+                    nonce_seed_with_retries(&mut rand::thread_rng(), self.nonce_seed_attempts)
+                        .map_err(|e| warp::reject::custom(SignerError(e)))?,
+                    request.signer_index,
+                    Some(&message),
+                )
+                .map_err(|_| {
+                    warp::reject::custom(SignerError("Failed to generate nonce".to_string()))
+                })?,
+        };
+
+        let public_nonce = first_round.our_public_nonce();
+
+        // Store session data and FirstRound separately
+        let mut sessions = self.sessions.lock().await;
+        let mut first_rounds = self.first_rounds.lock().await;
+
+        let session = SigningSession {
+            session_id: request.session_id.clone(),
+            message: request.message.clone(),
+            message_encoding: request.message_encoding,
+            signing_domain: request.signing_domain,
+            key_agg_ctx,
+        };
+        sessions.insert(request.session_id.clone(), session);
+
+        if let Some(tweak) = request.tweak {
+            self.tweaks
+                .lock()
+                .await
+                .insert(request.session_id.clone(), tweak);
+        }
+        first_rounds.insert(request.session_id, first_round);
+
+        Ok(reply::json(&public_nonce.serialize().to_vec()))
+    }
+
+    async fn handle_reserve_nonces(
+        self,
+        request: ReserveNoncesRequest,
+    ) -> Result<impl warp::Reply, warp::Rejection> {
+        self.check_registered().await?;
+        if request.count == 0 || request.count > MAX_NONCE_RESERVATIONS {
+            return Err(warp::reject::custom(SignerError(format!(
+                "Reservation count must be between 1 and {}",
+                MAX_NONCE_RESERVATIONS
+            ))));
+        }
+        if request.key_agg_ctx.pubkey_index(self.public_key) != Some(request.signer_index) {
+            return Err(warp::reject::custom(SignerError(
+                "Not a participant at the given index of the key aggregation context".to_string(),
+            )));
+        }
+
+        let aggregated_pubkey: PublicKey = request.key_agg_ctx.aggregated_pubkey();
+        let mut reservations = self.reservations.lock().await;
+        let mut reserved = Vec::with_capacity(request.count);
+
+        for _ in 0..request.count {
+            // The message isn't known yet, so it can't be mixed into the nonce
+            let first_round = self
+                .backend
+                .first_round(
+                    request.key_agg_ctx.clone(),
+                    nonce_seed_with_retries(&mut rand::thread_rng(), self.nonce_seed_attempts)
+                        .map_err(|e| warp::reject::custom(SignerError(e)))?,
+                    request.signer_index,
+                    None,
+                )
+                .map_err(|_| {
+                    warp::reject::custom(SignerError("Failed to generate nonce".to_string()))
+                })?;
+
+            let reservation_id = Uuid::new_v4().to_string();
+            reserved.push(ReservedNonce {
+                reservation_id: reservation_id.clone(),
+                public_nonce: first_round.our_public_nonce().serialize().to_vec(),
+            });
+            reservations.insert(
+                reservation_id,
+                NonceReservation {
+                    aggregated_pubkey,
+                    signer_index: request.signer_index,
+                    first_round,
+                },
+            );
+        }
+        println!("🎟️ Reserved {} nonce(s).", reserved.len());
+
+        Ok(reply::json(&reserved))
+    }
+
+    /// Removes a reservation so its nonce can be used at most once.
+    async fn take_reservation(
+        &self,
+        reservation_id: &str,
+        aggregated_pubkey: PublicKey,
+        signer_index: usize,
+    ) -> Result<FirstRound, warp::Rejection> {
+        let mut reservations = self.reservations.lock().await;
+
+        let reservation = reservations.get(reservation_id).ok_or_else(|| {
+            warp::reject::custom(SignerError(format!(
+                "Nonce reservation {} is unknown or was already used",
+                reservation_id
+            )))
+        })?;
+
+        if reservation.aggregated_pubkey != aggregated_pubkey
+            || reservation.signer_index != signer_index
+        {
+            return Err(warp::reject::custom(SignerError(format!(
+                "Nonce reservation {} was made for a different context",
+                reservation_id
+            ))));
+        }
+
+        Ok(reservations.remove(reservation_id).unwrap().first_round)
+    }
+
+    async fn handle_receive_nonces(
+        self,
+        request: ReceiveNoncesRequest,
+    ) -> Result<impl warp::Reply, warp::Rejection> {
+        let mut sessions = self.sessions.lock().await;
+        // Only ever sign the message of the session the nonces are for
+        let session = sessions.get(&request.session_id).ok_or_else(|| {
+            warp::reject::custom(SignerError(format!(
+                "Session {} not found",
+                request.session_id
+            )))
+        })?;
+
+        let mut first_rounds = self.first_rounds.lock().await;
+        let mut second_rounds = self.second_rounds.lock().await;
+
+        // The round stays in place until it is finalized, so that a failed attempt can be retried
+        let first_round = first_rounds.get_mut(&request.session_id).ok_or_else(|| {
+            warp::reject::custom(SignerError("First round not found".to_string()))
+        })?;
+
+        // Receive nonces from other signers
+        for (index, nonce_bytes) in request.nonces {
+            // println!("Received nonce for signer index {}", index);
+            let other_nonce = PubNonce::from_bytes(&nonce_bytes).map_err(|_| {
+                warp::reject::custom(SignerError("Invalid nonce format".to_string()))
+            })?;
+
+            first_round.receive_nonce(index, other_nonce).map_err(|e| {
+                eprintln!("Failed to receive nonce from index {}: {:?}", index, e);
+                warp::reject::custom(SignerError(format!(
+                    "Failed to receive nonce from index {}",
+                    index
+                )))
+            })?;
+        }
+
+        // Finalize first round
+        let message_bytes = signed_bytes(
+            &session.message,
+            session.message_encoding,
+            session.signing_domain,
+        )
+        .map_err(|e| warp::reject::custom(SignerError(e)))?;
+        let mut tweaks = self.tweaks.lock().await;
+        let tweak = tweaks.get(&request.session_id).copied();
+
+        let first_round = first_rounds.remove(&request.session_id).unwrap();
+        let second_round = match self
+            .backend
+            .finalize(first_round, message_bytes.clone(), tweak)
+        {
+            Ok(second_round) => second_round,
+            Err(failure) => {
+                eprintln!("Failed to finalize first round: {:?}", failure.error);
+                let message = match failure.first_round {
+                    Some(first_round) => {
+                        first_rounds.insert(request.session_id.clone(), *first_round);
+                        "Failed to finalize first round; the session can be retried"
+                    }
+                    None => {
+                        tweaks.remove(&request.session_id);
+                        sessions.remove(&request.session_id);
+                        "Failed to finalize first round"
+                    }
+                };
+                return Err(warp::reject::custom(SignerError(message.to_string())));
+            }
+        };
+        tweaks.remove(&request.session_id);
+        drop(tweaks);
+
+        let mut partial_signature: PartialSignature = second_round.our_signature();
+        second_rounds.insert(
+            request.session_id.clone(),
+            Finalization::Pending(Box::new(second_round)),
+        );
+
+        if self.take_fault(Fault::CorruptPartialSignature).await {
+            eprintln!("💥 Injecting a corrupted partial signature.");
+            partial_signature += Scalar::one();
+        }
+        println!(
+            "Partial signature: {:?}",
+            hex::encode(partial_signature.serialize())
+        );
+
+        Ok(reply::json(&ReceiveNoncesResponse {
+            partial_signature,
+            message_hash: message_hash(&message_bytes),
+        }))
+    }
+
+    async fn handle_receive_partial_signatures(
+        self,
+        request: ReceivePartialSignaturesRequest,
+    ) -> Result<impl warp::Reply, warp::Rejection> {
+        let session_id = request.session_id;
+        let second_round = {
+            let mut second_rounds = self.second_rounds.lock().await;
+            match second_rounds.remove(&session_id) {
+                Some(Finalization::Pending(second_round)) => {
+                    second_rounds.insert(session_id.clone(), Finalization::Finalizing);
+                    second_round
+                }
+                // A retried request gets the signature we already produced
+                Some(done @ Finalization::Done { signature, .. }) => {
+                    println!(
+                        "Session {} is already finalized, returning the cached signature.",
+                        session_id
+                    );
+                    second_rounds.insert(session_id, done);
+                    return Ok(reply::json(&ReceivePartialSignaturesResponse {
+                        final_signature: signature,
+                    }));
+                }
+                Some(Finalization::Finalizing) => {
+                    second_rounds.insert(session_id.clone(), Finalization::Finalizing);
+                    return Err(warp::reject::custom(SignerError(format!(
+                        "Session {} is already being finalized",
+                        session_id
+                    ))));
+                }
+                None => {
+                    drop(second_rounds);
+                    // A first round means the session exists but hasn't received nonces yet
+                    let message = if self.first_rounds.lock().await.contains_key(&session_id) {
+                        format!("Partials received before nonces for session {}", session_id)
+                    } else {
+                        "Second round not found".to_string()
+                    };
+                    return Err(warp::reject::custom(SignerError(message)));
+                }
+            }
+        };
+
+        // The round is consumed either way, so a failed session can't be finalized again
+        let result = finalize_second_round(*second_round, request.partial_signatures);
+        let mut second_rounds = self.second_rounds.lock().await;
+        match &result {
+            Ok(final_signature) => {
+                prune_finalized(&mut second_rounds, Instant::now());
+                second_rounds.insert(session_id.clone(), Finalization::done(*final_signature));
+            }
+            Err(_) => {
+                second_rounds.remove(&session_id);
+            }
+        }
+        drop(second_rounds);
+        // The round is finished, so the session's message is no longer needed
+        self.sessions.lock().await.remove(&session_id);
+        let mut final_signature = result?;
+
+        if self.take_fault(Fault::MismatchedFinalSignature).await {
+            eprintln!("💥 Injecting a mismatched final signature.");
+            final_signature.s += Scalar::one();
+        }
+
+        Ok(reply::json(&ReceivePartialSignaturesResponse {
+            final_signature,
+        }))
+    }
+
+    /// Takes the final signature of a session the operator finalized itself, in place of the
+    /// round that was waiting for partials. The signature is checked against the session when
+    /// we still hold it, and kept to answer later requests like one we produced ourselves.
+    async fn handle_receive_final_signature(
+        self,
+        request: FinalSignatureRequest,
+    ) -> Result<impl warp::Reply, warp::Rejection> {
+        let session_id = request.session_id;
+        let final_signature = request.final_signature;
+
+        let sessions = self.sessions.lock().await;
+        if let Some(session) = sessions.get(&session_id) {
+            let message = signed_bytes(
+                &session.message,
+                session.message_encoding,
+                session.signing_domain,
+            )
+            .map_err(|e| warp::reject::custom(SignerError(e)))?;
+            let aggregated_pubkey: PublicKey = session.key_agg_ctx.aggregated_pubkey();
+            if verify_schnorr(aggregated_pubkey, final_signature, &message).is_err() {
+                return Err(warp::reject::custom(SignerError(format!(
+                    "Final signature doesn't verify for session {}",
+                    session_id
+                ))));
+            }
+        }
+        drop(sessions);
+
+        let mut second_rounds = self.second_rounds.lock().await;
+        match second_rounds.get(&session_id) {
+            Some(Finalization::Pending(_)) => {}
+            Some(Finalization::Done { signature, .. }) if *signature == final_signature => {}
+            Some(Finalization::Done { .. }) => {
+                return Err(warp::reject::custom(SignerError(format!(
+                    "Session {} is already finalized with another signature",
+                    session_id
+                ))));
+            }
+            Some(Finalization::Finalizing) => {
+                return Err(warp::reject::custom(SignerError(format!(
+                    "Session {} is already being finalized",
+                    session_id
+                ))));
+            }
+            None => {
+                return Err(warp::reject::custom(SignerError(
+                    "Second round not found".to_string(),
+                )));
+            }
+        }
+        prune_finalized(&mut second_rounds, Instant::now());
+        second_rounds.insert(session_id.clone(), Finalization::done(final_signature));
+        drop(second_rounds);
+        self.sessions.lock().await.remove(&session_id);
+        println!(
+            "🏁 Final signature of session {}: {}",
+            session_id,
+            hex::encode(final_signature.serialize())
+        );
+
+        Ok(warp::reply())
+    }
+
+    /// Consumes the pending fault if it is `fault`, returning whether it should be injected.
+    async fn take_fault(&self, fault: Fault) -> bool {
+        let mut pending = self.fault.lock().await;
+        if *pending == Some(fault) {
+            *pending = None;
+            true
+        } else {
+            false
+        }
+    }
+
+    #[cfg(feature = "fault-injection")]
+    async fn handle_fault(
+        self,
+        request: FaultRequest,
+    ) -> Result<impl warp::Reply, warp::Rejection> {
+        match request.fault {
+            Some(fault) => println!("💥 Will inject {:?} in the next ceremony.", fault),
+            None => println!("Cleared pending fault."),
+        }
+        *self.fault.lock().await = request.fault;
+        Ok(reply::json(&request.fault))
+    }
+
+    async fn handle_activate(self) -> Result<impl warp::Reply, warp::Rejection> {
+        let mut standby = self.standby.lock().await;
+        if *standby {
+            println!("🟢 Activated, taking over for the primary signer.");
+            *standby = false;
+        }
+        // The operator checks this against the primary's key before routing to us
+        Ok(reply::json(&ActivateResponse {
+            public_key: self.public_key,
+        }))
+    }
+
+    async fn handle_list_sessions(self) -> Result<impl warp::Reply, warp::Rejection> {
+        let first_rounds = self.first_rounds.lock().await;
+        let second_rounds = self.second_rounds.lock().await;
+
+        let mut sessions: Vec<SessionInfo> = first_rounds
+            .keys()
+            .map(|session_id| SessionInfo {
+                session_id: session_id.clone(),
+                phase: SessionPhase::AwaitingNonces,
+            })
+            .chain(
+                second_rounds
+                    .iter()
+                    .filter_map(|(session_id, finalization)| {
+                        let phase = match finalization {
+                            Finalization::Pending(_) => SessionPhase::AwaitingPartialSignatures,
+                            Finalization::Finalizing => SessionPhase::Finalizing,
+                            Finalization::Done { .. } => return None,
+                        };
+                        Some(SessionInfo {
+                            session_id: session_id.clone(),
+                            phase,
+                        })
+                    }),
+            )
+            .collect();
+        sessions.sort_by(|a, b| a.session_id.cmp(&b.session_id));
+
+        Ok(reply::json(&sessions))
+    }
+
+    /// Answers readiness probes with our public key and the number of rounds in flight.
+    async fn handle_health(self) -> Result<impl warp::Reply, warp::Rejection> {
+        let active_sessions =
+            self.first_rounds.lock().await.len() + count_active(&*self.second_rounds.lock().await);
+
+        Ok(reply::json(&HealthResponse {
+            public_key: self.public_key,
+            active_sessions,
+        }))
+    }
+
+    async fn handle_decline(
+        self,
+        request: DeclineRequest,
+    ) -> Result<impl warp::Reply, warp::Rejection> {
+        *self.declined.lock().await = request.declined;
+        if request.declined {
+            println!("⏸️ Signer is now declining new ceremonies.");
+        } else {
+            println!("▶️ Signer is accepting new ceremonies again.");
+        }
+
+        Ok(reply::json(&request))
+    }
+
+    /// Drops all state held for one session, whatever phase it is in.
+    async fn handle_cancel_session(
+        self,
+        session_id: String,
+    ) -> Result<impl warp::Reply, warp::Rejection> {
+        let mut sessions = self.sessions.lock().await;
+        let mut first_rounds = self.first_rounds.lock().await;
+        let mut second_rounds = self.second_rounds.lock().await;
+
+        let cleared = usize::from(first_rounds.remove(&session_id).is_some())
+            + usize::from(
+                second_rounds
+                    .remove(&session_id)
+                    .is_some_and(|finalization| finalization.is_active()),
+            );
+        sessions.remove(&session_id);
+        self.tweaks.lock().await.remove(&session_id);
+        if cleared > 0 {
+            println!("🧹 Cancelled session {}.", session_id);
+        }
+
+        Ok(reply::json(&ClearSessionsResponse { cleared }))
+    }
+
+    async fn handle_clear_sessions(self) -> Result<impl warp::Reply, warp::Rejection> {
+        let mut sessions = self.sessions.lock().await;
+        let mut first_rounds = self.first_rounds.lock().await;
+        let mut second_rounds = self.second_rounds.lock().await;
+
+        let cleared = first_rounds.len() + count_active(&second_rounds);
+        sessions.clear();
+        first_rounds.clear();
+        second_rounds.clear();
+        self.tweaks.lock().await.clear();
+        println!("🧹 Cleared {} in-flight session(s).", cleared);
+
+        Ok(reply::json(&ClearSessionsResponse { cleared }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signature() -> CompactSignature {
+        CompactSignature::from_bytes(&[1; 64]).unwrap()
+    }
+
+    #[test]
+    fn finalized_signatures_are_pruned_after_the_retention() {
+        let mut second_rounds = HashMap::from([
+            ("finalizing".to_string(), Finalization::Finalizing),
+            ("done".to_string(), Finalization::done(signature())),
+        ]);
+        let now = Instant::now();
+
+        prune_finalized(&mut second_rounds, now);
+        assert_eq!(second_rounds.len(), 2);
+
+        prune_finalized(&mut second_rounds, now + FINALIZED_RETENTION);
+        assert!(second_rounds.contains_key("finalizing"));
+        assert!(!second_rounds.contains_key("done"));
+    }
+
+    #[test]
+    fn finalized_sessions_are_not_active() {
+        let second_rounds = HashMap::from([
+            ("finalizing".to_string(), Finalization::Finalizing),
+            ("a".to_string(), Finalization::done(signature())),
+            ("b".to_string(), Finalization::done(signature())),
+        ]);
+        assert_eq!(count_active(&second_rounds), 1);
+    }
+}
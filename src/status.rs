@@ -0,0 +1,215 @@
+use crate::types::{CeremonyPhase, SessionStatus};
+use musig2::CompactSignature;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How long a complete or failed session's status stays queryable.
+const FINISHED_RETENTION: Duration = Duration::from_secs(10 * 60);
+
+/// A session's status, with when it completed or failed.
+struct Tracked {
+    status: SessionStatus,
+    finished_at: Option<Instant>,
+}
+
+impl Tracked {
+    fn collecting_nonces(session_id: &str, signer_count: usize) -> Self {
+        Tracked {
+            status: collecting_nonces(session_id, signer_count),
+            finished_at: None,
+        }
+    }
+}
+
+/// Drops the sessions that finished more than [`FINISHED_RETENTION`] before `now`.
+fn prune_finished(statuses: &mut HashMap<String, Tracked>, now: Instant) {
+    statuses.retain(|_, tracked| match tracked.finished_at {
+        Some(finished_at) => now.duration_since(finished_at) < FINISHED_RETENTION,
+        None => true,
+    });
+}
+
+/// Progress of every ceremony by session ID, shared between the ceremonies and
+/// `GET /status/{session_id}`. Finished sessions are forgotten after [`FINISHED_RETENTION`].
+#[derive(Clone, Default)]
+pub struct SessionTracker {
+    // Only touched synchronously, also from within the per-signer request futures
+    statuses: Arc<Mutex<HashMap<String, Tracked>>>,
+}
+
+impl SessionTracker {
+    /// Starts tracking a session that is about to collect nonces from `signer_count` signers.
+    pub fn start(&self, session_id: &str, signer_count: usize) -> TrackedSession {
+        let mut statuses = self.statuses.lock().unwrap();
+        prune_finished(&mut statuses, Instant::now());
+        statuses.insert(
+            session_id.to_string(),
+            Tracked::collecting_nonces(session_id, signer_count),
+        );
+        TrackedSession {
+            tracker: self.clone(),
+            session_id: session_id.to_string(),
+            finished: false,
+        }
+    }
+
+    /// Like [`start`](Self::start), but leaves a running or complete session alone and returns
+    /// `None`, so that two ceremonies can't claim the same ID. A failed session can be claimed
+    /// again.
+    pub fn claim(&self, session_id: &str, signer_count: usize) -> Option<TrackedSession> {
+        let mut statuses = self.statuses.lock().unwrap();
+        prune_finished(&mut statuses, Instant::now());
+        if statuses
+            .get(session_id)
+            .is_some_and(|tracked| tracked.status.phase != CeremonyPhase::Failed)
+        {
+            return None;
+        }
+        statuses.insert(
+            session_id.to_string(),
+            Tracked::collecting_nonces(session_id, signer_count),
+        );
+        Some(TrackedSession {
+            tracker: self.clone(),
+            session_id: session_id.to_string(),
+            finished: false,
+        })
+    }
+
+    pub fn get(&self, session_id: &str) -> Option<SessionStatus> {
+        self.statuses
+            .lock()
+            .unwrap()
+            .get(session_id)
+            .map(|tracked| tracked.status.clone())
+    }
+
+    fn update(&self, session_id: &str, update: impl FnOnce(&mut Tracked)) {
+        if let Some(tracked) = self.statuses.lock().unwrap().get_mut(session_id) {
+            update(tracked);
+        }
+    }
+}
+
+fn collecting_nonces(session_id: &str, signer_count: usize) -> SessionStatus {
+    SessionStatus {
+        session_id: session_id.to_string(),
+        phase: CeremonyPhase::CollectingNonces,
+        responded: 0,
+        signer_count,
+        final_signature: None,
+    }
+}
+
+/// A ceremony's handle on its status. Dropping it before `complete`, as a ceremony that bails
+/// out does, marks the session failed.
+pub struct TrackedSession {
+    tracker: SessionTracker,
+    session_id: String,
+    finished: bool,
+}
+
+impl TrackedSession {
+    pub fn session_id(&self) -> &str {
+        &self.session_id
+    }
+
+    /// Starts over with `signer_count` signers, for a ceremony rerun under the same ID.
+    pub fn restart(&self, signer_count: usize) {
+        self.tracker.update(&self.session_id, |tracked| {
+            *tracked = Tracked::collecting_nonces(&self.session_id, signer_count)
+        });
+    }
+
+    /// Moves on to `phase`, in which no signer has answered yet.
+    pub fn enter(&self, phase: CeremonyPhase) {
+        self.tracker.update(&self.session_id, |tracked| {
+            tracked.status.phase = phase;
+            tracked.status.responded = 0;
+        });
+    }
+
+    pub fn signer_responded(&self) {
+        self.tracker
+            .update(&self.session_id, |tracked| tracked.status.responded += 1);
+    }
+
+    pub fn complete(mut self, final_signature: CompactSignature) {
+        self.finished = true;
+        self.tracker.update(&self.session_id, |tracked| {
+            tracked.status.phase = CeremonyPhase::Complete;
+            tracked.status.final_signature = Some(final_signature);
+            tracked.finished_at = Some(Instant::now());
+        });
+    }
+}
+
+impl Drop for TrackedSession {
+    fn drop(&mut self) {
+        if !self.finished {
+            self.tracker.update(&self.session_id, |tracked| {
+                tracked.status.phase = CeremonyPhase::Failed;
+                tracked.finished_at = Some(Instant::now());
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_failed_ids_can_be_claimed_again() {
+        let tracker = SessionTracker::default();
+        let claimed = tracker.claim("chosen", 2).expect("A fresh ID is free");
+        assert!(tracker.claim("chosen", 3).is_none());
+        assert_eq!(tracker.get("chosen").unwrap().signer_count, 2);
+        assert!(tracker.claim("another", 2).is_some());
+
+        // Complete sessions keep their ID taken, failed ones free it for a retry
+        let signature = CompactSignature::from_bytes(&[1; 64]).unwrap();
+        tracker.claim("complete", 2).unwrap().complete(signature);
+        assert!(tracker.claim("complete", 2).is_none());
+        drop(claimed);
+        assert_eq!(tracker.get("chosen").unwrap().phase, CeremonyPhase::Failed);
+        let _retried = tracker
+            .claim("chosen", 3)
+            .expect("A failed ID can be claimed again");
+        assert_eq!(
+            tracker.get("chosen").unwrap().phase,
+            CeremonyPhase::CollectingNonces
+        );
+        assert_eq!(tracker.get("chosen").unwrap().signer_count, 3);
+    }
+
+    #[test]
+    fn restarted_sessions_collect_nonces_again() {
+        let tracker = SessionTracker::default();
+        let claimed = tracker.claim("rerun", 3).unwrap();
+        claimed.enter(CeremonyPhase::CollectingPartials);
+        claimed.signer_responded();
+        claimed.restart(2);
+        let status = tracker.get("rerun").unwrap();
+        assert_eq!(status.phase, CeremonyPhase::CollectingNonces);
+        assert_eq!((status.responded, status.signer_count), (0, 2));
+    }
+
+    #[test]
+    fn finished_sessions_are_forgotten_after_the_retention() {
+        let tracker = SessionTracker::default();
+        let signature = CompactSignature::from_bytes(&[1; 64]).unwrap();
+        tracker.claim("complete", 2).unwrap().complete(signature);
+        drop(tracker.claim("failed", 2).unwrap());
+        let _running = tracker.claim("running", 2).unwrap();
+
+        let mut statuses = tracker.statuses.lock().unwrap();
+        let now = Instant::now();
+        prune_finished(&mut statuses, now);
+        assert_eq!(statuses.len(), 3);
+
+        prune_finished(&mut statuses, now + FINISHED_RETENTION);
+        assert_eq!(statuses.keys().collect::<Vec<_>>(), ["running"]);
+    }
+}
@@ -0,0 +1,552 @@
+use crate::registry::SignerRegistry;
+use crate::serde_utils::public_key_from_hex;
+use crate::serde_utils::{
+    deserialize_key_agg_ctx, deserialize_nonce_map, serialize_key_agg_ctx, serialize_nonce_map,
+};
+use crate::types::{MessageEncoding, Participant, SigningDomain, SigningSession};
+use musig2::{KeyAggContext, PubNonce};
+use rusqlite::{params, Connection, OptionalExtension, TransactionBehavior};
+use secp256k1::PublicKey;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// How far a persisted session got.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StoredPhase {
+    CollectingNonces,
+    CollectingPartials,
+    Distributing,
+    Completed,
+    Failed,
+    /// Dropped on the signers while recovering from a restart
+    Cancelled,
+}
+
+impl StoredPhase {
+    fn as_str(self) -> &'static str {
+        match self {
+            StoredPhase::CollectingNonces => "collecting_nonces",
+            StoredPhase::CollectingPartials => "collecting_partials",
+            StoredPhase::Distributing => "distributing",
+            StoredPhase::Completed => "completed",
+            StoredPhase::Failed => "failed",
+            StoredPhase::Cancelled => "cancelled",
+        }
+    }
+
+    fn parse(s: &str) -> Option<Self> {
+        [
+            StoredPhase::CollectingNonces,
+            StoredPhase::CollectingPartials,
+            StoredPhase::Distributing,
+            StoredPhase::Completed,
+            StoredPhase::Failed,
+            StoredPhase::Cancelled,
+        ]
+        .into_iter()
+        .find(|phase| phase.as_str() == s)
+    }
+}
+
+/// A session as persisted by the operator, listed by `GET /sessions` while incomplete.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct StoredSession {
+    pub session_id: String,
+    pub message: String,
+    pub message_encoding: MessageEncoding,
+    pub signing_domain: SigningDomain,
+    #[serde(
+        serialize_with = "serialize_key_agg_ctx",
+        deserialize_with = "deserialize_key_agg_ctx"
+    )]
+    pub key_agg_ctx: KeyAggContext,
+    /// Public nonces collected so far, by signer index
+    #[serde(
+        serialize_with = "serialize_nonce_map",
+        deserialize_with = "deserialize_nonce_map"
+    )]
+    pub nonces: BTreeMap<usize, PubNonce>,
+    pub phase: StoredPhase,
+    /// Unix time in milliseconds of the last update
+    pub updated_at_ms: u64,
+}
+
+/// SQLite database of the operator's sessions, updated as each ceremony progresses, so that
+/// sessions a crash interrupted can still be found after a restart. It also serves as the
+/// [`SignerRegistry`] of every operator opening it, so that several operators can share it.
+pub struct SessionStore {
+    // rusqlite connections aren't `Sync`; every statement is short, so a std mutex will do
+    connection: Mutex<Connection>,
+}
+
+impl SessionStore {
+    pub fn open(path: &Path) -> rusqlite::Result<Self> {
+        let connection = Connection::open(path)?;
+        // Other operators may hold the write lock for a moment
+        connection.busy_timeout(Duration::from_secs(5))?;
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS sessions (
+                session_id TEXT PRIMARY KEY,
+                message TEXT NOT NULL,
+                message_encoding TEXT NOT NULL,
+                signing_domain TEXT NOT NULL,
+                key_agg_ctx BLOB NOT NULL,
+                nonces TEXT NOT NULL,
+                phase TEXT NOT NULL,
+                updated_at_ms INTEGER NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS signers (
+                signer_index INTEGER PRIMARY KEY,
+                public_key TEXT NOT NULL UNIQUE,
+                address TEXT NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS next_signer_index (
+                id INTEGER PRIMARY KEY CHECK (id = 0),
+                value INTEGER NOT NULL
+            );
+            INSERT OR IGNORE INTO next_signer_index (id, value) VALUES (0, 0);",
+        )?;
+        Ok(Self {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    /// Records a session that just started, replacing any earlier attempt with the same ID.
+    pub fn begin(&self, session: &SigningSession) -> rusqlite::Result<()> {
+        self.connection.lock().unwrap().execute(
+            "INSERT OR REPLACE INTO sessions
+                (session_id, message, message_encoding, signing_domain, key_agg_ctx, nonces, phase, updated_at_ms)
+             VALUES (?1, ?2, ?3, ?4, ?5, '{}', ?6, ?7)",
+            params![
+                session.session_id,
+                session.message,
+                to_json(&session.message_encoding),
+                to_json(&session.signing_domain),
+                session.key_agg_ctx.serialize().to_vec(),
+                StoredPhase::CollectingNonces.as_str(),
+                now_ms(),
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Records the collected public nonces, which moves the session on to partial signatures.
+    pub fn record_nonces(
+        &self,
+        session_id: &str,
+        nonces: &HashMap<usize, PubNonce>,
+    ) -> rusqlite::Result<()> {
+        let nonces: BTreeMap<usize, String> = nonces
+            .iter()
+            .map(|(index, nonce)| (*index, hex::encode(nonce.serialize())))
+            .collect();
+        self.connection.lock().unwrap().execute(
+            "UPDATE sessions SET nonces = ?2, phase = ?3, updated_at_ms = ?4 WHERE session_id = ?1",
+            params![
+                session_id,
+                to_json(&nonces),
+                StoredPhase::CollectingPartials.as_str(),
+                now_ms(),
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn set_phase(&self, session_id: &str, phase: StoredPhase) -> rusqlite::Result<()> {
+        self.connection.lock().unwrap().execute(
+            "UPDATE sessions SET phase = ?2, updated_at_ms = ?3 WHERE session_id = ?1",
+            params![session_id, phase.as_str(), now_ms()],
+        )?;
+        Ok(())
+    }
+
+    /// Whether a session with this ID was recorded, by this run or an earlier one, and didn't
+    /// fail. A failed session's ID can be used again.
+    pub fn in_use(&self, session_id: &str) -> rusqlite::Result<bool> {
+        self.connection.lock().unwrap().query_row(
+            "SELECT EXISTS (SELECT 1 FROM sessions WHERE session_id = ?1 AND phase != ?2)",
+            params![session_id, StoredPhase::Failed.as_str()],
+            |row| row.get(0),
+        )
+    }
+
+    /// Sessions that neither completed nor failed, oldest first. Rows that no longer parse are
+    /// reported and skipped.
+    pub fn incomplete(&self) -> rusqlite::Result<Vec<StoredSession>> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare(
+            "SELECT session_id, message, message_encoding, signing_domain, key_agg_ctx, nonces, phase, updated_at_ms
+             FROM sessions
+             WHERE phase NOT IN (?1, ?2, ?3)
+             ORDER BY updated_at_ms",
+        )?;
+        let rows = statement.query_map(
+            params![
+                StoredPhase::Completed.as_str(),
+                StoredPhase::Failed.as_str(),
+                StoredPhase::Cancelled.as_str(),
+            ],
+            |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, String>(3)?,
+                    row.get::<_, Vec<u8>>(4)?,
+                    row.get::<_, String>(5)?,
+                    row.get::<_, String>(6)?,
+                    row.get::<_, i64>(7)?,
+                ))
+            },
+        )?;
+
+        let mut sessions = Vec::new();
+        for row in rows {
+            let (session_id, message, encoding, domain, key_agg_ctx, nonces, phase, updated) = row?;
+            match parse_session(
+                &session_id,
+                message,
+                &encoding,
+                &domain,
+                &key_agg_ctx,
+                &nonces,
+                &phase,
+                updated,
+            ) {
+                Ok(session) => sessions.push(session),
+                Err(e) => eprintln!("Skipping unreadable stored session {}: {}", session_id, e),
+            }
+        }
+        Ok(sessions)
+    }
+}
+
+impl SignerRegistry for SessionStore {
+    fn register(&self, public_key: PublicKey, address: &str) -> Result<Option<usize>, String> {
+        let mut connection = self.connection.lock().unwrap();
+        // Taking the write lock up front keeps two operators from handing out the same index
+        let tx = connection
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .map_err(|e| e.to_string())?;
+        let public_key = hex::encode(public_key.serialize());
+
+        let registered: bool = tx
+            .query_row(
+                "SELECT EXISTS (SELECT 1 FROM signers WHERE public_key = ?1)",
+                params![public_key],
+                |row| row.get(0),
+            )
+            .map_err(|e| e.to_string())?;
+        if registered {
+            return Ok(None);
+        }
+
+        let index: i64 = tx
+            .query_row(
+                "SELECT value FROM next_signer_index WHERE id = 0",
+                [],
+                |row| row.get(0),
+            )
+            .map_err(|e| e.to_string())?;
+        tx.execute(
+            "INSERT INTO signers (signer_index, public_key, address) VALUES (?1, ?2, ?3)",
+            params![index, public_key, address],
+        )
+        .map_err(|e| e.to_string())?;
+        tx.execute(
+            "UPDATE next_signer_index SET value = ?1 WHERE id = 0",
+            params![index + 1],
+        )
+        .map_err(|e| e.to_string())?;
+        tx.commit().map_err(|e| e.to_string())?;
+        Ok(Some(index as usize))
+    }
+
+    fn deregister(&self, public_key: PublicKey) -> Result<Option<usize>, String> {
+        let connection = self.connection.lock().unwrap();
+        let index: Option<i64> = connection
+            .query_row(
+                "DELETE FROM signers WHERE public_key = ?1 RETURNING signer_index",
+                params![hex::encode(public_key.serialize())],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|e| e.to_string())?;
+        Ok(index.map(|index| index as usize))
+    }
+
+    fn set_address(&self, public_key: PublicKey, address: &str) -> Result<(), String> {
+        let updated = self
+            .connection
+            .lock()
+            .unwrap()
+            .execute(
+                "UPDATE signers SET address = ?2 WHERE public_key = ?1",
+                params![hex::encode(public_key.serialize()), address],
+            )
+            .map_err(|e| e.to_string())?;
+        if updated == 0 {
+            return Err(format!(
+                "No registered signer with public key {}",
+                public_key
+            ));
+        }
+        Ok(())
+    }
+
+    fn replace(&self, signers: &[Participant]) -> Result<(), String> {
+        let mut connection = self.connection.lock().unwrap();
+        let tx = connection
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .map_err(|e| e.to_string())?;
+        tx.execute("DELETE FROM signers", [])
+            .map_err(|e| e.to_string())?;
+        for p in signers {
+            tx.execute(
+                "INSERT INTO signers (signer_index, public_key, address) VALUES (?1, ?2, ?3)",
+                params![
+                    p.index as i64,
+                    hex::encode(p.public_key.serialize()),
+                    p.address
+                ],
+            )
+            .map_err(|e| e.to_string())?;
+        }
+        let next_index = signers.iter().map(|p| p.index + 1).max().unwrap_or(0);
+        tx.execute(
+            "UPDATE next_signer_index SET value = ?1 WHERE id = 0",
+            params![next_index as i64],
+        )
+        .map_err(|e| e.to_string())?;
+        tx.commit().map_err(|e| e.to_string())
+    }
+
+    fn signers(&self) -> Result<Vec<Participant>, String> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection
+            .prepare("SELECT signer_index, public_key, address FROM signers ORDER BY signer_index")
+            .map_err(|e| e.to_string())?;
+        let rows = statement
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                ))
+            })
+            .map_err(|e| e.to_string())?;
+
+        let mut signers = Vec::new();
+        for (position, row) in rows.enumerate() {
+            let (index, public_key, address) = row.map_err(|e| e.to_string())?;
+            signers.push(Participant {
+                index: index as usize,
+                position,
+                public_key: public_key_from_hex(&public_key).map_err(|e| e.to_string())?,
+                address,
+            });
+        }
+        Ok(signers)
+    }
+}
+
+/// One session's row, through which its ceremony reports progress. Write failures are only
+/// logged, as losing the record mustn't fail the ceremony. A ceremony that bails out drops its
+/// progress unfinished, which marks the session failed; a crash leaves it incomplete.
+pub struct SessionProgress {
+    store: Arc<SessionStore>,
+    session_id: String,
+    finished: bool,
+}
+
+impl SessionProgress {
+    pub fn begin(store: Arc<SessionStore>, session: &SigningSession) -> Self {
+        log_failure(&session.session_id, store.begin(session));
+        Self {
+            store,
+            session_id: session.session_id.clone(),
+            finished: false,
+        }
+    }
+
+    pub fn nonces_collected(&self, nonces: &HashMap<usize, PubNonce>) {
+        log_failure(
+            &self.session_id,
+            self.store.record_nonces(&self.session_id, nonces),
+        );
+    }
+
+    pub fn distributing(&self) {
+        log_failure(
+            &self.session_id,
+            self.store
+                .set_phase(&self.session_id, StoredPhase::Distributing),
+        );
+    }
+
+    pub fn complete(mut self) {
+        self.finished = true;
+        log_failure(
+            &self.session_id,
+            self.store
+                .set_phase(&self.session_id, StoredPhase::Completed),
+        );
+    }
+}
+
+impl Drop for SessionProgress {
+    fn drop(&mut self) {
+        if !self.finished {
+            log_failure(
+                &self.session_id,
+                self.store.set_phase(&self.session_id, StoredPhase::Failed),
+            );
+        }
+    }
+}
+
+fn log_failure(session_id: &str, result: rusqlite::Result<()>) {
+    if let Err(e) = result {
+        eprintln!("Failed to persist session {}: {}", session_id, e);
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn parse_session(
+    session_id: &str,
+    message: String,
+    encoding: &str,
+    domain: &str,
+    key_agg_ctx: &[u8],
+    nonces: &str,
+    phase: &str,
+    updated_at_ms: i64,
+) -> Result<StoredSession, String> {
+    let nonces: BTreeMap<usize, String> =
+        serde_json::from_str(nonces).map_err(|e| e.to_string())?;
+    let nonces = nonces
+        .into_iter()
+        .map(|(index, nonce)| {
+            let bytes = hex::decode(nonce).map_err(|e| e.to_string())?;
+            let nonce = PubNonce::from_bytes(&bytes).map_err(|e| e.to_string())?;
+            Ok((index, nonce))
+        })
+        .collect::<Result<_, String>>()?;
+
+    Ok(StoredSession {
+        session_id: session_id.to_string(),
+        message,
+        message_encoding: serde_json::from_str(encoding).map_err(|e| e.to_string())?,
+        signing_domain: serde_json::from_str(domain).map_err(|e| e.to_string())?,
+        key_agg_ctx: KeyAggContext::from_bytes(key_agg_ctx).map_err(|e| e.to_string())?,
+        nonces,
+        phase: StoredPhase::parse(phase).ok_or_else(|| format!("unknown phase {}", phase))?,
+        updated_at_ms: updated_at_ms as u64,
+    })
+}
+
+fn to_json<T: Serialize>(value: &T) -> String {
+    serde_json::to_string(value).expect("plain enums and maps always serialize")
+}
+
+fn now_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now| now.as_millis() as i64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::secp::SECP256K1;
+    use secp256k1::SecretKey;
+    use std::path::PathBuf;
+    use uuid::Uuid;
+
+    fn public_key() -> PublicKey {
+        PublicKey::from_secret_key(&SECP256K1, &SecretKey::new(&mut rand::thread_rng()))
+    }
+
+    fn database() -> PathBuf {
+        std::env::temp_dir().join(format!("sessions-{}.db", Uuid::new_v4()))
+    }
+
+    fn session(session_id: &str) -> SigningSession {
+        SigningSession {
+            session_id: session_id.to_string(),
+            message: "hello".to_string(),
+            message_encoding: MessageEncoding::default(),
+            signing_domain: SigningDomain::default(),
+            key_agg_ctx: KeyAggContext::new([public_key(), public_key()]).unwrap(),
+        }
+    }
+
+    #[test]
+    fn interrupted_sessions_are_listed_after_a_reopen() {
+        let path = database();
+        let store = Arc::new(SessionStore::open(&path).unwrap());
+        let interrupted = session("interrupted");
+        let nonce = [public_key().serialize(), public_key().serialize()].concat();
+        let nonces = HashMap::from([(0, PubNonce::from_bytes(&nonce).unwrap())]);
+        // Its ceremony is still running when the operator "crashes"
+        let progress = SessionProgress::begin(store.clone(), &interrupted);
+        progress.nonces_collected(&nonces);
+        SessionProgress::begin(store.clone(), &session("completed")).complete();
+        // A ceremony bailing out drops its progress unfinished
+        drop(SessionProgress::begin(store.clone(), &session("failed")));
+
+        let reopened = SessionStore::open(&path).unwrap();
+        let incomplete = reopened.incomplete().unwrap();
+        assert_eq!(incomplete.len(), 1);
+        assert_eq!(incomplete[0].session_id, "interrupted");
+        assert_eq!(incomplete[0].phase, StoredPhase::CollectingPartials);
+        assert_eq!(incomplete[0].nonces, nonces.into_iter().collect());
+        assert_eq!(
+            incomplete[0].key_agg_ctx.serialize(),
+            interrupted.key_agg_ctx.serialize()
+        );
+        assert!(reopened.in_use("completed").unwrap());
+        assert!(reopened.in_use("interrupted").unwrap());
+        assert!(!reopened.in_use("failed").unwrap());
+        assert!(!reopened.in_use("unknown").unwrap());
+
+        reopened
+            .set_phase("interrupted", StoredPhase::Cancelled)
+            .unwrap();
+        assert!(reopened.incomplete().unwrap().is_empty());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn operators_sharing_a_database_share_their_signers() {
+        let path = database();
+        let (first, second) = (
+            SessionStore::open(&path).unwrap(),
+            SessionStore::open(&path).unwrap(),
+        );
+        let keys = [public_key(), public_key()];
+        assert_eq!(first.register(keys[0], "http://a"), Ok(Some(0)));
+        assert_eq!(second.register(keys[0], "http://b"), Ok(None));
+        assert_eq!(second.register(keys[1], "http://b"), Ok(Some(1)));
+
+        second.set_address(keys[0], "http://standby").unwrap();
+        assert_eq!(first.deregister(keys[1]), Ok(Some(1)));
+        assert_eq!(
+            first.signers(),
+            Ok(vec![Participant {
+                index: 0,
+                position: 0,
+                public_key: keys[0],
+                address: "http://standby".to_string(),
+            }])
+        );
+        // Freed indices aren't handed out again by either operator
+        assert_eq!(second.register(public_key(), "http://c"), Ok(Some(2)));
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -1,13 +1,22 @@
 use crate::serde_utils::{
-    deserialize_compact_signature, deserialize_key_agg_ctx, deserialize_partial_sig_map,
-    deserialize_partial_signature, deserialize_public_key, serialize_compact_signature,
-    serialize_key_agg_ctx, serialize_partial_sig_map, serialize_partial_signature,
-    serialize_public_key,
+    deserialize_aggregated_pubkey, deserialize_compact_signature, deserialize_key_agg_ctx,
+    deserialize_nonce_map, deserialize_optional_aggregated_pubkey,
+    deserialize_optional_compact_signature, deserialize_optional_key_agg_ctx,
+    deserialize_optional_public_key, deserialize_optional_scalar,
+    deserialize_optional_taproot_commitment, deserialize_partial_sig_map,
+    deserialize_partial_signature, deserialize_public_key, deserialize_scalar,
+    deserialize_tweak_map, deserialize_xonly_public_key, serialize_aggregated_pubkey,
+    serialize_compact_signature, serialize_key_agg_ctx, serialize_nonce_map,
+    serialize_optional_aggregated_pubkey, serialize_optional_compact_signature,
+    serialize_optional_key_agg_ctx, serialize_optional_public_key, serialize_optional_scalar,
+    serialize_optional_taproot_commitment, serialize_partial_sig_map, serialize_partial_signature,
+    serialize_public_key, serialize_scalar, serialize_tweak_map, serialize_xonly_public_key,
 };
-use musig2::{CompactSignature, KeyAggContext, PartialSignature};
-use secp256k1::PublicKey;
+use musig2::secp::Scalar;
+use musig2::{CompactSignature, KeyAggContext, PartialSignature, PubNonce};
+use secp256k1::{PublicKey, XOnlyPublicKey};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 #[derive(Serialize, Deserialize)]
 pub struct SignerRegistrationRequest {
@@ -19,15 +28,206 @@ pub struct SignerRegistrationRequest {
     pub public_key: PublicKey,
 }
 
+/// Body of `POST /validate-keyset`: a candidate signer set, as hex-encoded pubkeys in key order.
+#[derive(Serialize, Deserialize)]
+pub struct ValidateKeysetRequest {
+    pub public_keys: Vec<String>,
+}
+
+/// Reply to `POST /validate-keyset`, with either the aggregated key or why there is none.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ValidateKeysetResponse {
+    pub valid: bool,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_optional_aggregated_pubkey",
+        deserialize_with = "deserialize_optional_aggregated_pubkey"
+    )]
+    pub aggregated_pubkey: Option<PublicKey>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Body of `POST /deregister`, sent by a signer leaving the operator's signer set.
+#[derive(Serialize, Deserialize)]
+pub struct SignerDeregistrationRequest {
+    #[serde(
+        serialize_with = "serialize_public_key",
+        deserialize_with = "deserialize_public_key"
+    )]
+    pub public_key: PublicKey,
+}
+
+/// Reply of a standby signer to `POST /activate`.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ActivateResponse {
+    #[serde(
+        serialize_with = "serialize_public_key",
+        deserialize_with = "deserialize_public_key"
+    )]
+    pub public_key: PublicKey,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Participant {
+    /// Index the signer was registered under, which stays the same for as long as it is
+    /// registered; this is how the signer is named in logs and errors
+    pub index: usize,
+    /// Position in the list the participant belongs to. In a ceremony's participant set, which
+    /// is sorted by key, it is the signer's index in the key aggregation context.
+    pub position: usize,
+    #[serde(
+        serialize_with = "serialize_public_key",
+        deserialize_with = "deserialize_public_key"
+    )]
+    pub public_key: PublicKey,
+    pub address: String,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct SigningRequest {
     pub message: String,
+    /// Session ID to run the ceremony under; a random UUID is generated when unset
+    #[serde(default)]
+    pub session_id: Option<String>,
+    /// Retry counter mixed into deterministic session IDs
+    #[serde(default)]
+    pub attempt: u32,
+    /// Extra encodings to return the final signature in, next to the hex `aggregated_signature`
+    #[serde(default)]
+    pub encodings: Vec<SignatureEncoding>,
+    /// Tweaks applied to individual participant keys before aggregation, keyed by pubkey
+    #[serde(
+        default,
+        serialize_with = "serialize_tweak_map",
+        deserialize_with = "deserialize_tweak_map"
+    )]
+    pub tweaks: HashMap<PublicKey, Scalar>,
+    /// Sign with only this many of the registered signers, picking the healthiest ones
+    #[serde(default)]
+    pub signer_count: Option<usize>,
+    #[serde(default)]
+    pub message_encoding: MessageEncoding,
+    #[serde(default)]
+    pub signing_domain: SigningDomain,
+    /// Return every signer's public nonce, so the aggregated nonce can be recomputed
+    #[serde(default)]
+    pub include_nonces: bool,
+    /// Return how long each phase of the ceremony took
+    #[serde(default)]
+    pub include_timings: bool,
+    /// Tapscript merkle root to commit the aggregated key to, as a BIP341 Taproot output key,
+    /// or `""` for an output without scripts
+    #[serde(
+        default,
+        serialize_with = "serialize_optional_taproot_commitment",
+        deserialize_with = "deserialize_optional_taproot_commitment"
+    )]
+    pub taproot_merkle_root: Option<TaprootCommitment>,
+}
+
+/// How the `message` string maps to the bytes that are signed.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MessageEncoding {
+    /// The UTF-8 bytes of the string itself
+    #[default]
+    Utf8,
+    /// Hex-encoded raw bytes, e.g. a digest
+    Hex,
+}
+
+impl MessageEncoding {
+    pub fn decode(self, message: &str) -> Result<Vec<u8>, String> {
+        match self {
+            MessageEncoding::Utf8 => Ok(message.as_bytes().to_vec()),
+            MessageEncoding::Hex => {
+                hex::decode(message).map_err(|e| format!("Message is not valid hex: {}", e))
+            }
+        }
+    }
+}
+
+/// Convention the signed bytes are derived under.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SigningDomain {
+    /// The message bytes are signed as they are
+    #[default]
+    Raw,
+    /// Bitcoin's `signmessage` digest: double SHA-256 over the prefixed message
+    BitcoinMessage,
+}
+
+/// Wire encodings the final signature can be returned in.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum SignatureEncoding {
+    Hex,
+    Base64,
+    /// Raw bytes, as a JSON array of numbers
+    Bytes,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SignBatchRequest {
+    pub messages: Vec<String>,
+    /// Extra signature encodings, applied to every message
+    #[serde(default)]
+    pub encodings: Vec<SignatureEncoding>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SignBatchResponse {
+    /// Identifies the batch for `GET /session/{id}/messages`
+    pub session_id: String,
+    pub responses: Vec<SigningResponse>,
+}
+
+/// Result of `POST /sign/upload`: the signature covers the payload's SHA-256 digest, which is
+/// returned hex-encoded in `message`.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SignUploadResponse {
+    pub payload_size: u64,
+    #[serde(flatten)]
+    pub response: SigningResponse,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MessageStatus {
+    Pending,
+    Signed,
+    Failed,
+}
+
+/// Operator state moved between instances by `POST /admin/export` and `POST /admin/import`.
+///
+/// In-flight ceremonies are not part of it: their rounds live on the signers and can't be
+/// resumed elsewhere, so they are dropped on import.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct OperatorState {
+    pub signers: Vec<Participant>,
+    pub session_messages: HashMap<String, Vec<SessionMessage>>,
+}
+
+/// A message covered by a session, as reported by `GET /session/{id}/messages`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SessionMessage {
+    pub message: String,
+    pub encodings: Vec<SignatureEncoding>,
+    pub status: MessageStatus,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct SigningSession {
     pub session_id: String,
     pub message: String,
+    #[serde(default)]
+    pub message_encoding: MessageEncoding,
+    #[serde(default)]
+    pub signing_domain: SigningDomain,
     #[serde(
         serialize_with = "serialize_key_agg_ctx",
         deserialize_with = "deserialize_key_agg_ctx"
@@ -39,20 +239,97 @@ pub struct SigningSession {
 pub struct GenerateNonceRequest {
     pub session_id: String,
     pub message: String,
+    #[serde(default)]
+    pub message_encoding: MessageEncoding,
+    #[serde(default)]
+    pub signing_domain: SigningDomain,
+    /// Full serialized context; when omitted, the signer rebuilds it from `participants`
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_optional_key_agg_ctx",
+        deserialize_with = "deserialize_optional_key_agg_ctx"
+    )]
+    pub key_agg_ctx: Option<KeyAggContext>,
+    /// Key a context rebuilt from `participants` must aggregate to
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_optional_public_key",
+        deserialize_with = "deserialize_optional_public_key"
+    )]
+    pub aggregated_pubkey: Option<PublicKey>,
+    pub signer_index: usize,
+    /// Consume a previously reserved nonce instead of generating a fresh one
+    #[serde(default)]
+    pub reservation_id: Option<String>,
+    /// Participant set the context is claimed to be built from, ordered by index
+    #[serde(default)]
+    pub participants: Vec<Participant>,
+    /// Tweak applied to this signer's key before aggregation
+    #[serde(
+        default,
+        serialize_with = "serialize_optional_scalar",
+        deserialize_with = "deserialize_optional_scalar"
+    )]
+    pub tweak: Option<Scalar>,
+    /// Taproot merkle root a context rebuilt from `participants` is tweaked with
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_optional_taproot_commitment",
+        deserialize_with = "deserialize_optional_taproot_commitment"
+    )]
+    pub taproot_merkle_root: Option<TaprootCommitment>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ReserveNoncesRequest {
     #[serde(
         serialize_with = "serialize_key_agg_ctx",
         deserialize_with = "deserialize_key_agg_ctx"
     )]
     pub key_agg_ctx: KeyAggContext,
     pub signer_index: usize,
+    pub count: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ReservedNonce {
+    pub reservation_id: String,
+    pub public_nonce: Vec<u8>,
+}
+
+/// Reply to `GET /aggregated-pubkey`.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AggregatedPubkeyResponse {
+    #[serde(
+        serialize_with = "serialize_aggregated_pubkey",
+        deserialize_with = "deserialize_aggregated_pubkey"
+    )]
+    pub aggregated_pubkey: PublicKey,
+    pub signer_count: usize,
+}
+
+/// Milliseconds spent in each phase of a ceremony.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
+pub struct PhaseTimings {
+    pub nonce_collection_ms: f64,
+    pub partial_collection_ms: f64,
+    pub distribution_ms: f64,
+    pub verification_ms: f64,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct SigningResponse {
     pub session_id: String,
+    /// The message exactly as the request gave it, in `message_encoding`
+    pub message: String,
+    #[serde(default)]
+    pub message_encoding: MessageEncoding,
     #[serde(
-        serialize_with = "serialize_public_key",
-        deserialize_with = "deserialize_public_key"
+        serialize_with = "serialize_aggregated_pubkey",
+        deserialize_with = "deserialize_aggregated_pubkey"
     )]
     pub aggregated_pubkey: PublicKey,
     #[serde(
@@ -60,7 +337,59 @@ pub struct SigningResponse {
         deserialize_with = "deserialize_compact_signature"
     )]
     pub aggregated_signature: CompactSignature,
-    pub is_signature_valid: bool,
+    /// `None` when verification was skipped by the operator's verify policy
+    pub is_signature_valid: Option<bool>,
+    /// The final signature in each encoding the request asked for
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub encoded_signatures: BTreeMap<SignatureEncoding, serde_json::Value>,
+    /// Hex-encoded public nonce of each signer by index, when the request asked for them
+    #[serde(
+        default,
+        skip_serializing_if = "BTreeMap::is_empty",
+        serialize_with = "serialize_nonce_map",
+        deserialize_with = "deserialize_nonce_map"
+    )]
+    pub public_nonces: BTreeMap<usize, PubNonce>,
+    /// Phase durations of the ceremony, when the request asked for them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timings: Option<PhaseTimings>,
+    /// Keys and tweak of the Taproot output, when the request gave a merkle root
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub taproot: Option<TaprootKeys>,
+}
+
+/// What a BIP341 Taproot output key commits to besides the signers' aggregated key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TaprootCommitment {
+    /// No script tree, so the output can only be spent through the key path (BIP86)
+    KeyPathOnly,
+    /// The merkle root of a tapscript tree
+    ScriptTree([u8; 32]),
+}
+
+/// The pieces of a Taproot output needed to build script-path control blocks.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TaprootKeys {
+    /// Aggregated key of the signers before the Taproot tweak
+    #[serde(
+        serialize_with = "serialize_xonly_public_key",
+        deserialize_with = "deserialize_xonly_public_key"
+    )]
+    pub internal_key: XOnlyPublicKey,
+    /// BIP341 tweak `t = H_TapTweak(internal_key || merkle_root)`, with an empty merkle root
+    /// for a key-path-only output
+    #[serde(
+        serialize_with = "serialize_scalar",
+        deserialize_with = "deserialize_scalar"
+    )]
+    pub tweak: Scalar,
+    #[serde(
+        serialize_with = "serialize_xonly_public_key",
+        deserialize_with = "deserialize_xonly_public_key"
+    )]
+    pub output_key: XOnlyPublicKey,
+    /// Parity of the output key's y coordinate (0 even, 1 odd), which control blocks carry
+    pub output_key_parity: u8,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -76,6 +405,8 @@ pub struct ReceiveNoncesResponse {
         deserialize_with = "deserialize_partial_signature"
     )]
     pub partial_signature: PartialSignature,
+    /// Hash of the message the partial signature was made over
+    pub message_hash: String,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -96,3 +427,106 @@ pub struct ReceivePartialSignaturesResponse {
     )]
     pub final_signature: CompactSignature,
 }
+
+/// The final signature of a session the operator finalized itself, sent to every participant.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FinalSignatureRequest {
+    pub session_id: String,
+    #[serde(
+        serialize_with = "serialize_compact_signature",
+        deserialize_with = "deserialize_compact_signature"
+    )]
+    pub final_signature: CompactSignature,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DeclineRequest {
+    pub declined: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionPhase {
+    AwaitingNonces,
+    AwaitingPartialSignatures,
+    Finalizing,
+}
+
+/// Phase of a ceremony as seen by the operator.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CeremonyPhase {
+    CollectingNonces,
+    CollectingPartials,
+    /// Partial signatures are with the signers, which finalize them
+    Distributing,
+    Complete,
+    Failed,
+}
+
+/// Progress of a ceremony, as reported by `GET /status/{session_id}`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SessionStatus {
+    pub session_id: String,
+    pub phase: CeremonyPhase,
+    /// Signers that answered in the current phase
+    pub responded: usize,
+    pub signer_count: usize,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_optional_compact_signature",
+        deserialize_with = "deserialize_optional_compact_signature"
+    )]
+    pub final_signature: Option<CompactSignature>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SessionInfo {
+    pub session_id: String,
+    pub phase: SessionPhase,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ClearSessionsResponse {
+    pub cleared: usize,
+}
+
+/// Reply of a signer to `GET /health`, for readiness probes.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct HealthResponse {
+    #[serde(
+        serialize_with = "serialize_public_key",
+        deserialize_with = "deserialize_public_key"
+    )]
+    pub public_key: PublicKey,
+    /// Rounds held for sessions in flight, first and second rounds alike
+    pub active_sessions: usize,
+}
+
+/// Misbehavior a signer can be told to show in its next ceremony (`fault-injection` feature).
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Fault {
+    /// Return a partial signature that doesn't verify
+    CorruptPartialSignature,
+    /// Return a final signature that differs from the other signers'
+    MismatchedFinalSignature,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FaultRequest {
+    /// `None` clears a pending fault
+    pub fault: Option<Fault>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages_decode_to_their_signed_bytes() {
+        assert_eq!(MessageEncoding::Utf8.decode("00ff"), Ok(b"00ff".to_vec()));
+        assert_eq!(MessageEncoding::Hex.decode("00ff"), Ok(vec![0x00, 0xff]));
+    }
+}
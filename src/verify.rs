@@ -0,0 +1,273 @@
+use crate::key_agg::taproot_tweak;
+use crate::secp::verify_schnorr;
+use crate::types::TaprootCommitment;
+use musig2::secp::Point;
+use musig2::{BatchVerificationRow, CompactSignature, KeyAggContext};
+use secp256k1::PublicKey;
+
+/// How many times the operator verifies an aggregated signature before returning it.
+///
+/// - `none` trusts the signers and skips verification entirely; a faulty or malicious signer
+///   can then make the operator hand out an invalid signature.
+/// - `single` verifies once, which catches any invalid aggregate.
+/// - `double` verifies twice and requires both to agree, guarding against transient
+///   computation faults at the cost of another verification per ceremony.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum VerifyPolicy {
+    None,
+    #[default]
+    Single,
+    Double,
+}
+
+impl VerifyPolicy {
+    /// Runs `verify` as often as the policy requires, or returns `None` if it was skipped.
+    pub fn apply(self, mut verify: impl FnMut() -> bool) -> Option<bool> {
+        match self {
+            VerifyPolicy::None => None,
+            VerifyPolicy::Single => Some(verify()),
+            VerifyPolicy::Double => {
+                let first = verify();
+                let second = verify();
+                Some(first && second)
+            }
+        }
+    }
+}
+
+/// Outcome of checking a signature against a known participant set.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ParticipantSetVerification {
+    /// The participant set aggregates to the expected key
+    pub key_matches: bool,
+    /// The signature is valid under the key the participant set aggregates to
+    pub signature_valid: bool,
+}
+
+impl ParticipantSetVerification {
+    /// Whether the signature was made by exactly the expected group.
+    pub fn is_valid(&self) -> bool {
+        self.key_matches && self.signature_valid
+    }
+}
+
+/// Verifies that `signature` was made by the group of `pubkeys`, in that order and committed to
+/// the Taproot `commitment` if given, rather than merely under some key. The key aggregation
+/// context is rebuilt from the pubkeys, its key compared against `expected_key` and the
+/// signature verified under it. Fails only if no context can be built from the pubkeys.
+pub fn verify_participant_set(
+    pubkeys: &[PublicKey],
+    commitment: Option<&TaprootCommitment>,
+    expected_key: PublicKey,
+    signature: CompactSignature,
+    message: &[u8],
+) -> Result<ParticipantSetVerification, String> {
+    // `KeyAggContext::new` panics on an empty key set
+    if pubkeys.is_empty() {
+        return Err("Participant set is empty".to_string());
+    }
+    let mut key_agg_ctx = KeyAggContext::new(pubkeys.to_vec())
+        .map_err(|e| format!("Failed to create key aggregation context: {}", e))?;
+    if let Some(commitment) = commitment {
+        key_agg_ctx = taproot_tweak(key_agg_ctx, commitment)?.0;
+    }
+
+    let aggregated_pubkey: PublicKey = key_agg_ctx.aggregated_pubkey();
+    Ok(ParticipantSetVerification {
+        key_matches: aggregated_pubkey == expected_key,
+        signature_valid: verify_schnorr(aggregated_pubkey, signature, message).is_ok(),
+    })
+}
+
+/// Verifies many aggregated signatures, returning the indices of the invalid ones.
+///
+/// Everything is first checked with a single BIP340 batch verification, which is much cheaper
+/// than verifying each signature on its own. Only when the batch fails are the signatures
+/// verified one by one to find out which ones are bad.
+pub fn verify_batch(items: &[(PublicKey, CompactSignature, &[u8])]) -> Result<(), Vec<usize>> {
+    let mut rows = Vec::with_capacity(items.len());
+    let mut failed = Vec::new();
+
+    for (i, (pubkey, signature, message)) in items.iter().enumerate() {
+        match signature.lift_nonce() {
+            Ok(lifted) => {
+                // BIP340 keys are x-only, so verify against the even-parity point
+                let pubkey = Point::from(*pubkey).to_even_y();
+                rows.push(BatchVerificationRow::from_signature(
+                    pubkey, message, lifted,
+                ));
+            }
+            Err(_) => failed.push(i),
+        }
+    }
+
+    if failed.is_empty() && musig2::verify_batch(&rows).is_ok() {
+        return Ok(());
+    }
+
+    Err(items
+        .iter()
+        .enumerate()
+        .filter(|(_, (pubkey, signature, message))| {
+            verify_schnorr(*pubkey, *signature, message).is_err()
+        })
+        .map(|(i, _)| i)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::secp::SECP256K1;
+    use secp256k1::{Keypair, SecretKey};
+
+    /// A fresh key and its BIP340 signature over `message`.
+    fn signed(message: &[u8]) -> (PublicKey, CompactSignature) {
+        let keypair = Keypair::new(&SECP256K1, &mut rand::thread_rng());
+        let signature = SECP256K1.sign_schnorr_no_aux_rand(message, &keypair);
+        (
+            keypair.public_key(),
+            CompactSignature::from_bytes(&signature.serialize()).unwrap(),
+        )
+    }
+
+    #[test]
+    fn policies_verify_as_often_as_they_require() {
+        for (policy, expected_runs) in [
+            (VerifyPolicy::None, 0),
+            (VerifyPolicy::Single, 1),
+            (VerifyPolicy::Double, 2),
+        ] {
+            let mut runs = 0;
+            let outcome = policy.apply(|| {
+                runs += 1;
+                true
+            });
+            assert_eq!(runs, expected_runs);
+            assert_eq!(outcome, (expected_runs > 0).then_some(true));
+        }
+    }
+
+    #[test]
+    fn double_verification_requires_both_runs_to_agree() {
+        let mut results = [true, false].into_iter();
+        assert_eq!(
+            VerifyPolicy::Double.apply(|| results.next().unwrap()),
+            Some(false)
+        );
+    }
+
+    #[test]
+    fn valid_batches_pass() {
+        let messages: [&[u8]; 3] = [b"first", b"second", b"third"];
+        let items: Vec<_> = messages
+            .iter()
+            .map(|message| {
+                let (pubkey, signature) = signed(message);
+                (pubkey, signature, *message)
+            })
+            .collect();
+        assert_eq!(verify_batch(&items), Ok(()));
+        assert_eq!(verify_batch(&[]), Ok(()));
+    }
+
+    #[test]
+    fn invalid_signatures_are_reported_by_index() {
+        let messages: [&[u8]; 4] = [b"first", b"second", b"third", b"fourth"];
+        let mut items: Vec<_> = messages
+            .iter()
+            .map(|message| {
+                let (pubkey, signature) = signed(message);
+                (pubkey, signature, *message)
+            })
+            .collect();
+        // A signature over another message, and one under another key
+        items[1].2 = b"tampered";
+        items[3].0 = signed(b"fourth").0;
+        assert_eq!(verify_batch(&items), Err(vec![1, 3]));
+    }
+
+    /// A signature over `message` by the group of `seckeys`, in that order, committed to
+    /// `commitment` if given.
+    fn group_signed(
+        seckeys: &[SecretKey],
+        commitment: Option<&TaprootCommitment>,
+        message: &[u8],
+    ) -> (PublicKey, CompactSignature) {
+        let pubkeys = seckeys
+            .iter()
+            .map(|seckey| PublicKey::from_secret_key(&SECP256K1, seckey));
+        let mut key_agg_ctx = KeyAggContext::new(pubkeys).unwrap();
+        if let Some(commitment) = commitment {
+            key_agg_ctx = taproot_tweak(key_agg_ctx, commitment).unwrap().0;
+        }
+        let seckey: SecretKey = key_agg_ctx
+            .aggregated_seckey(seckeys.iter().copied())
+            .unwrap();
+        let keypair = Keypair::from_secret_key(&SECP256K1, &seckey);
+        let signature = SECP256K1.sign_schnorr_no_aux_rand(message, &keypair);
+        (
+            key_agg_ctx.aggregated_pubkey(),
+            CompactSignature::from_bytes(&signature.serialize()).unwrap(),
+        )
+    }
+
+    #[test]
+    fn signatures_are_bound_to_the_ordered_participant_set() {
+        let seckeys: Vec<SecretKey> = (0..3)
+            .map(|_| SecretKey::new(&mut rand::thread_rng()))
+            .collect();
+        let pubkeys: Vec<PublicKey> = seckeys
+            .iter()
+            .map(|seckey| PublicKey::from_secret_key(&SECP256K1, seckey))
+            .collect();
+        let (key, signature) = group_signed(&seckeys, None, b"group");
+
+        let verification = verify_participant_set(&pubkeys, None, key, signature, b"group");
+        assert!(verification.unwrap().is_valid());
+
+        // Another order is another group, even though the signature itself is fine
+        let reordered = [pubkeys[1], pubkeys[0], pubkeys[2]];
+        let verification = verify_participant_set(&reordered, None, key, signature, b"group");
+        assert_eq!(
+            verification,
+            Ok(ParticipantSetVerification {
+                key_matches: false,
+                signature_valid: false,
+            })
+        );
+        let verification = verify_participant_set(&pubkeys, None, key, signature, b"other");
+        assert_eq!(
+            verification,
+            Ok(ParticipantSetVerification {
+                key_matches: true,
+                signature_valid: false,
+            })
+        );
+        assert_eq!(
+            verify_participant_set(&[], None, key, signature, b"group"),
+            Err("Participant set is empty".to_string())
+        );
+    }
+
+    #[test]
+    fn taproot_signatures_need_the_same_commitment() {
+        let seckeys: Vec<SecretKey> = (0..2)
+            .map(|_| SecretKey::new(&mut rand::thread_rng()))
+            .collect();
+        let pubkeys: Vec<PublicKey> = seckeys
+            .iter()
+            .map(|seckey| PublicKey::from_secret_key(&SECP256K1, seckey))
+            .collect();
+        let commitment = TaprootCommitment::ScriptTree([9; 32]);
+        let (key, signature) = group_signed(&seckeys, Some(&commitment), b"taproot");
+
+        let verification =
+            verify_participant_set(&pubkeys, Some(&commitment), key, signature, b"taproot");
+        assert!(verification.unwrap().is_valid());
+        for other in [None, Some(&TaprootCommitment::KeyPathOnly)] {
+            let verification = verify_participant_set(&pubkeys, other, key, signature, b"taproot");
+            assert!(!verification.unwrap().is_valid());
+        }
+    }
+}
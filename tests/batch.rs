@@ -0,0 +1,79 @@
+mod common;
+
+use common::{get_json, operator_config, post_json, sign, start_operator, start_signers};
+use musig2_example::secp::verify_schnorr;
+use musig2_example::types::SignBatchResponse;
+use serde_json::json;
+
+#[tokio::test]
+async fn batches_are_limited_to_the_configured_size() {
+    let mut config = operator_config();
+    config.max_batch_size = 2;
+    let (_operator, url) = start_operator(config).await;
+    start_signers(&url, 2).await;
+    let sign_batch = format!("{}/sign-batch", url);
+
+    let (status, body) = post_json(&sign_batch, &json!({ "messages": ["a", "b", "c"] })).await;
+    assert_eq!(status, 400);
+    assert_eq!(
+        body["error"],
+        "Batch of 3 messages exceeds the maximum of 2"
+    );
+
+    let (status, body) = post_json(&sign_batch, &json!({ "messages": [] })).await;
+    assert_eq!(status, 400);
+    assert_eq!(body["error"], "Batch must contain at least one message");
+
+    let (status, body) = post_json(&sign_batch, &json!({ "messages": ["a", "b"] })).await;
+    assert_eq!(status, 200);
+    let batch: SignBatchResponse = serde_json::from_value(body).unwrap();
+    assert_eq!(batch.responses.len(), 2);
+    for (response, message) in batch.responses.iter().zip(["a", "b"]) {
+        assert_eq!(response.message, message);
+        assert!(verify_schnorr(
+            response.aggregated_pubkey,
+            response.aggregated_signature,
+            message.as_bytes()
+        )
+        .is_ok());
+    }
+    // Every message had a ceremony of its own
+    assert_ne!(batch.responses[0].session_id, batch.responses[1].session_id);
+}
+
+#[tokio::test]
+async fn sessions_list_their_messages_and_status() {
+    let (_operator, url) = start_operator(operator_config()).await;
+    start_signers(&url, 2).await;
+
+    let request = json!({ "messages": ["a", "b"], "encodings": ["hex"] });
+    let (status, body) = post_json(&format!("{}/sign-batch", url), &request).await;
+    assert_eq!(status, 200);
+    let (status, messages) = get_json(&format!(
+        "{}/session/{}/messages",
+        url,
+        body["session_id"].as_str().unwrap()
+    ))
+    .await;
+    assert_eq!(status, 200);
+    assert_eq!(
+        messages,
+        json!([
+            { "message": "a", "encodings": ["hex"], "status": "signed" },
+            { "message": "b", "encodings": ["hex"], "status": "signed" },
+        ])
+    );
+
+    // A single ceremony is a session of its own
+    let response = sign(&url, &json!({ "message": "single" })).await;
+    let (_, messages) =
+        get_json(&format!("{}/session/{}/messages", url, response.session_id)).await;
+    assert_eq!(
+        messages,
+        json!([{ "message": "single", "encodings": [], "status": "signed" }])
+    );
+
+    let (status, body) = get_json(&format!("{}/session/unknown/messages", url)).await;
+    assert_eq!(status, 404);
+    assert_eq!(body["code"], "unknown_session");
+}
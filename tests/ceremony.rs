@@ -0,0 +1,830 @@
+mod common;
+
+use common::{
+    free_port, get_json, key_agg_ctx, operator_config, pass_through, post_json, random_public_key,
+    register, send, sign, start_operator, start_proxied_signer, start_signer, start_signers,
+    ProxyHook, TestSigner,
+};
+use futures::future::BoxFuture;
+use musig2::secp::{MaybeScalar, Point};
+use musig2::AggNonce;
+use musig2_example::client::HttpClient;
+use musig2_example::commitment::{participant_set_hash, KeyCommitment};
+use musig2_example::hook::{OnSignatureComplete, WebhookHook};
+use musig2_example::key_agg::{tweak_pubkey, KeyAggEncoding};
+use musig2_example::metrics::MetricsSummary;
+use musig2_example::operator::FinalizeAt;
+use musig2_example::secp::verify_schnorr;
+use musig2_example::serde_utils::{decode_signature, scalar_from_hex};
+use musig2_example::session::{deterministic_session_id, signed_bytes};
+use musig2_example::store::SessionStore;
+use musig2_example::types::{MessageEncoding, SignUploadResponse, SigningDomain, SigningResponse};
+use musig2_example::verify::VerifyPolicy;
+use secp256k1::{PublicKey, Scalar};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use warp::Filter;
+
+#[tokio::test]
+async fn response_echoes_the_signed_message() {
+    let (_operator, url) = start_operator(operator_config()).await;
+    start_signers(&url, 2).await;
+
+    let response = sign(&url, &json!({ "message": "Hello, MuSig2!" })).await;
+    assert_eq!(response.message, "Hello, MuSig2!");
+    assert_eq!(response.message_encoding, MessageEncoding::Utf8);
+    assert!(verify_schnorr(
+        response.aggregated_pubkey,
+        response.aggregated_signature,
+        b"Hello, MuSig2!"
+    )
+    .is_ok());
+
+    let bytes = [0x00, 0xff, 0x10, 0x80];
+    let request = json!({ "message": hex::encode(bytes), "message_encoding": "hex" });
+    let response = sign(&url, &request).await;
+    assert_eq!(response.message_encoding, MessageEncoding::Hex);
+    let echoed = response.message_encoding.decode(&response.message).unwrap();
+    assert_eq!(echoed, bytes);
+    assert!(verify_schnorr(
+        response.aggregated_pubkey,
+        response.aggregated_signature,
+        &echoed
+    )
+    .is_ok());
+}
+
+#[tokio::test]
+async fn deterministic_session_ids_follow_the_attempt_counter() {
+    let mut config = operator_config();
+    config.deterministic_session_ids = true;
+    let (_operator, url) = start_operator(config).await;
+    let signers = start_signers(&url, 2).await;
+    let pubkeys: Vec<_> = signers.iter().map(|signer| signer.public_key).collect();
+
+    for attempt in [0, 1] {
+        let response = sign(&url, &json!({ "message": "retry me", "attempt": attempt })).await;
+        assert_eq!(
+            response.session_id,
+            deterministic_session_id(&pubkeys, b"retry me", &HashMap::new(), None, attempt)
+        );
+    }
+}
+
+#[tokio::test]
+async fn deterministic_session_ids_are_claimed_once() {
+    let mut config = operator_config();
+    config.deterministic_session_ids = true;
+    let (_operator, url) = start_operator(config).await;
+    start_signers(&url, 2).await;
+
+    let request = json!({ "message": "sign me once" });
+    let first = sign(&url, &request).await;
+    let (status, body) = post_json(&format!("{}/sign", url), &request).await;
+    assert_eq!(status, 409);
+    assert_eq!(body["code"], "duplicate_session");
+    assert_eq!(
+        body["error"],
+        format!("Session {} already exists", first.session_id)
+    );
+
+    let other_message = sign(&url, &json!({ "message": "sign me too" })).await;
+    assert_ne!(other_message.session_id, first.session_id);
+
+    start_signer(&url).await;
+    let other_signers = sign(&url, &request).await;
+    assert_ne!(other_signers.session_id, first.session_id);
+    assert_ne!(other_signers.session_id, other_message.session_id);
+}
+
+#[tokio::test]
+async fn client_chosen_session_ids_are_used_once() {
+    let path = std::env::temp_dir().join(format!("sessions-{}.db", uuid::Uuid::new_v4()));
+    let mut config = operator_config();
+    config.deterministic_session_ids = true;
+    config.session_store = Some(SessionStore::open(&path).unwrap());
+    let (_operator, url) = start_operator(config).await;
+    start_signers(&url, 2).await;
+
+    let chosen = json!({ "message": "chosen", "session_id": "order-42" });
+    let response = sign(&url, &chosen).await;
+    assert_eq!(response.session_id, "order-42");
+    let (status, body) = post_json(&format!("{}/sign", url), &chosen).await;
+    assert_eq!(status, 409);
+    assert_eq!(body["code"], "duplicate_session");
+    assert_eq!(body["error"], "Session order-42 already exists");
+
+    for malformed in ["", "has spaces", "../status"] {
+        let request = json!({ "message": "chosen", "session_id": malformed });
+        let (status, body) = post_json(&format!("{}/sign", url), &request).await;
+        assert_eq!(status, 400);
+        assert_eq!(
+            body["error"],
+            "Session ID must be 1 to 64 ASCII letters, digits, '-' or '_'"
+        );
+    }
+
+    // An operator restarted on the same database remembers the ID
+    let mut config = operator_config();
+    config.session_store = Some(SessionStore::open(&path).unwrap());
+    let (_restarted, restarted_url) = start_operator(config).await;
+    let (status, body) = post_json(&format!("{}/sign", restarted_url), &chosen).await;
+    assert_eq!(status, 409);
+    assert_eq!(body["code"], "duplicate_session");
+    let response = sign(
+        &restarted_url,
+        &json!({ "message": "chosen", "session_id": "order-43" }),
+    )
+    .await;
+    assert_eq!(response.session_id, "order-43");
+    std::fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn client_chosen_session_ids_are_freed_by_a_failure() {
+    let path = std::env::temp_dir().join(format!("sessions-{}.db", uuid::Uuid::new_v4()));
+    let mut config = operator_config();
+    config.session_store = Some(SessionStore::open(&path).unwrap());
+    let (_operator, url) = start_operator(config).await;
+    start_signer(&url).await;
+    let reluctant = TestSigner::with_options(&url, Some("admin-secret"), None);
+    reluctant.serve().await;
+    assert!(reluctant.signer.register().await.is_ok());
+    let decline = |declined: bool| {
+        send(
+            reqwest::Client::new()
+                .post(format!("{}/decline", reluctant.url))
+                .header("x-admin-token", "admin-secret")
+                .json(&json!({ "declined": declined })),
+        )
+    };
+
+    let chosen = json!({ "message": "retry me", "session_id": "order-7" });
+    decline(true).await;
+    let (status, _) = post_json(&format!("{}/sign", url), &chosen).await;
+    assert_eq!(status, 502);
+    let (_, status) = get_json(&format!("{}/status/order-7", url)).await;
+    assert_eq!(status["phase"], "failed");
+
+    // The client retries under the same ID once the signer is back
+    decline(false).await;
+    let response = sign(&url, &chosen).await;
+    assert_eq!(response.session_id, "order-7");
+    let (status, body) = post_json(&format!("{}/sign", url), &chosen).await;
+    assert_eq!(status, 409);
+    assert_eq!(body["code"], "duplicate_session");
+    std::fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn declining_signers_fail_the_ceremony_until_they_accept_again() {
+    let (_operator, url) = start_operator(operator_config()).await;
+    start_signer(&url).await;
+    let reluctant = TestSigner::with_options(&url, Some("admin-secret"), None);
+    reluctant.serve().await;
+    assert!(reluctant.signer.register().await.is_ok());
+
+    let decline = |declined: bool| {
+        send(
+            reqwest::Client::new()
+                .post(format!("{}/decline", reluctant.url))
+                .header("x-admin-token", "admin-secret")
+                .json(&json!({ "declined": declined })),
+        )
+    };
+
+    let (status, _) = decline(true).await;
+    assert_eq!(status, 200);
+    let (status, body) = post_json(&format!("{}/sign", url), &json!({ "message": "hi" })).await;
+    assert_eq!(status, 502);
+    assert_eq!(body["code"], "signer_failed");
+    let error = body["error"].as_str().unwrap();
+    assert!(error.contains(&reluctant.url), "{}", error);
+    assert!(error.ends_with("declined to participate"), "{}", error);
+
+    let (status, _) = decline(false).await;
+    assert_eq!(status, 200);
+    sign(&url, &json!({ "message": "hi" })).await;
+}
+
+#[tokio::test]
+async fn signers_registering_mid_ceremony_do_not_join_it() {
+    let (_operator, url) = start_operator(operator_config()).await;
+    let first = start_signer(&url).await;
+
+    // Another signer registers while the proxied one is asked for its nonce
+    let operator_url = url.clone();
+    let latecomer = random_public_key();
+    let registered = Arc::new(AtomicBool::new(false));
+    let on_request: ProxyHook = Arc::new(move |path, body| {
+        let (operator_url, registered) = (operator_url.clone(), registered.clone());
+        Box::pin(async move {
+            if path == "/nonce" && !registered.swap(true, Ordering::SeqCst) {
+                register(&operator_url, latecomer, "http://127.0.0.1:1").await;
+            }
+            body
+        })
+    });
+    let (second, _proxy) = start_proxied_signer(&url, on_request, pass_through()).await;
+
+    let response = sign(&url, &json!({ "message": "snapshot" })).await;
+    let mut pubkeys = vec![first.public_key, second.public_key];
+    pubkeys.sort_by_key(|pubkey| pubkey.serialize());
+    let key_agg_ctx = key_agg_ctx(&pubkeys);
+    assert_eq!(
+        response.aggregated_pubkey,
+        key_agg_ctx.aggregated_pubkey::<PublicKey>()
+    );
+    assert!(verify_schnorr(
+        response.aggregated_pubkey,
+        response.aggregated_signature,
+        b"snapshot"
+    )
+    .is_ok());
+
+    let (_, signers) = get_json(&format!("{}/signers", url)).await;
+    assert_eq!(signers.as_array().map(Vec::len), Some(3));
+}
+
+#[tokio::test]
+async fn the_first_aggregated_key_is_committed() {
+    let path = std::env::temp_dir().join(format!("key-commitment-{}.json", uuid::Uuid::new_v4()));
+    let mut config = operator_config();
+    config.key_commitment_path = Some(path.clone());
+    let (_operator, url) = start_operator(config).await;
+    let signers = start_signers(&url, 2).await;
+
+    let response = sign(&url, &json!({ "message": "commit" })).await;
+    let commitment = KeyCommitment::load(&path).unwrap().unwrap();
+    assert_eq!(commitment.aggregated_key, response.aggregated_pubkey);
+    let pubkeys: Vec<_> = signers.iter().map(|signer| signer.public_key).collect();
+    assert_eq!(
+        commitment.participant_set_hash,
+        participant_set_hash(&pubkeys)
+    );
+
+    // A diverging signer set only warns, and leaves the commitment alone
+    start_signer(&url).await;
+    let response = sign(&url, &json!({ "message": "commit" })).await;
+    assert_ne!(response.aggregated_pubkey, commitment.aggregated_key);
+    assert_eq!(KeyCommitment::load(&path).unwrap(), Some(commitment));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn skipped_verification_is_reported_as_unknown() {
+    let mut config = operator_config();
+    config.verify_policy = VerifyPolicy::None;
+    let (_operator, url) = start_operator(config).await;
+    start_signers(&url, 2).await;
+
+    let (status, body) = post_json(&format!("{}/sign", url), &json!({ "message": "trust" })).await;
+    assert_eq!(status, 200);
+    assert_eq!(body["is_signature_valid"], json!(null));
+
+    // The signature is still a valid one, the operator just didn't check
+    let response: SigningResponse = serde_json::from_value(body).unwrap();
+    assert!(verify_schnorr(
+        response.aggregated_pubkey,
+        response.aggregated_signature,
+        b"trust"
+    )
+    .is_ok());
+}
+
+#[tokio::test]
+async fn metrics_count_successes_and_failures() {
+    let (operator, url) = start_operator(operator_config()).await;
+    start_signers(&url, 2).await;
+    sign(&url, &json!({ "message": "measured" })).await;
+    // A signer nobody listens for fails the next ceremony
+    register(&url, random_public_key(), "http://127.0.0.1:1").await;
+    let (status, _) = post_json(&format!("{}/sign", url), &json!({ "message": "measured" })).await;
+    assert_eq!(status, 502);
+
+    let path = std::env::temp_dir().join(format!("metrics-{}.json", uuid::Uuid::new_v4()));
+    operator.write_metrics(&path).unwrap();
+    let summary: MetricsSummary =
+        serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(summary.successes, 1);
+    assert_eq!(summary.failures, 1);
+    assert!(summary.average_phase_latencies.nonce_collection_ms > 0.0);
+}
+
+#[tokio::test]
+async fn signatures_come_back_in_every_requested_encoding() {
+    let (_operator, url) = start_operator(operator_config()).await;
+    start_signers(&url, 2).await;
+
+    let request = json!({ "message": "encoded", "encodings": ["hex", "base64", "bytes"] });
+    let response = sign(&url, &request).await;
+    assert_eq!(response.encoded_signatures.len(), 3);
+    for (encoding, encoded) in &response.encoded_signatures {
+        assert_eq!(
+            decode_signature(encoded, *encoding),
+            Ok(response.aggregated_signature)
+        );
+    }
+
+    let response = sign(&url, &json!({ "message": "encoded" })).await;
+    assert!(response.encoded_signatures.is_empty());
+}
+
+#[tokio::test]
+async fn tweaked_participants_sign_for_the_tweaked_aggregate() {
+    let (_operator, url) = start_operator(operator_config()).await;
+    let mut signers = start_signers(&url, 2).await;
+    signers.sort_by_key(|signer| signer.public_key.serialize());
+
+    let tweak = "03".repeat(32);
+    let request = json!({
+        "message": "tweaked",
+        "tweaks": { (hex::encode(signers[1].public_key.serialize())): tweak },
+    });
+    let response = sign(&url, &request).await;
+
+    let tweak = scalar_from_hex(&tweak).unwrap();
+    let tweaked = tweak_pubkey(signers[1].public_key, tweak).unwrap();
+    let expected = key_agg_ctx(&[signers[0].public_key, tweaked]);
+    assert_eq!(
+        response.aggregated_pubkey,
+        expected.aggregated_pubkey::<PublicKey>()
+    );
+    assert_eq!(response.is_signature_valid, Some(true));
+
+    // Tweaks can only be given for registered signers
+    let request = json!({
+        "message": "tweaked",
+        "tweaks": { (hex::encode(random_public_key().serialize())): "03".repeat(32) },
+    });
+    let (status, body) = post_json(&format!("{}/sign", url), &request).await;
+    assert_eq!(status, 400);
+    assert!(body["error"]
+        .as_str()
+        .unwrap()
+        .starts_with("Tweak given for unregistered public key"));
+}
+
+#[tokio::test]
+async fn tweaks_repeating_a_participant_key_are_refused() {
+    let (_operator, url) = start_operator(operator_config()).await;
+    let signers = start_signers(&url, 2).await;
+
+    // sk0 - sk1 moves the second signer's key onto the first one's
+    let tweak = signers[0]
+        .secret_key
+        .add_tweak(&Scalar::from(signers[1].secret_key.negate()))
+        .unwrap();
+    let request = json!({
+        "message": "duplicated",
+        "tweaks": { (hex::encode(signers[1].public_key.serialize())): hex::encode(tweak.secret_bytes()) },
+    });
+    let (status, body) = post_json(&format!("{}/sign", url), &request).await;
+    assert_eq!(status, 409);
+    assert_eq!(body["code"], "key_aggregation_failed");
+    assert_eq!(
+        body["error"],
+        format!(
+            "Public key {} appears more than once in the participant set",
+            signers[0].public_key
+        )
+    );
+}
+
+#[tokio::test]
+async fn both_finalize_strategies_produce_valid_signatures() {
+    let (_signers_operator, signers_url) = start_operator(operator_config()).await;
+    let signers = start_signers(&signers_url, 2).await;
+    let mut config = operator_config();
+    config.finalize_at = FinalizeAt::Operator;
+    let (_operator, operator_url) = start_operator(config).await;
+    for signer in &signers {
+        register(&operator_url, signer.public_key, &signer.url).await;
+    }
+
+    let request = json!({ "message": "finalized" });
+    let by_signers = sign(&signers_url, &request).await;
+    let by_operator = sign(&operator_url, &request).await;
+    assert_eq!(by_signers.aggregated_pubkey, by_operator.aggregated_pubkey);
+    for response in [by_signers, by_operator] {
+        assert!(verify_schnorr(
+            response.aggregated_pubkey,
+            response.aggregated_signature,
+            b"finalized"
+        )
+        .is_ok());
+        assert_eq!(response.is_signature_valid, Some(true));
+    }
+}
+
+#[tokio::test]
+async fn standbys_take_over_for_unreachable_signers() {
+    let (_operator, url) = start_operator(operator_config()).await;
+    let online = start_signer(&url).await;
+    // The primary is registered, but went down before the ceremony
+    let primary = TestSigner::new(&url);
+    let unreachable = format!("http://127.0.0.1:{}", free_port());
+    register(&url, primary.public_key, &unreachable).await;
+    let standby = TestSigner::with_key(&url, primary.secret_key);
+    standby.serve().await;
+    assert!(standby.signer.register_standby().await.is_ok());
+
+    let response = sign(&url, &json!({ "message": "failover" })).await;
+    assert_eq!(response.is_signature_valid, Some(true));
+    let mut pubkeys = [online.public_key, primary.public_key];
+    pubkeys.sort_by_key(PublicKey::serialize);
+    assert_eq!(
+        response.aggregated_pubkey,
+        key_agg_ctx(&pubkeys).aggregated_pubkey::<PublicKey>()
+    );
+
+    // The primary's index is routed to the standby from now on
+    let (_, signers) = get_json(&format!("{}/signers", url)).await;
+    let addresses: Vec<&str> = signers
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|signer| signer["address"].as_str().unwrap())
+        .collect();
+    assert!(addresses.contains(&standby.url.as_str()));
+    assert!(!addresses.contains(&unreachable.as_str()));
+}
+
+#[tokio::test]
+async fn rebuilt_contexts_sign_like_full_contexts() {
+    let (_full_operator, full_url) = start_operator(operator_config()).await;
+    let signers = start_signers(&full_url, 2).await;
+    let mut config = operator_config();
+    config.key_agg_encoding = KeyAggEncoding::Pubkeys;
+    let (_operator, pubkeys_url) = start_operator(config).await;
+    for signer in &signers {
+        register(&pubkeys_url, signer.public_key, &signer.url).await;
+    }
+
+    let request = json!({ "message": "rebuilt" });
+    let full = sign(&full_url, &request).await;
+    let rebuilt = sign(&pubkeys_url, &request).await;
+    assert_eq!(full.aggregated_pubkey, rebuilt.aggregated_pubkey);
+    for response in [full, rebuilt] {
+        assert!(verify_schnorr(
+            response.aggregated_pubkey,
+            response.aggregated_signature,
+            b"rebuilt"
+        )
+        .is_ok());
+    }
+}
+
+#[tokio::test]
+async fn failing_signers_are_left_out_of_subsets() {
+    let (_operator, url) = start_operator(operator_config()).await;
+    let signers = start_signers(&url, 2).await;
+    let dead = format!("http://127.0.0.1:{}", free_port());
+    register(&url, random_public_key(), &dead).await;
+
+    // A ceremony with everyone fails on the dead signer
+    let everyone = json!({ "message": "subset", "signer_count": 3 });
+    let (status, _) = post_json(&format!("{}/sign", url), &everyone).await;
+    assert_eq!(status, 502);
+
+    let subset = json!({ "message": "subset", "signer_count": 2 });
+    for _ in 0..3 {
+        let response = sign(&url, &subset).await;
+        let mut pubkeys = [signers[0].public_key, signers[1].public_key];
+        pubkeys.sort_by_key(PublicKey::serialize);
+        assert_eq!(
+            response.aggregated_pubkey,
+            key_agg_ctx(&pubkeys).aggregated_pubkey::<PublicKey>()
+        );
+    }
+
+    let (_, statuses) = get_json(&format!("{}/signers", url)).await;
+    for status in statuses.as_array().unwrap() {
+        let health = &status["health"];
+        if status["address"] == dead {
+            assert_eq!(health["successes"], 0);
+            assert!(health["failures"].as_u64().unwrap() >= 1);
+        } else {
+            assert_eq!(health["successes"], 3);
+            assert_eq!(health["failures"], 0);
+            assert!(health["recent_latency_ms"].is_number());
+        }
+    }
+}
+
+#[tokio::test]
+async fn uploads_are_signed_by_their_digest() {
+    let (_operator, url) = start_operator(operator_config()).await;
+    start_signers(&url, 2).await;
+    let payload: Vec<u8> = (0..4 * 1024 * 1024).map(|i| (i % 251) as u8).collect();
+
+    let (status, body) = send(
+        reqwest::Client::new()
+            .post(format!("{}/sign/upload", url))
+            .body(payload.clone()),
+    )
+    .await;
+    assert_eq!(status, 200);
+    let upload: SignUploadResponse = serde_json::from_value(body).unwrap();
+    assert_eq!(upload.payload_size, payload.len() as u64);
+
+    let digest = Sha256::digest(&payload);
+    assert_eq!(upload.response.message, hex::encode(digest));
+    assert!(verify_schnorr(
+        upload.response.aggregated_pubkey,
+        upload.response.aggregated_signature,
+        &digest
+    )
+    .is_ok());
+}
+
+#[tokio::test]
+async fn returned_nonces_recompute_the_signature_nonce() {
+    let (_operator, url) = start_operator(operator_config()).await;
+    start_signers(&url, 2).await;
+
+    let response = sign(&url, &json!({ "message": "audited" })).await;
+    assert!(response.public_nonces.is_empty());
+
+    let response = sign(
+        &url,
+        &json!({ "message": "audited", "include_nonces": true }),
+    )
+    .await;
+    assert_eq!(
+        response.public_nonces.keys().copied().collect::<Vec<_>>(),
+        [0, 1]
+    );
+    let aggregated_nonce: AggNonce = response.public_nonces.values().sum();
+    let nonce_coefficient: MaybeScalar =
+        aggregated_nonce.nonce_coefficient(response.aggregated_pubkey, "audited");
+    let final_nonce: Point = aggregated_nonce.final_nonce(nonce_coefficient);
+    // A BIP340 signature starts with the x-coordinate of its nonce
+    assert_eq!(
+        final_nonce.serialize_xonly(),
+        response.aggregated_signature.serialize()[..32]
+    );
+}
+
+#[tokio::test]
+async fn bitcoin_messages_verify_only_against_their_digest() {
+    let (_operator, url) = start_operator(operator_config()).await;
+    start_signers(&url, 2).await;
+
+    let request = json!({ "message": "hello", "signing_domain": "bitcoin_message" });
+    let response = sign(&url, &request).await;
+    assert_eq!(response.is_signature_valid, Some(true));
+    let digest = signed_bytes(
+        "hello",
+        MessageEncoding::Utf8,
+        SigningDomain::BitcoinMessage,
+    )
+    .unwrap();
+    let verify = |message: &[u8]| {
+        verify_schnorr(
+            response.aggregated_pubkey,
+            response.aggregated_signature,
+            message,
+        )
+    };
+    assert!(verify(&digest).is_ok());
+    assert!(verify(b"hello").is_err());
+}
+
+#[tokio::test]
+async fn timings_are_reported_on_request() {
+    let (_operator, url) = start_operator(operator_config()).await;
+    start_signers(&url, 2).await;
+
+    let (_, body) = post_json(&format!("{}/sign", url), &json!({ "message": "timed" })).await;
+    assert!(body.get("timings").is_none());
+
+    let request = json!({ "message": "timed", "include_timings": true });
+    let timings = sign(&url, &request).await.timings.expect("No timings");
+    for phase in [
+        timings.nonce_collection_ms,
+        timings.partial_collection_ms,
+        timings.distribution_ms,
+        timings.verification_ms,
+    ] {
+        assert!(phase >= 0.0);
+    }
+    assert!(timings.nonce_collection_ms > 0.0);
+}
+
+#[tokio::test]
+async fn results_are_published_to_the_socket() {
+    let path = std::env::temp_dir().join(format!("results-{}.sock", uuid::Uuid::new_v4()));
+    let listener = tokio::net::UnixListener::bind(&path).unwrap();
+    let mut config = operator_config();
+    config.result_socket = Some(path.clone());
+    let (_operator, url) = start_operator(config).await;
+    start_signers(&url, 2).await;
+
+    let response = sign(&url, &json!({ "message": "published" })).await;
+    let (stream, _) = listener.accept().await.unwrap();
+    let mut lines = BufReader::new(stream).lines();
+    let line = lines.next_line().await.unwrap().unwrap();
+    let published: SigningResponse = serde_json::from_str(&line).unwrap();
+    assert_eq!(published.session_id, response.session_id);
+    assert_eq!(
+        published.aggregated_signature,
+        response.aggregated_signature
+    );
+    assert_eq!(lines.next_line().await.unwrap(), None);
+
+    // Nobody listening doesn't fail the ceremony
+    drop(listener);
+    std::fs::remove_file(&path).unwrap();
+    sign(&url, &json!({ "message": "unpublished" })).await;
+}
+
+#[tokio::test]
+async fn taproot_outputs_are_signed_under_the_output_key() {
+    let (_full_operator, full_url) = start_operator(operator_config()).await;
+    let signers = start_signers(&full_url, 2).await;
+    let mut config = operator_config();
+    config.key_agg_encoding = KeyAggEncoding::Pubkeys;
+    let (_operator, pubkeys_url) = start_operator(config).await;
+    for signer in &signers {
+        register(&pubkeys_url, signer.public_key, &signer.url).await;
+    }
+    let mut pubkeys = [signers[0].public_key, signers[1].public_key];
+    pubkeys.sort_by_key(PublicKey::serialize);
+    let internal_key = key_agg_ctx(&pubkeys).aggregated_pubkey::<PublicKey>();
+
+    // Key path only (BIP86), then a script tree
+    for merkle_root in [String::new(), "11".repeat(32)] {
+        let request = json!({ "message": "taproot", "taproot_merkle_root": merkle_root });
+        for url in [&full_url, &pubkeys_url] {
+            let response = sign(url, &request).await;
+            let taproot = response.taproot.expect("No taproot keys returned");
+            assert_eq!(taproot.internal_key, internal_key.x_only_public_key().0);
+            assert_eq!(
+                taproot.output_key,
+                response.aggregated_pubkey.x_only_public_key().0
+            );
+            assert_ne!(taproot.output_key, taproot.internal_key);
+            assert!(verify_schnorr(
+                response.aggregated_pubkey,
+                response.aggregated_signature,
+                b"taproot"
+            )
+            .is_ok());
+        }
+    }
+
+    let request = json!({ "message": "taproot", "taproot_merkle_root": "11" });
+    let (status, _) = post_json(&format!("{}/sign", full_url), &request).await;
+    assert_eq!(status, 400);
+}
+
+/// Hook counting the sessions it was handed.
+struct CountingHook {
+    sessions: std::sync::Mutex<Vec<String>>,
+}
+
+impl OnSignatureComplete for CountingHook {
+    fn handle<'a>(&'a self, response: &'a SigningResponse) -> BoxFuture<'a, ()> {
+        self.sessions
+            .lock()
+            .unwrap()
+            .push(response.session_id.clone());
+        Box::pin(async {})
+    }
+}
+
+#[tokio::test]
+async fn signature_hooks_see_every_completed_ceremony() {
+    let hook = Arc::new(CountingHook {
+        sessions: std::sync::Mutex::new(Vec::new()),
+    });
+    let mut config = operator_config();
+    config.signature_hook = hook.clone();
+    let (_operator, url) = start_operator(config).await;
+
+    // A ceremony that fails never reaches the hook
+    start_signer(&url).await;
+    let (status, _) = post_json(&format!("{}/sign", url), &json!({ "message": "one" })).await;
+    assert_eq!(status, 409);
+    start_signer(&url).await;
+    let first = sign(&url, &json!({ "message": "one" })).await;
+    let second = sign(&url, &json!({ "message": "two" })).await;
+    assert_eq!(
+        *hook.sessions.lock().unwrap(),
+        [first.session_id, second.session_id]
+    );
+}
+
+#[tokio::test]
+async fn webhooks_receive_the_signing_response() {
+    let (delivered, mut deliveries) = tokio::sync::mpsc::unbounded_channel();
+    let port = free_port();
+    let route = warp::post()
+        .and(warp::body::json())
+        .map(move |response: SigningResponse| {
+            delivered.send(response).unwrap();
+            warp::reply()
+        });
+    tokio::spawn(warp::serve(route).run(([127, 0, 0, 1], port)));
+    let mut config = operator_config();
+    config.signature_hook = Arc::new(WebhookHook::new(
+        HttpClient::new(),
+        format!("http://127.0.0.1:{}/hook", port),
+    ));
+    let (_operator, url) = start_operator(config).await;
+    start_signers(&url, 2).await;
+
+    let response = sign(&url, &json!({ "message": "hooked" })).await;
+    let hooked = deliveries.recv().await.unwrap();
+    assert_eq!(hooked.session_id, response.session_id);
+    assert_eq!(hooked.aggregated_signature, response.aggregated_signature);
+}
+
+/// A request hook keeping the JSON bodies sent to `path`.
+fn record(path: &'static str, bodies: Arc<std::sync::Mutex<Vec<serde_json::Value>>>) -> ProxyHook {
+    Arc::new(move |request_path, body| {
+        if request_path == path {
+            bodies
+                .lock()
+                .unwrap()
+                .push(serde_json::from_slice(&body).unwrap());
+        }
+        Box::pin(async move { body })
+    })
+}
+
+#[tokio::test]
+async fn operator_finalized_signatures_reach_every_signer() {
+    let mut config = operator_config();
+    config.finalize_at = FinalizeAt::Operator;
+    let (_operator, url) = start_operator(config).await;
+    let bodies = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let mut signers = Vec::new();
+    for _ in 0..2 {
+        let hook = record("/final-signature", bodies.clone());
+        signers.push(start_proxied_signer(&url, hook, pass_through()).await.0);
+    }
+
+    let response = sign(&url, &json!({ "message": "told" })).await;
+    let expected = json!({
+        "session_id": response.session_id,
+        "final_signature": hex::encode(response.aggregated_signature.serialize()),
+    });
+    assert_eq!(*bodies.lock().unwrap(), [expected.clone(), expected]);
+    for signer in &signers {
+        let (_, sessions) = get_json(&format!("{}/sessions", signer.url)).await;
+        assert_eq!(sessions, json!([]));
+    }
+
+    // Signers stick to the signature they were told
+    let other = sign(&url, &json!({ "message": "other" })).await;
+    let tampered = json!({
+        "session_id": response.session_id,
+        "final_signature": hex::encode(other.aggregated_signature.serialize()),
+    });
+    let final_signature = format!("{}/final-signature", signers[0].url);
+    let (status, error) = post_json(&final_signature, &tampered).await;
+    assert_eq!(status, 400);
+    assert_eq!(
+        error["error"],
+        format!(
+            "Session {} is already finalized with another signature",
+            response.session_id
+        )
+    );
+    let unknown =
+        json!({ "session_id": "unknown", "final_signature": tampered["final_signature"] });
+    let (status, error) = post_json(&final_signature, &unknown).await;
+    assert_eq!(status, 400);
+    assert_eq!(error["error"], "Second round not found");
+}
+
+#[tokio::test]
+async fn concurrent_ceremonies_sign_their_own_messages() {
+    let (_operator, url) = start_operator(operator_config()).await;
+    start_signers(&url, 2).await;
+
+    let ceremonies = (0..6).map(|i| {
+        let url = url.clone();
+        tokio::spawn(async move {
+            let message = format!("concurrent {}", i);
+            (sign(&url, &json!({ "message": message })).await, message)
+        })
+    });
+    for ceremony in futures::future::join_all(ceremonies).await {
+        let (response, message) = ceremony.unwrap();
+        assert_eq!(response.message, message);
+        assert!(verify_schnorr(
+            response.aggregated_pubkey,
+            response.aggregated_signature,
+            message.as_bytes()
+        )
+        .is_ok());
+    }
+}
@@ -0,0 +1,46 @@
+mod common;
+
+use common::{free_port, get_json, sign, wait_until_listening};
+use serde_json::json;
+use std::process::{Child, Command, Stdio};
+use std::time::Duration;
+
+/// Kills the process when the test ends, whether it passes or not.
+struct KillOnDrop(Child);
+
+impl Drop for KillOnDrop {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+#[tokio::test]
+async fn combined_binary_signs_with_its_own_signers() {
+    let port = free_port();
+    let _process = KillOnDrop(
+        Command::new(env!("CARGO_BIN_EXE_combined"))
+            .args(["--port", &port.to_string(), "--signers", "3"])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("Failed to start the combined binary"),
+    );
+    wait_until_listening(port).await;
+    let url = format!("http://127.0.0.1:{}", port);
+
+    // Signers register one after another once the operator is up
+    let mut registered = 0;
+    for _ in 0..100 {
+        let (_, signers) = get_json(&format!("{}/signers", url)).await;
+        registered = signers.as_array().map_or(0, Vec::len);
+        if registered == 3 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    assert_eq!(registered, 3);
+
+    let response = sign(&url, &json!({ "message": "all in one" })).await;
+    assert_eq!(response.is_signature_valid, Some(true));
+}
@@ -0,0 +1,290 @@
+// Each test binary uses its own subset of these helpers
+#![allow(dead_code)]
+
+use futures::future::BoxFuture;
+use musig2::KeyAggContext;
+use musig2_example::backend::SoftwareSigner;
+use musig2_example::client::HttpClient;
+use musig2_example::operator::{Operator, OperatorConfig};
+use musig2_example::secp::SECP256K1;
+use musig2_example::signer::Signer;
+use musig2_example::types::{
+    GenerateNonceRequest, MessageEncoding, SigningDomain, SigningResponse,
+};
+use secp256k1::{PublicKey, SecretKey};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::net::{Ipv4Addr, TcpListener};
+use std::sync::Arc;
+use std::time::Duration;
+use warp::http::{HeaderMap, Method, Response};
+use warp::hyper::body::Bytes;
+use warp::path::FullPath;
+use warp::Filter;
+
+/// A port nothing listens on right now, picked by binding an ephemeral one.
+pub fn free_port() -> u16 {
+    TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+        .and_then(|listener| listener.local_addr())
+        .expect("Failed to pick a free port")
+        .port()
+}
+
+/// Waits until a server accepts connections on `port`.
+pub async fn wait_until_listening(port: u16) {
+    for _ in 0..200 {
+        if tokio::net::TcpStream::connect((Ipv4Addr::LOCALHOST, port))
+            .await
+            .is_ok()
+        {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("Nothing is listening on port {}", port);
+}
+
+/// An operator configuration on a free port with every optional feature off.
+pub fn operator_config() -> OperatorConfig {
+    OperatorConfig {
+        port: free_port(),
+        max_batch_size: 1,
+        ..Default::default()
+    }
+}
+
+/// Serves an operator built from `config` in the background, returning it with its URL.
+pub async fn start_operator(config: OperatorConfig) -> (Operator, String) {
+    let port = config.port;
+    let operator = Operator::new(HttpClient::new(), config, None);
+    let server = operator.clone();
+    tokio::spawn(async move { server.start_server(std::future::pending()).await });
+    wait_until_listening(port).await;
+    (operator, format!("http://127.0.0.1:{}", port))
+}
+
+/// A signer node with an in-memory key, not serving yet.
+pub struct TestSigner {
+    pub signer: Signer<SoftwareSigner>,
+    pub secret_key: SecretKey,
+    pub public_key: PublicKey,
+    pub port: u16,
+    pub url: String,
+}
+
+impl TestSigner {
+    pub fn new(operator_url: &str) -> Self {
+        Self::with_options(operator_url, None, None)
+    }
+
+    /// A signer requiring `admin_token` on its admin endpoints and `operator_auth_key`
+    /// signatures on the operator's requests.
+    pub fn with_options(
+        operator_url: &str,
+        admin_token: Option<&str>,
+        operator_auth_key: Option<&str>,
+    ) -> Self {
+        let secret_key = SecretKey::new(&mut rand::thread_rng());
+        Self::build(operator_url, secret_key, admin_token, operator_auth_key)
+    }
+
+    /// A signer holding `secret_key`, e.g. a standby for another signer.
+    pub fn with_key(operator_url: &str, secret_key: SecretKey) -> Self {
+        Self::build(operator_url, secret_key, None, None)
+    }
+
+    fn build(
+        operator_url: &str,
+        secret_key: SecretKey,
+        admin_token: Option<&str>,
+        operator_auth_key: Option<&str>,
+    ) -> Self {
+        let port = free_port();
+        let signer = Signer::new(
+            HttpClient::new(),
+            operator_url.to_string(),
+            port,
+            admin_token.map(str::to_string),
+            operator_auth_key.map(str::to_string),
+            1,
+            SoftwareSigner::new(secret_key, &SECP256K1),
+        );
+        Self {
+            signer,
+            secret_key,
+            public_key: PublicKey::from_secret_key(&SECP256K1, &secret_key),
+            port,
+            url: format!("http://127.0.0.1:{}", port),
+        }
+    }
+
+    /// Serves the signer's endpoints in the background.
+    pub async fn serve(&self) {
+        let signer = self.signer.clone();
+        tokio::spawn(async move { signer.start_server().await });
+        wait_until_listening(self.port).await;
+    }
+}
+
+/// Starts a signer and registers it with the operator at `operator_url`.
+pub async fn start_signer(operator_url: &str) -> TestSigner {
+    let signer = TestSigner::new(operator_url);
+    signer.serve().await;
+    if signer.signer.register().await.is_err() {
+        panic!("Failed to register signer at {}", signer.url);
+    }
+    signer
+}
+
+pub async fn start_signers(operator_url: &str, count: usize) -> Vec<TestSigner> {
+    let mut signers = Vec::with_capacity(count);
+    for _ in 0..count {
+        signers.push(start_signer(operator_url).await);
+    }
+    signers
+}
+
+/// Registers `public_key` at `address` with the operator, as a signer would for itself.
+pub async fn register(operator_url: &str, public_key: PublicKey, address: &str) -> Value {
+    let response = reqwest::Client::new()
+        .post(format!("{}/register", operator_url))
+        .json(&json!({
+            "address": address,
+            "public_key": hex::encode(public_key.serialize()),
+        }))
+        .send()
+        .await
+        .expect("Failed to reach the operator");
+    assert!(
+        response.status().is_success(),
+        "Registration failed with {}",
+        response.status()
+    );
+    response.json().await.expect("Invalid registration reply")
+}
+
+/// Sends `request`, returning the status and the JSON reply (`null` if there is none).
+pub async fn send(request: reqwest::RequestBuilder) -> (reqwest::StatusCode, Value) {
+    let response = request.send().await.expect("Request failed");
+    let status = response.status();
+    (status, response.json().await.unwrap_or(Value::Null))
+}
+
+pub async fn post_json(url: &str, body: &impl Serialize) -> (reqwest::StatusCode, Value) {
+    send(reqwest::Client::new().post(url).json(body)).await
+}
+
+pub async fn put_json(url: &str, body: &impl Serialize) -> (reqwest::StatusCode, Value) {
+    send(reqwest::Client::new().put(url).json(body)).await
+}
+
+pub async fn get_json(url: &str) -> (reqwest::StatusCode, Value) {
+    send(reqwest::Client::new().get(url)).await
+}
+
+pub fn random_public_key() -> PublicKey {
+    PublicKey::from_secret_key(&SECP256K1, &SecretKey::new(&mut rand::thread_rng()))
+}
+
+/// A key aggregation context over `public_keys`, in that order.
+pub fn key_agg_ctx(public_keys: &[PublicKey]) -> KeyAggContext {
+    KeyAggContext::new(public_keys.to_vec()).expect("Failed to aggregate keys")
+}
+
+/// The nonce request the operator sends the signer at `signer_index` of `key_agg_ctx`.
+pub fn nonce_request(
+    session_id: &str,
+    message: &str,
+    key_agg_ctx: &KeyAggContext,
+    signer_index: usize,
+) -> GenerateNonceRequest {
+    GenerateNonceRequest {
+        session_id: session_id.to_string(),
+        message: message.to_string(),
+        message_encoding: MessageEncoding::Utf8,
+        signing_domain: SigningDomain::Raw,
+        key_agg_ctx: Some(key_agg_ctx.clone()),
+        aggregated_pubkey: None,
+        signer_index,
+        reservation_id: None,
+        participants: Vec::new(),
+        tweak: None,
+        taproot_merkle_root: None,
+    }
+}
+
+/// Signs through the operator at `operator_url`, failing the test unless it succeeds.
+pub async fn sign(operator_url: &str, request: &Value) -> SigningResponse {
+    let (status, body) = post_json(&format!("{}/sign", operator_url), request).await;
+    assert_eq!(status, 200, "Signing failed: {}", body);
+    serde_json::from_value(body).expect("Invalid signing response")
+}
+
+/// Rewrites a body passing through a proxy, given the request's path.
+pub type ProxyHook = Arc<dyn Fn(String, Bytes) -> BoxFuture<'static, Bytes> + Send + Sync>;
+
+/// A hook passing bodies through unchanged.
+pub fn pass_through() -> ProxyHook {
+    Arc::new(|_, body| Box::pin(async move { body }))
+}
+
+/// Serves a proxy in front of the node at `target`, returning its URL. Request bodies go
+/// through `on_request` before they are forwarded and response bodies through `on_response`
+/// before they are returned, which lets tests delay, observe or tamper with the protocol.
+pub async fn start_proxy(target: &str, on_request: ProxyHook, on_response: ProxyHook) -> String {
+    let port = free_port();
+    let target = target.to_string();
+    let client = reqwest::Client::new();
+    let route = warp::method()
+        .and(warp::path::full())
+        .and(warp::header::headers_cloned())
+        .and(warp::body::bytes())
+        .then(
+            move |method: Method, path: FullPath, headers: HeaderMap, body: Bytes| {
+                let (target, client) = (target.clone(), client.clone());
+                let (on_request, on_response) = (on_request.clone(), on_response.clone());
+                async move {
+                    let path = path.as_str().to_string();
+                    let body = on_request(path.clone(), body).await;
+                    let mut request = client
+                        .request(method, format!("{}{}", target, path))
+                        .body(body);
+                    for name in ["content-type", "x-operator-signature", "x-admin-token"] {
+                        if let Some(value) = headers.get(name) {
+                            request = request.header(name, value.clone());
+                        }
+                    }
+                    let (status, body) = match request.send().await {
+                        Ok(response) => (
+                            response.status(),
+                            response.bytes().await.unwrap_or_default(),
+                        ),
+                        Err(_) => (warp::http::StatusCode::BAD_GATEWAY, Bytes::new()),
+                    };
+                    let body = on_response(path, body).await;
+                    Response::builder()
+                        .status(status)
+                        .header("content-type", "application/json")
+                        .body(body.to_vec())
+                        .unwrap()
+                }
+            },
+        );
+    tokio::spawn(warp::serve(route).run((Ipv4Addr::LOCALHOST, port)));
+    wait_until_listening(port).await;
+    format!("http://127.0.0.1:{}", port)
+}
+
+/// Starts a signer that the operator reaches through a proxy, see [`start_proxy`]. The signer
+/// is registered under the proxy's address.
+pub async fn start_proxied_signer(
+    operator_url: &str,
+    on_request: ProxyHook,
+    on_response: ProxyHook,
+) -> (TestSigner, String) {
+    let signer = TestSigner::new(operator_url);
+    signer.serve().await;
+    let proxy = start_proxy(&signer.url, on_request, on_response).await;
+    register(operator_url, signer.public_key, &proxy).await;
+    (signer, proxy)
+}
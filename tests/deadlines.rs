@@ -0,0 +1,129 @@
+mod common;
+
+use common::{
+    get_json, operator_config, pass_through, post_json, sign, start_operator, start_proxied_signer,
+    start_signer, ProxyHook,
+};
+use serde_json::json;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// A request hook holding requests to `path` back for `delay`.
+fn delay(path: &'static str, delay: Duration) -> ProxyHook {
+    Arc::new(move |request_path, body| {
+        Box::pin(async move {
+            if request_path == path {
+                tokio::time::sleep(delay).await;
+            }
+            body
+        })
+    })
+}
+
+#[tokio::test]
+async fn slow_nonces_exceed_the_nonce_deadline() {
+    let mut config = operator_config();
+    config.nonce_deadline = Some(Duration::from_millis(300));
+    let (_operator, url) = start_operator(config).await;
+    start_signer(&url).await;
+    let slow = delay("/nonce", Duration::from_secs(30));
+    let (_stalled, proxy) = start_proxied_signer(&url, slow, pass_through()).await;
+
+    let started = Instant::now();
+    let (status, body) = post_json(&format!("{}/sign", url), &json!({ "message": "hurry" })).await;
+    assert!(started.elapsed() < Duration::from_secs(10));
+    assert_eq!(status, 504);
+    assert_eq!(body["code"], "deadline_exceeded");
+    // The stalled signer registered second
+    assert_eq!(
+        body["error"],
+        format!(
+            "Nonce collection deadline exceeded; no response from signers 1 at {}",
+            proxy
+        )
+    );
+}
+
+#[tokio::test]
+async fn signers_are_asked_concurrently() {
+    let (_operator, url) = start_operator(operator_config()).await;
+    // Both signers are slow to hand out and to receive nonces
+    let slow: ProxyHook = Arc::new(|path, body| {
+        Box::pin(async move {
+            if path == "/nonce" || path == "/nonces" {
+                tokio::time::sleep(Duration::from_millis(500)).await;
+            }
+            body
+        })
+    });
+    for _ in 0..2 {
+        start_proxied_signer(&url, slow.clone(), pass_through()).await;
+    }
+
+    // Asked one after the other, the two signers would take at least 2 s
+    let started = Instant::now();
+    let response = sign(&url, &json!({ "message": "concurrent" })).await;
+    assert!(started.elapsed() < Duration::from_millis(1800));
+    assert_eq!(response.is_signature_valid, Some(true));
+}
+
+#[tokio::test]
+async fn slow_signers_time_out_by_name() {
+    let mut config = operator_config();
+    config.signer_timeout = Some(Duration::from_millis(300));
+    let (_operator, url) = start_operator(config).await;
+    start_signer(&url).await;
+    let slow = delay("/nonces", Duration::from_secs(30));
+    let (_signer, proxy) = start_proxied_signer(&url, slow, pass_through()).await;
+
+    let started = Instant::now();
+    let (status, body) = post_json(&format!("{}/sign", url), &json!({ "message": "hi" })).await;
+    assert!(started.elapsed() < Duration::from_secs(5));
+    assert_eq!(status, 502);
+    assert_eq!(body["code"], "signer_unreachable");
+    let (_, signers) = get_json(&format!("{}/signers", url)).await;
+    let index = signers
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|signer| signer["address"] == proxy)
+        .unwrap()["index"]
+        .clone();
+    assert_eq!(
+        body["error"],
+        format!("Signer {} at {} timed out after 300 ms", index, proxy)
+    );
+}
+
+/// Polls the signer at `url` until its in-flight session count is `count`.
+async fn wait_for_sessions(url: &str, count: usize) {
+    for _ in 0..200 {
+        let (_, sessions) = get_json(&format!("{}/sessions", url)).await;
+        if sessions.as_array().map(Vec::len) == Some(count) {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(25)).await;
+    }
+    panic!("Signer at {} never had {} session(s) in flight", url, count);
+}
+
+#[tokio::test]
+async fn clients_giving_up_cancel_the_ceremony_on_the_signers() {
+    let (_operator, url) = start_operator(operator_config()).await;
+    let honest = start_signer(&url).await;
+    let slow = delay("/nonces", Duration::from_secs(30));
+    let (stalled, _) = start_proxied_signer(&url, slow, pass_through()).await;
+
+    let impatient = reqwest::Client::new()
+        .post(format!("{}/sign", url))
+        .timeout(Duration::from_secs(1))
+        .json(&json!({ "message": "abandoned" }))
+        .send();
+    let client = tokio::spawn(impatient);
+    wait_for_sessions(&honest.url, 1).await;
+    assert!(client.await.unwrap().unwrap_err().is_timeout());
+
+    // Both signers are told to drop their rounds, the stalled one included
+    wait_for_sessions(&honest.url, 0).await;
+    wait_for_sessions(&stalled.url, 0).await;
+}
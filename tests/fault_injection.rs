@@ -0,0 +1,40 @@
+// Run with `cargo test --features fault-injection`
+#![cfg(feature = "fault-injection")]
+
+mod common;
+
+use common::{operator_config, post_json, sign, start_operator, start_signers};
+use serde_json::json;
+
+#[tokio::test]
+async fn injected_faults_fail_one_ceremony_each() {
+    let (_operator, url) = start_operator(operator_config()).await;
+    let signers = start_signers(&url, 2).await;
+    let fault = format!("{}/fault", signers[0].url);
+
+    let (status, _) = post_json(&fault, &json!({ "fault": "corrupt_partial_signature" })).await;
+    assert_eq!(status, 200);
+    let (status, body) = post_json(&format!("{}/sign", url), &json!({ "message": "hi" })).await;
+    assert_eq!(status, 502);
+    assert_eq!(body["code"], "signer_failed");
+    assert!(body["error"]
+        .as_str()
+        .unwrap()
+        .ends_with("returned an invalid partial signature"));
+
+    let (status, _) = post_json(&fault, &json!({ "fault": "mismatched_final_signature" })).await;
+    assert_eq!(status, 200);
+    let (status, body) = post_json(&format!("{}/sign", url), &json!({ "message": "hi" })).await;
+    assert_eq!(status, 502);
+    assert_eq!(body["code"], "inconsistent_final_signatures");
+
+    // Each fault was injected once only
+    sign(&url, &json!({ "message": "hi" })).await;
+
+    // A cleared fault isn't injected at all
+    let (status, _) = post_json(&fault, &json!({ "fault": "corrupt_partial_signature" })).await;
+    assert_eq!(status, 200);
+    let (status, _) = post_json(&fault, &json!({ "fault": null })).await;
+    assert_eq!(status, 200);
+    sign(&url, &json!({ "message": "hi" })).await;
+}
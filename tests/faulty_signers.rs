@@ -0,0 +1,298 @@
+mod common;
+
+use common::{
+    get_json, key_agg_ctx, operator_config, pass_through, post_json, random_public_key, register,
+    sign, start_operator, start_proxied_signer, start_proxy, start_signer, ProxyHook, TestSigner,
+};
+use musig2_example::client::HttpClient;
+use musig2_example::orchestrator::{run_signing_session, SigningError};
+use secp256k1::PublicKey;
+use serde_json::{json, Value};
+use std::net::Ipv4Addr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use warp::hyper::body::Bytes;
+
+/// A hook replacing `field` in the JSON bodies sent to `path` with `value`, or the whole body
+/// if `field` is empty.
+fn rewrite_json(path: &'static str, field: &'static str, value: Value) -> ProxyHook {
+    Arc::new(move |request_path, body| {
+        let value = value.clone();
+        Box::pin(async move {
+            if request_path != path {
+                return body;
+            }
+            let mut reply: Value = serde_json::from_slice(&body).unwrap();
+            match field {
+                "" => reply = value,
+                field => reply[field] = value,
+            }
+            Bytes::from(serde_json::to_vec(&reply).unwrap())
+        })
+    })
+}
+
+#[tokio::test]
+async fn malformed_nonces_fail_the_ceremony_early() {
+    let (_operator, url) = start_operator(operator_config()).await;
+    start_signer(&url).await;
+    let garbage = rewrite_json("/nonce", "", json!([1, 2, 3]));
+    let (_signer, proxy) = start_proxied_signer(&url, pass_through(), garbage).await;
+
+    let (status, body) = post_json(&format!("{}/sign", url), &json!({ "message": "hi" })).await;
+    assert_eq!(status, 502);
+    assert_eq!(body["code"], "signer_failed");
+    let error = body["error"].as_str().unwrap();
+    assert!(error.contains(&proxy), "{}", error);
+    assert!(
+        error.ends_with("returned an invalid public nonce (3 bytes)"),
+        "{}",
+        error
+    );
+}
+
+#[tokio::test]
+async fn invalid_partial_signatures_are_caught_on_arrival() {
+    let (_operator, url) = start_operator(operator_config()).await;
+    start_signer(&url).await;
+    // A well-formed scalar that isn't the signer's partial signature
+    let mut forged = vec![0u8; 31];
+    forged.push(1);
+    let forge = rewrite_json("/nonces", "partial_signature", json!(forged));
+    let (_signer, proxy) = start_proxied_signer(&url, pass_through(), forge).await;
+
+    let (status, body) = post_json(&format!("{}/sign", url), &json!({ "message": "hi" })).await;
+    assert_eq!(status, 502);
+    assert_eq!(body["code"], "signer_failed");
+    let error = body["error"].as_str().unwrap();
+    assert!(error.contains(&proxy), "{}", error);
+    assert!(
+        error.ends_with("returned an invalid partial signature"),
+        "{}",
+        error
+    );
+}
+
+#[tokio::test]
+async fn faulty_signers_can_be_excluded() {
+    let mut config = operator_config();
+    config.exclude_faulty_signers = true;
+    let (_operator, url) = start_operator(config).await;
+    let honest = [start_signer(&url).await, start_signer(&url).await];
+    let garbage = rewrite_json("/nonce", "", json!([1, 2, 3]));
+    start_proxied_signer(&url, pass_through(), garbage).await;
+
+    let response = sign(&url, &json!({ "message": "without you" })).await;
+    let mut pubkeys: Vec<_> = honest.iter().map(|signer| signer.public_key).collect();
+    pubkeys.sort_by_key(|pubkey| pubkey.serialize());
+    assert_eq!(
+        response.aggregated_pubkey,
+        key_agg_ctx(&pubkeys).aggregated_pubkey::<PublicKey>()
+    );
+    assert_eq!(response.is_signature_valid, Some(true));
+}
+
+#[tokio::test]
+async fn signers_driven_with_another_message_are_named() {
+    let (_operator, url) = start_operator(operator_config()).await;
+    start_signer(&url).await;
+    let tamper = rewrite_json("/nonce", "message", json!("tampered"));
+    start_proxied_signer(&url, tamper, pass_through()).await;
+
+    let (status, body) = post_json(&format!("{}/sign", url), &json!({ "message": "hi" })).await;
+    assert_eq!(status, 502);
+    assert_eq!(body["code"], "message_divergence");
+    // Signers are named by the index they registered under, whatever their key order
+    assert_eq!(
+        body["error"],
+        "Message divergence: signers at indices [1] signed a different message"
+    );
+}
+
+#[tokio::test]
+async fn strict_operators_refuse_unknown_response_fields() {
+    for strict in [false, true] {
+        let mut config = operator_config();
+        config.strict_signer_responses = strict;
+        let (_operator, url) = start_operator(config).await;
+        start_signer(&url).await;
+        let newer = rewrite_json("/nonces", "protocol_version", json!(2));
+        let (_signer, proxy) = start_proxied_signer(&url, pass_through(), newer).await;
+
+        let (status, body) = post_json(&format!("{}/sign", url), &json!({ "message": "hi" })).await;
+        if !strict {
+            // Lenient parsing ignores fields it doesn't know
+            assert_eq!(status, 200);
+            continue;
+        }
+        assert_eq!(status, 502);
+        assert_eq!(
+            body["error"],
+            format!(
+                "Signer at {} is incompatible with protocol version 1: unexpected field `protocol_version`",
+                proxy
+            )
+        );
+    }
+}
+
+/// Serves a fake signer that starts every response and then drops the connection, counting
+/// the connections it accepted.
+async fn start_resetting_signer() -> (String, Arc<AtomicUsize>) {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let connections = Arc::new(AtomicUsize::new(0));
+    let accepted = connections.clone();
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            accepted.fetch_add(1, Ordering::SeqCst);
+            let mut request = [0; 4096];
+            let _ = stream.read(&mut request).await;
+            let _ = stream
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: 64\r\n\r\n[1,2")
+                .await;
+        }
+    });
+    (url, connections)
+}
+
+#[tokio::test]
+async fn lost_connections_are_retried_before_aborting() {
+    let mut config = operator_config();
+    config.connection_retries = 1;
+    let (_operator, url) = start_operator(config).await;
+    start_signer(&url).await;
+    let (resetting, connections) = start_resetting_signer().await;
+    register(&url, random_public_key(), &resetting).await;
+
+    let (status, body) = post_json(&format!("{}/sign", url), &json!({ "message": "hi" })).await;
+    assert_eq!(status, 502);
+    assert_eq!(body["code"], "signer_unreachable");
+    assert!(body["error"]
+        .as_str()
+        .unwrap()
+        .starts_with(&format!("Lost connection to signer at {}: ", resetting)));
+    // The first attempt and its one retry
+    assert_eq!(connections.load(Ordering::SeqCst), 2);
+}
+
+/// A hook answering every request to `path` with the body of the first response to it.
+fn replay_first(path: &'static str) -> ProxyHook {
+    let first = Arc::new(std::sync::Mutex::new(None::<Bytes>));
+    Arc::new(move |request_path, body| {
+        let first = first.clone();
+        Box::pin(async move {
+            if request_path != path {
+                return body;
+            }
+            first.lock().unwrap().get_or_insert(body).clone()
+        })
+    })
+}
+
+#[tokio::test]
+async fn replayed_nonces_from_earlier_ceremonies_abort() {
+    let (_operator, url) = start_operator(operator_config()).await;
+    start_signer(&url).await;
+    let (_signer, proxy) = start_proxied_signer(&url, pass_through(), replay_first("/nonce")).await;
+
+    let first = sign(&url, &json!({ "message": "first" })).await;
+    let (status, body) = post_json(&format!("{}/sign", url), &json!({ "message": "second" })).await;
+    assert_eq!(status, 502);
+    assert_eq!(body["code"], "nonce_reuse");
+    let (_, signers) = get_json(&format!("{}/signers", url)).await;
+    let index = signers
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|signer| signer["address"] == proxy)
+        .unwrap()["index"]
+        .clone();
+    assert_eq!(
+        body["error"],
+        format!(
+            "Nonce from signer {} at {} was already used in session {}; refusing to reuse it",
+            index, proxy, first.session_id
+        )
+    );
+}
+
+#[tokio::test]
+async fn signers_sharing_a_nonce_abort_the_ceremony() {
+    let (_operator, url) = start_operator(operator_config()).await;
+    // Both signers answer with whichever of their nonces reached the proxies first
+    let shared = replay_first("/nonce");
+    let (first, first_proxy) = start_proxied_signer(&url, pass_through(), shared.clone()).await;
+    let (second, second_proxy) = start_proxied_signer(&url, pass_through(), shared).await;
+
+    let (status, body) = post_json(&format!("{}/sign", url), &json!({ "message": "shared" })).await;
+    assert_eq!(status, 502);
+    assert_eq!(body["code"], "duplicate_nonce");
+    // Named by registration index, in the key order the nonces are compared in
+    let mut signers = [
+        (first.public_key.serialize(), 0, first_proxy),
+        (second.public_key.serialize(), 1, second_proxy),
+    ];
+    signers.sort();
+    assert_eq!(
+        body["error"],
+        format!(
+            "Signers {} at {} and {} at {} returned the same nonce; their nonce generation is broken, refusing to sign",
+            signers[0].1, signers[0].2, signers[1].1, signers[1].2
+        )
+    );
+}
+
+#[tokio::test]
+async fn tampered_final_signatures_name_the_divergent_signer() {
+    let (_operator, url) = start_operator(operator_config()).await;
+    let honest = start_signer(&url).await;
+    let tamper = rewrite_json(
+        "/partial-signatures",
+        "final_signature",
+        json!("07".repeat(64)),
+    );
+    let (_tampered, proxy) = start_proxied_signer(&url, pass_through(), tamper).await;
+
+    let (status, body) = post_json(&format!("{}/sign", url), &json!({ "message": "hi" })).await;
+    assert_eq!(status, 502);
+    assert_eq!(body["code"], "inconsistent_final_signatures");
+    assert_eq!(
+        body["error"],
+        format!(
+            "Inconsistent final signatures: signers 1 at {} returned a different one than signer 0 at {}",
+            proxy, honest.url
+        )
+    );
+}
+
+#[tokio::test]
+async fn library_sessions_name_signers_by_the_given_index() {
+    // Neither signer registers, so the operator URL is never used
+    let honest = TestSigner::new("http://127.0.0.1:1");
+    let faulty = TestSigner::new("http://127.0.0.1:1");
+    honest.serve().await;
+    faulty.serve().await;
+    let garbage = rewrite_json("/nonce", "", json!([1, 2, 3]));
+    let proxy = start_proxy(&faulty.url, pass_through(), garbage).await;
+
+    let signers = [
+        (5, honest.public_key, honest.url.clone()),
+        (9, faulty.public_key, proxy.clone()),
+    ];
+    match run_signing_session(&HttpClient::new(), &signers, b"hi", None).await {
+        Err(SigningError::Signer(failure)) => {
+            assert_eq!(failure.address, proxy);
+            assert_eq!(
+                failure.message,
+                format!(
+                    "Signer 9 at {} returned an invalid public nonce (3 bytes)",
+                    proxy
+                )
+            );
+        }
+        other => panic!("Expected signer 9 to fail, got {:?}", other),
+    }
+}
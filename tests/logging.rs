@@ -0,0 +1,70 @@
+mod common;
+
+use common::{operator_config, sign, start_operator, start_signers};
+use serde_json::json;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use tracing::field::{Field, Visit};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+
+type Events = Arc<Mutex<Vec<HashMap<String, String>>>>;
+
+/// Records the fields of every event, formatted with `Debug`.
+struct Capture(Events);
+
+struct Fields(HashMap<String, String>);
+
+impl Visit for Fields {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0
+            .insert(field.name().to_string(), format!("{:?}", value));
+    }
+}
+
+impl<S: Subscriber> Layer<S> for Capture {
+    fn on_event(&self, event: &Event<'_>, _: Context<'_, S>) {
+        let mut fields = Fields(HashMap::new());
+        event.record(&mut fields);
+        self.0.lock().unwrap().push(fields.0);
+    }
+}
+
+#[tokio::test]
+async fn ceremony_start_logs_the_participants_and_aggregated_key() {
+    let events = Events::default();
+    let subscriber = tracing_subscriber::registry().with(Capture(events.clone()));
+    tracing::subscriber::set_global_default(subscriber).unwrap();
+
+    let (_operator, url) = start_operator(operator_config()).await;
+    let signers = start_signers(&url, 2).await;
+    let response = sign(&url, &json!({ "message": "logged" })).await;
+
+    let events = events.lock().unwrap();
+    let started = events
+        .iter()
+        .find(|fields| fields.get("session_id") == Some(&response.session_id))
+        .expect("No event for the ceremony");
+    assert_eq!(started["message"], "ceremony started");
+
+    // Participants are aggregated in the order of their serialized keys
+    let mut pubkeys: Vec<String> = signers
+        .iter()
+        .map(|signer| hex::encode(signer.public_key.serialize()))
+        .collect();
+    pubkeys.sort();
+    assert_eq!(started["participants"], format!("{:?}", pubkeys));
+    assert_eq!(
+        started["aggregated_pubkey"],
+        hex::encode(response.aggregated_pubkey.serialize())
+    );
+
+    for signer in &signers {
+        let secret = hex::encode(signer.secret_key.secret_bytes());
+        assert!(events
+            .iter()
+            .flat_map(HashMap::values)
+            .all(|value| !value.contains(&secret)));
+    }
+}
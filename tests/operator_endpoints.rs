@@ -0,0 +1,448 @@
+mod common;
+
+use common::{
+    get_json, key_agg_ctx, nonce_request, operator_config, pass_through, post_json,
+    random_public_key, register, send, sign, start_operator, start_proxied_signer, start_signer,
+    start_signers, wait_until_listening, ProxyHook, TestSigner,
+};
+use musig2_example::client::HttpClient;
+use musig2_example::operator::Operator;
+use musig2_example::types::{AggregatedPubkeyResponse, SignerRegistrationRequest};
+use secp256k1::PublicKey;
+use serde_json::json;
+use std::sync::Arc;
+use std::time::Duration;
+
+const ADMIN_TOKEN: &str = "admin-secret";
+
+#[tokio::test]
+async fn signing_without_enough_signers_fails_cleanly() {
+    let (_operator, url) = start_operator(operator_config()).await;
+    let sign = json!({ "message": "hello" });
+
+    let (status, body) = post_json(&format!("{}/sign", url), &sign).await;
+    assert_eq!(status, 409);
+    assert_eq!(body["code"], "not_enough_signers");
+
+    start_signer(&url).await;
+    let (status, body) = post_json(&format!("{}/sign", url), &sign).await;
+    assert_eq!(status, 409);
+    assert_eq!(body["code"], "not_enough_signers");
+    assert_eq!(
+        body["error"],
+        "At least 2 signers are required, 1 registered"
+    );
+
+    // The operator survived both attempts
+    let (status, signers) = get_json(&format!("{}/signers", url)).await;
+    assert_eq!(status, 200);
+    assert_eq!(signers.as_array().map(Vec::len), Some(1));
+}
+
+#[tokio::test]
+async fn malformed_registrations_are_rejected_with_400() {
+    let (_operator, url) = start_operator(operator_config()).await;
+    let address = "http://127.0.0.1:1";
+
+    let not_hex = json!({ "address": address, "public_key": "not hex" });
+    let (status, body) = post_json(&format!("{}/register", url), &not_hex).await;
+    assert_eq!(status, 400);
+    assert_eq!(body["code"], "invalid_request");
+    assert!(body["error"]
+        .as_str()
+        .unwrap()
+        .contains("invalid hex encoding"));
+
+    let not_a_point = json!({ "address": address, "public_key": format!("05{}", "11".repeat(32)) });
+    let (status, body) = post_json(&format!("{}/register", url), &not_a_point).await;
+    assert_eq!(status, 400);
+    assert!(body["error"]
+        .as_str()
+        .unwrap()
+        .contains("invalid secp256k1 data"));
+
+    let (_, signers) = get_json(&format!("{}/signers", url)).await;
+    assert_eq!(signers, json!([]));
+}
+
+#[tokio::test]
+async fn static_signer_sets_refuse_registration() {
+    let signers = [
+        TestSigner::new("http://127.0.0.1:1"),
+        TestSigner::new("http://127.0.0.1:1"),
+    ];
+    for signer in &signers {
+        signer.serve().await;
+    }
+    let mut config = operator_config();
+    config.static_signers = Some(
+        signers
+            .iter()
+            .map(|signer| SignerRegistrationRequest {
+                address: signer.url.clone(),
+                public_key: signer.public_key,
+            })
+            .collect(),
+    );
+    let (_operator, url) = start_operator(config).await;
+
+    let registration = json!({
+        "address": "http://127.0.0.1:1",
+        "public_key": hex::encode(random_public_key().serialize()),
+    });
+    let (status, body) = post_json(&format!("{}/register", url), &registration).await;
+    assert_eq!(status, 403);
+    assert_eq!(body["code"], "registration_disabled");
+    assert_eq!(
+        body["error"],
+        "Registration is disabled: the operator uses a static signer set"
+    );
+
+    sign(&url, &json!({ "message": "static" })).await;
+    let (_, registered) = get_json(&format!("{}/signers", url)).await;
+    assert_eq!(registered.as_array().map(Vec::len), Some(2));
+}
+
+#[tokio::test]
+async fn exported_state_restores_on_a_fresh_operator() {
+    let mut config = operator_config();
+    config.admin_token = Some(ADMIN_TOKEN.to_string());
+    let (_operator, url) = start_operator(config).await;
+    start_signers(&url, 2).await;
+    let signed = sign(&url, &json!({ "message": "before" })).await;
+
+    let export = format!("{}/admin/export", url);
+    let (status, _) = send(reqwest::Client::new().post(&export)).await;
+    assert_eq!(status, 401);
+    let (status, state) = send(
+        reqwest::Client::new()
+            .post(&export)
+            .header("x-admin-token", ADMIN_TOKEN),
+    )
+    .await;
+    assert_eq!(status, 200);
+
+    // The fresh operator has a ceremony of its own in flight, stuck collecting nonces
+    let mut config = operator_config();
+    config.admin_token = Some(ADMIN_TOKEN.to_string());
+    let (_fresh, fresh_url) = start_operator(config).await;
+    let answering = start_signer(&fresh_url).await;
+    let stuck: ProxyHook = Arc::new(|path, body| {
+        Box::pin(async move {
+            if path == "/nonce" {
+                tokio::time::sleep(Duration::from_secs(30)).await;
+            }
+            body
+        })
+    });
+    start_proxied_signer(&fresh_url, stuck, pass_through()).await;
+    let sign_url = format!("{}/sign", fresh_url);
+    let in_flight =
+        tokio::spawn(async move { post_json(&sign_url, &json!({ "message": "in flight" })).await });
+    let signer_sessions = format!("{}/sessions", answering.url);
+    while get_json(&signer_sessions).await.1 == json!([]) {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    let (status, _) = send(
+        reqwest::Client::new()
+            .post(format!("{}/admin/import", fresh_url))
+            .header("x-admin-token", ADMIN_TOKEN)
+            .json(&state),
+    )
+    .await;
+    assert_eq!(status, 200);
+    let (status, body) = tokio::time::timeout(Duration::from_secs(5), in_flight)
+        .await
+        .expect("The import stops the ceremony")
+        .unwrap();
+    assert_eq!(status, 409);
+    assert_eq!(body["code"], "cancelled");
+    assert_eq!(get_json(&signer_sessions).await.1, json!([]));
+
+    let (_, signers) = get_json(&format!("{}/signers", fresh_url)).await;
+    assert_eq!(signers.as_array().map(Vec::len), Some(2));
+    let (_, messages) = get_json(&format!(
+        "{}/session/{}/messages",
+        fresh_url, signed.session_id
+    ))
+    .await;
+    assert_eq!(
+        messages,
+        json!([{ "message": "before", "encodings": [], "status": "signed" }])
+    );
+    let response = sign(&fresh_url, &json!({ "message": "after" })).await;
+    assert_eq!(response.aggregated_pubkey, signed.aggregated_pubkey);
+}
+
+#[tokio::test]
+async fn signers_register_once_the_operator_comes_up() {
+    let config = operator_config();
+    let url = format!("http://127.0.0.1:{}", config.port);
+    let signer = TestSigner::new(&url);
+    signer.serve().await;
+    let registration = signer.signer.register_in_background(false).await;
+
+    let ctx = key_agg_ctx(&[signer.public_key, random_public_key()]);
+    let request = nonce_request("early", "hello", &ctx, 0);
+    let (status, body) = post_json(&format!("{}/nonce", signer.url), &request).await;
+    assert_eq!(status, 400);
+    assert_eq!(
+        body["error"],
+        "Signer is not registered with the operator yet"
+    );
+
+    let (_operator, _) = start_operator(config).await;
+    tokio::time::timeout(Duration::from_secs(10), registration)
+        .await
+        .expect("Registration didn't recover")
+        .unwrap();
+    let (_, signers) = get_json(&format!("{}/signers", url)).await;
+    assert_eq!(signers.as_array().map(Vec::len), Some(1));
+    let (status, _) = post_json(&format!("{}/nonce", signer.url), &request).await;
+    assert_eq!(status, 200);
+}
+
+#[tokio::test]
+async fn deregistered_signers_leave_the_key() {
+    let (_operator, url) = start_operator(operator_config()).await;
+    let signers = start_signers(&url, 3).await;
+
+    signers[1].signer.deregister().await.unwrap();
+    let (_, registered) = get_json(&format!("{}/signers", url)).await;
+    assert_eq!(registered.as_array().map(Vec::len), Some(2));
+    let response = sign(&url, &json!({ "message": "remaining" })).await;
+    let mut pubkeys = [signers[0].public_key, signers[2].public_key];
+    pubkeys.sort_by_key(PublicKey::serialize);
+    assert_eq!(
+        response.aggregated_pubkey,
+        key_agg_ctx(&pubkeys).aggregated_pubkey::<PublicKey>()
+    );
+
+    let deregistration = json!({ "public_key": hex::encode(signers[1].public_key.serialize()) });
+    let (status, body) = post_json(&format!("{}/deregister", url), &deregistration).await;
+    assert_eq!(status, 404);
+    assert_eq!(body["code"], "unknown_signer");
+}
+
+#[tokio::test]
+async fn candidate_keysets_are_validated_without_registering() {
+    let (_operator, url) = start_operator(operator_config()).await;
+    let validate = format!("{}/validate-keyset", url);
+    let pubkeys = [random_public_key(), random_public_key()];
+    let hex_keys: Vec<String> = pubkeys
+        .iter()
+        .map(|pk| hex::encode(pk.serialize()))
+        .collect();
+
+    let (status, body) = post_json(&validate, &json!({ "public_keys": hex_keys })).await;
+    assert_eq!(status, 200);
+    assert_eq!(body["valid"], true);
+    assert_eq!(
+        body["aggregated_pubkey"],
+        hex::encode(
+            key_agg_ctx(&pubkeys)
+                .aggregated_pubkey::<PublicKey>()
+                .serialize()
+        )
+    );
+
+    let invalid = json!({ "public_keys": [hex_keys[0], "not a key"] });
+    let (status, body) = post_json(&validate, &invalid).await;
+    assert_eq!(status, 200);
+    assert_eq!(body["valid"], false);
+    assert!(body.get("aggregated_pubkey").is_none());
+    assert!(body["error"]
+        .as_str()
+        .unwrap()
+        .starts_with("Public key at index 1 is invalid"));
+
+    let (_, signers) = get_json(&format!("{}/signers", url)).await;
+    assert_eq!(signers, json!([]));
+}
+
+#[tokio::test]
+async fn aggregated_pubkey_matches_the_next_ceremony() {
+    let (_operator, url) = start_operator(operator_config()).await;
+    let aggregated_pubkey = format!("{}/aggregated-pubkey", url);
+
+    let (status, body) = get_json(&aggregated_pubkey).await;
+    assert_eq!(status, 409);
+    assert_eq!(body["code"], "not_enough_signers");
+    start_signer(&url).await;
+    let (status, _) = get_json(&aggregated_pubkey).await;
+    assert_eq!(status, 409);
+
+    start_signer(&url).await;
+    let (status, body) = get_json(&aggregated_pubkey).await;
+    assert_eq!(status, 200);
+    let expected: AggregatedPubkeyResponse = serde_json::from_value(body).unwrap();
+    assert_eq!(expected.signer_count, 2);
+    let response = sign(&url, &json!({ "message": "funded" })).await;
+    assert_eq!(response.aggregated_pubkey, expected.aggregated_pubkey);
+}
+
+#[tokio::test]
+async fn every_ceremony_needs_two_signers() {
+    let (_operator, url) = start_operator(operator_config()).await;
+    start_signer(&url).await;
+
+    let batch = json!({ "messages": ["alone"] });
+    let (status, body) = post_json(&format!("{}/sign-batch", url), &batch).await;
+    assert_eq!(status, 409);
+    assert_eq!(body["code"], "not_enough_signers");
+
+    start_signer(&url).await;
+    for signer_count in [1, 3] {
+        let request = json!({ "message": "subset", "signer_count": signer_count });
+        let (status, body) = post_json(&format!("{}/sign", url), &request).await;
+        assert_eq!(status, 400);
+        assert_eq!(
+            body["error"],
+            "Signer count must be between 2 and the 2 registered signer(s)"
+        );
+    }
+    sign(&url, &json!({ "message": "pair", "signer_count": 2 })).await;
+}
+
+#[tokio::test]
+async fn operators_stop_when_shutdown_resolves() {
+    let config = operator_config();
+    let port = config.port;
+    let operator = Operator::new(HttpClient::new(), config, None);
+    let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+    let server = operator.clone();
+    let serving = tokio::spawn(async move {
+        server
+            .start_server(async move {
+                stopped.await.ok();
+            })
+            .await
+    });
+    wait_until_listening(port).await;
+    let url = format!("http://127.0.0.1:{}", port);
+    let (status, _) = get_json(&format!("{}/signers", url)).await;
+    assert_eq!(status, 200);
+
+    // A second operator can't take the port, and says so instead of panicking
+    let mut busy = operator_config();
+    busy.port = port;
+    let busy = Operator::new(HttpClient::new(), busy, None);
+    assert!(busy.start_server(std::future::pending()).await.is_err());
+
+    stop.send(()).unwrap();
+    tokio::time::timeout(Duration::from_secs(5), serving)
+        .await
+        .expect("The operator didn't stop")
+        .unwrap()
+        .unwrap();
+    assert!(reqwest::get(format!("{}/signers", url)).await.is_err());
+}
+
+#[tokio::test]
+async fn registering_a_key_twice_is_a_conflict() {
+    let (_operator, url) = start_operator(operator_config()).await;
+    let public_key = random_public_key();
+    register(&url, public_key, "http://127.0.0.1:1").await;
+
+    let again = json!({
+        "address": "http://127.0.0.1:2",
+        "public_key": hex::encode(public_key.serialize()),
+    });
+    let (status, body) = post_json(&format!("{}/register", url), &again).await;
+    assert_eq!(status, 409);
+    assert_eq!(body["code"], "already_registered");
+    assert_eq!(body["error"], "public key already registered");
+
+    let (_, signers) = get_json(&format!("{}/signers", url)).await;
+    assert_eq!(signers.as_array().map(Vec::len), Some(1));
+    assert_eq!(signers[0]["address"], "http://127.0.0.1:1");
+}
+
+#[tokio::test]
+async fn signer_positions_follow_the_sorted_keys() {
+    let (_operator, url) = start_operator(operator_config()).await;
+    let public_keys = [
+        random_public_key(),
+        random_public_key(),
+        random_public_key(),
+    ];
+    for (port, public_key) in public_keys.iter().enumerate() {
+        register(&url, *public_key, &format!("http://127.0.0.1:{}", port + 1)).await;
+    }
+    let listed_keys = |signers: &serde_json::Value| -> Vec<String> {
+        signers
+            .as_array()
+            .unwrap()
+            .iter()
+            .enumerate()
+            .map(|(position, signer)| {
+                assert_eq!(signer["position"], position);
+                signer["public_key_hex"].as_str().unwrap().to_string()
+            })
+            .collect()
+    };
+    let mut sorted: Vec<String> = public_keys
+        .iter()
+        .map(|pk| hex::encode(pk.serialize()))
+        .collect();
+    sorted.sort();
+
+    let (status, signers) = get_json(&format!("{}/signers", url)).await;
+    assert_eq!(status, 200);
+    assert!(signers[0].get("public_key").is_none());
+    assert_eq!(listed_keys(&signers), sorted);
+    // The indices follow the registration order instead
+    for signer in signers.as_array().unwrap() {
+        let registered = public_keys
+            .iter()
+            .position(|pk| hex::encode(pk.serialize()) == signer["public_key_hex"])
+            .unwrap();
+        assert_eq!(signer["index"], registered);
+    }
+
+    // Removing the lowest key moves everyone after it up
+    let deregistration = json!({ "public_key": sorted[0] });
+    let (status, _) = post_json(&format!("{}/deregister", url), &deregistration).await;
+    assert_eq!(status, 200);
+    let (_, signers) = get_json(&format!("{}/signers", url)).await;
+    assert_eq!(listed_keys(&signers), sorted[1..].to_vec());
+}
+
+#[tokio::test]
+async fn signer_indices_survive_deregistrations() {
+    let (_operator, url) = start_operator(operator_config()).await;
+    let public_keys = [
+        random_public_key(),
+        random_public_key(),
+        random_public_key(),
+    ];
+    for (port, public_key) in public_keys.iter().enumerate() {
+        register(&url, *public_key, &format!("http://127.0.0.1:{}", port + 1)).await;
+    }
+
+    let deregistration = json!({ "public_key": hex::encode(public_keys[1].serialize()) });
+    let (status, _) = post_json(&format!("{}/deregister", url), &deregistration).await;
+    assert_eq!(status, 200);
+
+    let (_, signers) = get_json(&format!("{}/signers", url)).await;
+    let mut indices: Vec<(String, u64)> = signers
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|signer| {
+            (
+                signer["address"].as_str().unwrap().to_string(),
+                signer["index"].as_u64().unwrap(),
+            )
+        })
+        .collect();
+    indices.sort();
+    assert_eq!(
+        indices,
+        [
+            ("http://127.0.0.1:1".to_string(), 0),
+            ("http://127.0.0.1:3".to_string(), 2),
+        ]
+    );
+}
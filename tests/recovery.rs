@@ -0,0 +1,100 @@
+mod common;
+
+use common::{
+    operator_config, pass_through, put_json, start_operator, start_proxied_signer, start_signer,
+    ProxyHook,
+};
+use musig2_example::client::HttpClient;
+use musig2_example::journal::{PendingDistribution, SessionJournal};
+use musig2_example::operator::Operator;
+use musig2_example::secp::verify_schnorr;
+use musig2_example::types::{ReceivePartialSignaturesRequest, ReceivePartialSignaturesResponse};
+use serde_json::json;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// A request hook holding requests to `path` back forever while `stalled` is set.
+fn stall_while(path: &'static str, stalled: Arc<AtomicBool>) -> ProxyHook {
+    Arc::new(move |request_path, body| {
+        let stalled = stalled.clone();
+        Box::pin(async move {
+            if request_path == path && stalled.load(Ordering::SeqCst) {
+                std::future::pending::<()>().await;
+            }
+            body
+        })
+    })
+}
+
+/// Waits until the journal in `dir` holds a session, returning it.
+async fn journaled_session(dir: &Path) -> PendingDistribution {
+    let journal = SessionJournal::open(dir).unwrap();
+    for _ in 0..200 {
+        if let Some(session) = journal.pending().unwrap().pop() {
+            return session;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("No session was journaled");
+}
+
+#[tokio::test]
+async fn restarted_operators_finish_interrupted_distributions() {
+    let dir = std::env::temp_dir().join(format!("session-journal-{}", Uuid::new_v4()));
+    let mut config = operator_config();
+    config.session_journal = Some(SessionJournal::open(&dir).unwrap());
+    let (_crashed, url) = start_operator(config).await;
+    start_signer(&url).await;
+    let stalled = Arc::new(AtomicBool::new(true));
+    let stall = stall_while("/partial-signatures", stalled.clone());
+    start_proxied_signer(&url, stall, pass_through()).await;
+
+    // The operator "dies" while distributing: its ceremony never gets past the stalled signer
+    let ceremony = tokio::spawn(
+        reqwest::Client::new()
+            .post(format!("{}/sign", url))
+            .json(&json!({ "message": "interrupted" }))
+            .send(),
+    );
+    let session = journaled_session(&dir).await;
+    ceremony.abort();
+    stalled.store(false, Ordering::SeqCst);
+
+    let mut config = operator_config();
+    config.session_journal = Some(SessionJournal::open(&dir).unwrap());
+    let restarted = Operator::new(HttpClient::new(), config, None);
+    restarted.recover_sessions().await;
+    assert!(SessionJournal::open(&dir)
+        .unwrap()
+        .pending()
+        .unwrap()
+        .is_empty());
+
+    // Both signers finalized the session and answer with the signature they cached
+    let message = hex::decode(&session.signed_message).unwrap();
+    for participant in &session.participants {
+        let request = ReceivePartialSignaturesRequest {
+            session_id: session.session_id.clone(),
+            partial_signatures: session
+                .partial_signatures
+                .iter()
+                .filter(|(position, _)| **position != participant.position)
+                .map(|(position, partial)| (*position, *partial))
+                .collect(),
+        };
+        let url = format!("{}/partial-signatures", participant.address);
+        let (status, body) = put_json(&url, &request).await;
+        assert_eq!(status, 200);
+        let response: ReceivePartialSignaturesResponse = serde_json::from_value(body).unwrap();
+        assert!(verify_schnorr(
+            session.aggregated_pubkey,
+            response.final_signature,
+            &message
+        )
+        .is_ok());
+    }
+    std::fs::remove_dir_all(dir).unwrap();
+}
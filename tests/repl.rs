@@ -0,0 +1,89 @@
+mod common;
+
+use common::{
+    get_json, operator_config, pass_through, start_operator, start_proxied_signer, start_signer,
+    ProxyHook,
+};
+use serde_json::json;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, DuplexStream, Lines};
+use tokio::sync::Notify;
+
+async fn next_reply(replies: &mut Lines<BufReader<DuplexStream>>) -> String {
+    replies
+        .next_line()
+        .await
+        .unwrap()
+        .expect("The prompt closed its output")
+}
+
+#[tokio::test]
+async fn cancel_stops_the_ceremony_and_clears_the_signers() {
+    let (operator, url) = start_operator(operator_config()).await;
+    let fast = start_signer(&url).await;
+
+    // The proxied signer never gets to see the other nonces
+    let nonces_sent = Arc::new(Notify::new());
+    let notify = nonces_sent.clone();
+    let stall_nonces: ProxyHook = Arc::new(move |path, body| {
+        let notify = notify.clone();
+        Box::pin(async move {
+            if path == "/nonces" {
+                notify.notify_one();
+                tokio::time::sleep(Duration::from_secs(60)).await;
+            }
+            body
+        })
+    });
+    let (stalled, _proxy) = start_proxied_signer(&url, stall_nonces, pass_through()).await;
+
+    let (mut commands, input) = tokio::io::duplex(1024);
+    let (output, replies) = tokio::io::duplex(64 * 1024);
+    let repl = tokio::spawn(async move { operator.run_repl(BufReader::new(input), output).await });
+    let mut replies = BufReader::new(replies).lines();
+
+    assert!(next_reply(&mut replies)
+        .await
+        .starts_with("Interactive mode"));
+    commands.write_all(b"sign hello\n").await.unwrap();
+    let started = next_reply(&mut replies).await;
+    let session_id = started
+        .strip_prefix("Session ")
+        .and_then(|rest| rest.strip_suffix(" started"))
+        .unwrap_or_else(|| panic!("Unexpected reply: {}", started))
+        .to_string();
+
+    // The fast signer holds a round for the session by the time the nonces go out
+    nonces_sent.notified().await;
+    let (_, sessions) = get_json(&format!("{}/sessions", fast.url)).await;
+    assert_eq!(sessions.as_array().map(Vec::len), Some(1));
+
+    commands
+        .write_all(format!("cancel {}\n", session_id).as_bytes())
+        .await
+        .unwrap();
+    assert_eq!(
+        next_reply(&mut replies).await,
+        format!("Session {} cancelled", session_id)
+    );
+    for signer in [&fast, &stalled] {
+        let (_, sessions) = get_json(&format!("{}/sessions", signer.url)).await;
+        assert_eq!(sessions, json!([]));
+    }
+
+    commands
+        .write_all(format!("cancel {}\nquit\n", session_id).as_bytes())
+        .await
+        .unwrap();
+    assert_eq!(
+        next_reply(&mut replies).await,
+        format!("No session {}", session_id)
+    );
+    // The prompt exits without waiting for the cancelled ceremony, which never reports back
+    tokio::time::timeout(Duration::from_secs(5), repl)
+        .await
+        .expect("The prompt didn't exit")
+        .unwrap();
+    assert_eq!(replies.next_line().await.unwrap(), None);
+}
@@ -0,0 +1,33 @@
+mod common;
+
+use common::{operator_config, sign, start_operator, start_signers};
+use musig2_example::bench::run_bench;
+use musig2_example::runtime::build_runtime;
+use serde_json::json;
+use std::num::NonZeroUsize;
+
+#[test]
+fn ceremonies_complete_on_a_single_worker_thread() {
+    let runtime = build_runtime(NonZeroUsize::new(1)).unwrap();
+    runtime.block_on(async {
+        let (_operator, url) = start_operator(operator_config()).await;
+        start_signers(&url, 2).await;
+        let response = sign(&url, &json!({ "message": "one worker" })).await;
+        assert_eq!(response.is_signature_valid, Some(true));
+    });
+}
+
+#[tokio::test]
+async fn benchmarks_count_failed_ceremonies() {
+    let (operator, url) = start_operator(operator_config()).await;
+    let failed = run_bench(&operator, 2).await;
+    assert_eq!(failed.failures, 2);
+    assert_eq!(failed.median_ms, None);
+
+    start_signers(&url, 2).await;
+    let report = run_bench(&operator, 3).await;
+    assert_eq!(report.ceremonies, 3);
+    assert_eq!(report.failures, 0);
+    let (min, max) = (report.min_ms.unwrap(), report.max_ms.unwrap());
+    assert!(min > 0.0 && min <= report.median_ms.unwrap() && report.p95_ms.unwrap() <= max);
+}
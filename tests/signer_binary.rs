@@ -0,0 +1,80 @@
+mod common;
+
+use common::{free_port, get_json, wait_until_listening};
+use musig2_example::keystore::SECRET_KEY_ENV;
+use musig2_example::secp::SECP256K1;
+use secp256k1::{PublicKey, SecretKey};
+use std::process::{Child, Command, Stdio};
+
+/// Kills the process when the test ends, whether it passes or not.
+struct KillOnDrop(Child);
+
+impl Drop for KillOnDrop {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+/// A signer process on `port` that doesn't register, with `SIGNER_SECRET_KEY` unset.
+fn signer(port: u16) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_signer"));
+    command
+        .args(["--port", &port.to_string(), "--skip-registration"])
+        .env_remove(SECRET_KEY_ENV)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    command
+}
+
+/// The public key the signer on `port` reports.
+async fn public_key(port: u16) -> String {
+    wait_until_listening(port).await;
+    let (status, health) = get_json(&format!("http://127.0.0.1:{}/health", port)).await;
+    assert_eq!(status, 200);
+    health["public_key"].as_str().unwrap().to_string()
+}
+
+fn hex_public_key(secret_key: &SecretKey) -> String {
+    hex::encode(PublicKey::from_secret_key(&SECP256K1, secret_key).serialize())
+}
+
+#[tokio::test]
+async fn signers_take_their_key_from_the_environment() {
+    let secret_key = SecretKey::new(&mut rand::thread_rng());
+    let port = free_port();
+    let _process = KillOnDrop(
+        signer(port)
+            .env(SECRET_KEY_ENV, hex::encode(secret_key.secret_bytes()))
+            .spawn()
+            .expect("Failed to start the signer binary"),
+    );
+    assert_eq!(public_key(port).await, hex_public_key(&secret_key));
+
+    // --secret-key takes precedence over the variable
+    let flag_key = SecretKey::new(&mut rand::thread_rng());
+    let port = free_port();
+    let _process = KillOnDrop(
+        signer(port)
+            .args(["--secret-key", &hex::encode(flag_key.secret_bytes())])
+            .env(SECRET_KEY_ENV, hex::encode(secret_key.secret_bytes()))
+            .spawn()
+            .expect("Failed to start the signer binary"),
+    );
+    assert_eq!(public_key(port).await, hex_public_key(&flag_key));
+}
+
+#[tokio::test]
+async fn invalid_environment_keys_fail_startup() {
+    let output = signer(free_port())
+        .env(SECRET_KEY_ENV, "not a key")
+        .stderr(Stdio::piped())
+        .output()
+        .expect("Failed to run the signer binary");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.starts_with(&format!("Invalid {}: ", SECRET_KEY_ENV)),
+        "Unexpected output: {}",
+        stderr
+    );
+}
@@ -0,0 +1,388 @@
+mod common;
+
+use common::{
+    get_json, key_agg_ctx, nonce_request, operator_config, post_json, put_json, random_public_key,
+    register, send, sign, start_operator, TestSigner,
+};
+use musig2_example::auth::{sign_body, OPERATOR_SIGNATURE_HEADER};
+use musig2_example::types::{HealthResponse, Participant, ReserveNoncesRequest, ReservedNonce};
+use serde_json::json;
+
+const ADMIN_TOKEN: &str = "admin-secret";
+const AUTH_KEY: &str = "operator-secret";
+
+/// Signers tested on their own never reach an operator.
+const NO_OPERATOR: &str = "http://127.0.0.1:1";
+
+#[tokio::test]
+async fn in_flight_sessions_are_listed_and_cleared() {
+    let signer = TestSigner::with_options(NO_OPERATOR, Some(ADMIN_TOKEN), None);
+    signer.serve().await;
+    let ctx = key_agg_ctx(&[signer.public_key, random_public_key()]);
+
+    for session_id in ["session-a", "session-b"] {
+        let request = nonce_request(session_id, "hello", &ctx, 0);
+        let (status, _) = post_json(&format!("{}/nonce", signer.url), &request).await;
+        assert_eq!(status, 200);
+    }
+    let (_, sessions) = get_json(&format!("{}/sessions", signer.url)).await;
+    assert_eq!(
+        sessions,
+        json!([
+            { "session_id": "session-a", "phase": "awaiting_nonces" },
+            { "session_id": "session-b", "phase": "awaiting_nonces" },
+        ])
+    );
+
+    let clear = format!("{}/sessions/clear", signer.url);
+    let (status, _) = send(reqwest::Client::new().post(&clear)).await;
+    assert_eq!(status, 401);
+
+    let (status, cleared) = send(
+        reqwest::Client::new()
+            .post(&clear)
+            .header("x-admin-token", ADMIN_TOKEN),
+    )
+    .await;
+    assert_eq!(status, 200);
+    assert_eq!(cleared, json!({ "cleared": 2 }));
+
+    let (_, sessions) = get_json(&format!("{}/sessions", signer.url)).await;
+    assert_eq!(sessions, json!([]));
+    // The sessions' messages are gone along with their rounds
+    let nonces = json!({ "session_id": "session-a", "nonces": {} });
+    let (status, error) = put_json(&format!("{}/nonces", signer.url), &nonces).await;
+    assert_eq!(status, 400);
+    assert_eq!(error["error"], "Session session-a not found");
+}
+
+#[tokio::test]
+async fn nonce_requests_for_a_context_without_our_key_are_rejected() {
+    let signer = TestSigner::new(NO_OPERATOR);
+    signer.serve().await;
+    let ctx = key_agg_ctx(&[random_public_key(), random_public_key()]);
+
+    let request = nonce_request("stale-context", "hello", &ctx, 0);
+    let (status, error) = post_json(&format!("{}/nonce", signer.url), &request).await;
+    assert_eq!(status, 400);
+    assert_eq!(error["code"], "invalid_request");
+    assert_eq!(
+        error["error"],
+        "Not a participant in the key aggregation context"
+    );
+
+    let (_, sessions) = get_json(&format!("{}/sessions", signer.url)).await;
+    assert_eq!(sessions, json!([]));
+}
+
+#[tokio::test]
+async fn reserved_nonces_are_used_exactly_once() {
+    let signer = TestSigner::new(NO_OPERATOR);
+    signer.serve().await;
+    let ctx = key_agg_ctx(&[signer.public_key, random_public_key()]);
+
+    let reserve = ReserveNoncesRequest {
+        key_agg_ctx: ctx.clone(),
+        signer_index: 0,
+        count: 2,
+    };
+    let (status, reserved) = post_json(&format!("{}/reserve-nonces", signer.url), &reserve).await;
+    assert_eq!(status, 200);
+    let reserved: Vec<ReservedNonce> = serde_json::from_value(reserved).unwrap();
+    assert_eq!(reserved.len(), 2);
+    assert_ne!(reserved[0].public_nonce, reserved[1].public_nonce);
+
+    for (session, reservation) in reserved.iter().enumerate() {
+        let mut request = nonce_request(&format!("reserved-{}", session), "hello", &ctx, 0);
+        request.reservation_id = Some(reservation.reservation_id.clone());
+        let (status, nonce) = post_json(&format!("{}/nonce", signer.url), &request).await;
+        assert_eq!(status, 200);
+        assert_eq!(nonce, json!(reservation.public_nonce));
+
+        // Reusing the reservation for another session is refused
+        request.session_id = format!("reused-{}", session);
+        let (status, error) = post_json(&format!("{}/nonce", signer.url), &request).await;
+        assert_eq!(status, 400);
+        assert_eq!(
+            error["error"],
+            format!(
+                "Nonce reservation {} is unknown or was already used",
+                reservation.reservation_id
+            )
+        );
+    }
+}
+
+#[tokio::test]
+async fn nonce_requests_must_match_the_claimed_participants() {
+    let signer = TestSigner::new(NO_OPERATOR);
+    signer.serve().await;
+    let other = random_public_key();
+    let ctx = key_agg_ctx(&[signer.public_key, other]);
+    let participant = |index, public_key| Participant {
+        index,
+        position: index,
+        public_key,
+        address: format!("http://127.0.0.1:{}", 8081 + index),
+    };
+
+    // The participants claim the opposite key order
+    let mut request = nonce_request("mismatch", "hello", &ctx, 0);
+    request.participants = vec![participant(0, other), participant(1, signer.public_key)];
+    let (status, error) = post_json(&format!("{}/nonce", signer.url), &request).await;
+    assert_eq!(status, 400);
+    assert_eq!(
+        error["error"],
+        "Key aggregation context pubkey at index 0 doesn't match the participant set"
+    );
+
+    request.participants = vec![participant(0, signer.public_key), participant(1, other)];
+    let (status, _) = post_json(&format!("{}/nonce", signer.url), &request).await;
+    assert_eq!(status, 200);
+}
+
+#[tokio::test]
+async fn finalizing_twice_returns_the_cached_signature() {
+    let signers = [TestSigner::new(NO_OPERATOR), TestSigner::new(NO_OPERATOR)];
+    for signer in &signers {
+        signer.serve().await;
+    }
+    let ctx = key_agg_ctx(&[signers[0].public_key, signers[1].public_key]);
+
+    let mut nonces = Vec::new();
+    for (index, signer) in signers.iter().enumerate() {
+        let request = nonce_request("twice", "hello", &ctx, index);
+        let (status, nonce) = post_json(&format!("{}/nonce", signer.url), &request).await;
+        assert_eq!(status, 200);
+        nonces.push(nonce);
+    }
+    let mut partials = Vec::new();
+    for (index, signer) in signers.iter().enumerate() {
+        let other = 1 - index;
+        let request =
+            json!({ "session_id": "twice", "nonces": { (other.to_string()): nonces[other] } });
+        let (status, reply) = put_json(&format!("{}/nonces", signer.url), &request).await;
+        assert_eq!(status, 200);
+        partials.push(reply["partial_signature"].clone());
+    }
+
+    let finalize = json!({ "session_id": "twice", "partial_signatures": { "1": partials[1] } });
+    let url = format!("{}/partial-signatures", signers[0].url);
+    let (status, first) = put_json(&url, &finalize).await;
+    assert_eq!(status, 200);
+    let (status, second) = put_json(&url, &finalize).await;
+    assert_eq!(status, 200);
+    assert_eq!(first, second);
+
+    // The cached signature doesn't count as a session in flight
+    let (_, sessions) = get_json(&format!("{}/sessions", signers[0].url)).await;
+    assert_eq!(sessions, json!([]));
+    let (_, health) = get_json(&format!("{}/health", signers[0].url)).await;
+    assert_eq!(health["active_sessions"], 0);
+}
+
+#[tokio::test]
+async fn failed_finalizations_keep_the_first_round_for_a_retry() {
+    let signers = [TestSigner::new(NO_OPERATOR), TestSigner::new(NO_OPERATOR)];
+    for signer in &signers {
+        signer.serve().await;
+    }
+    let ctx = key_agg_ctx(&[signers[0].public_key, signers[1].public_key]);
+    let mut nonces = Vec::new();
+    for (index, signer) in signers.iter().enumerate() {
+        let request = nonce_request("retried", "hello", &ctx, index);
+        let (_, nonce) = post_json(&format!("{}/nonce", signer.url), &request).await;
+        nonces.push(nonce);
+    }
+    let url = format!("{}/nonces", signers[0].url);
+
+    // Without the other signer's nonce the round can't be finalized yet
+    let incomplete = json!({ "session_id": "retried", "nonces": {} });
+    let (status, error) = put_json(&url, &incomplete).await;
+    assert_eq!(status, 400);
+    assert_eq!(
+        error["error"],
+        "Failed to finalize first round; the session can be retried"
+    );
+    let (_, sessions) = get_json(&format!("{}/sessions", signers[0].url)).await;
+    assert_eq!(
+        sessions,
+        json!([{ "session_id": "retried", "phase": "awaiting_nonces" }])
+    );
+
+    let complete = json!({ "session_id": "retried", "nonces": { "1": nonces[1] } });
+    let (status, reply) = put_json(&url, &complete).await;
+    assert_eq!(status, 200);
+    assert!(reply["partial_signature"].is_array());
+}
+
+#[tokio::test]
+async fn auth_enabled_signers_reject_unsigned_requests() {
+    let signer = TestSigner::with_options(NO_OPERATOR, None, Some(AUTH_KEY));
+    signer.serve().await;
+    let ctx = key_agg_ctx(&[signer.public_key, random_public_key()]);
+    let body = serde_json::to_vec(&nonce_request("authed", "hello", &ctx, 0)).unwrap();
+    let nonce = |signature: Option<String>| {
+        let mut request = reqwest::Client::new()
+            .post(format!("{}/nonce", signer.url))
+            .header("content-type", "application/json")
+            .body(body.clone());
+        if let Some(signature) = signature {
+            request = request.header(OPERATOR_SIGNATURE_HEADER, signature);
+        }
+        send(request)
+    };
+
+    let (status, error) = nonce(None).await;
+    assert_eq!(status, 401);
+    assert_eq!(error["error"], "Unauthorized");
+    let (status, _) = nonce(Some(sign_body("another key", &body))).await;
+    assert_eq!(status, 401);
+    let (status, _) = nonce(Some(sign_body(AUTH_KEY, &body))).await;
+    assert_eq!(status, 200);
+}
+
+#[tokio::test]
+async fn operators_sign_their_requests_with_the_shared_key() {
+    let mut config = operator_config();
+    config.operator_auth_key = Some(AUTH_KEY.to_string());
+    let (_operator, url) = start_operator(config).await;
+    let mut signers = Vec::new();
+    for _ in 0..2 {
+        let signer = TestSigner::with_options(&url, None, Some(AUTH_KEY));
+        signer.serve().await;
+        register(&url, signer.public_key, &signer.url).await;
+        signers.push(signer);
+    }
+    sign(&url, &json!({ "message": "authenticated" })).await;
+
+    let mut config = operator_config();
+    config.operator_auth_key = Some("another key".to_string());
+    let (_operator, other_url) = start_operator(config).await;
+    for signer in &signers {
+        register(&other_url, signer.public_key, &signer.url).await;
+    }
+    let (status, body) = post_json(
+        &format!("{}/sign", other_url),
+        &json!({ "message": "authenticated" }),
+    )
+    .await;
+    assert_eq!(status, 502);
+    assert!(body["error"]
+        .as_str()
+        .unwrap()
+        .contains("rejected the request as unauthorized"));
+}
+
+#[tokio::test]
+async fn partials_before_nonces_are_reported_as_out_of_order() {
+    let signer = TestSigner::new(NO_OPERATOR);
+    signer.serve().await;
+    let ctx = key_agg_ctx(&[signer.public_key, random_public_key()]);
+    let request = nonce_request("early", "hello", &ctx, 0);
+    let (status, _) = post_json(&format!("{}/nonce", signer.url), &request).await;
+    assert_eq!(status, 200);
+
+    let url = format!("{}/partial-signatures", signer.url);
+    for (session_id, expected) in [
+        ("early", "Partials received before nonces for session early"),
+        ("unknown", "Second round not found"),
+    ] {
+        let partials = json!({ "session_id": session_id, "partial_signatures": {} });
+        let (status, error) = put_json(&url, &partials).await;
+        assert_eq!(status, 400);
+        assert_eq!(error["error"], expected);
+    }
+}
+
+#[tokio::test]
+async fn finalizing_needs_a_partial_from_every_other_signer() {
+    let signers = [
+        TestSigner::new(NO_OPERATOR),
+        TestSigner::new(NO_OPERATOR),
+        TestSigner::new(NO_OPERATOR),
+    ];
+    for signer in &signers {
+        signer.serve().await;
+    }
+    let pubkeys: Vec<_> = signers.iter().map(|signer| signer.public_key).collect();
+    let ctx = key_agg_ctx(&pubkeys);
+    let mut nonces = Vec::new();
+    for (index, signer) in signers.iter().enumerate() {
+        let request = nonce_request("counted", "hello", &ctx, index);
+        let (_, nonce) = post_json(&format!("{}/nonce", signer.url), &request).await;
+        nonces.push(nonce);
+    }
+    let mut partials = Vec::new();
+    for (index, signer) in signers.iter().enumerate() {
+        let others: serde_json::Map<String, serde_json::Value> = (0..signers.len())
+            .filter(|other| *other != index)
+            .map(|other| (other.to_string(), nonces[other].clone()))
+            .collect();
+        let request = json!({ "session_id": "counted", "nonces": others });
+        let (status, reply) = put_json(&format!("{}/nonces", signer.url), &request).await;
+        assert_eq!(status, 200);
+        partials.push(reply["partial_signature"].clone());
+    }
+    let finalize = |index: usize, partials: serde_json::Value| {
+        let url = format!("{}/partial-signatures", signers[index].url);
+        async move {
+            put_json(
+                &url,
+                &json!({ "session_id": "counted", "partial_signatures": partials }),
+            )
+            .await
+        }
+    };
+
+    let (status, error) = finalize(0, json!({ "1": partials[1] })).await;
+    assert_eq!(status, 400);
+    assert_eq!(error["error"], "Expected 3 partials, got 2");
+    // The failed attempt consumed the round
+    let (status, error) = finalize(0, json!({ "1": partials[1], "2": partials[2] })).await;
+    assert_eq!(status, 400);
+    assert_eq!(error["error"], "Second round not found");
+
+    let (status, _) = finalize(1, json!({ "0": partials[0], "2": partials[2] })).await;
+    assert_eq!(status, 200);
+}
+
+#[tokio::test]
+async fn health_reports_the_key_and_rounds_in_flight() {
+    let signers = [
+        TestSigner::with_options(NO_OPERATOR, Some(ADMIN_TOKEN), None),
+        TestSigner::new(NO_OPERATOR),
+    ];
+    for signer in &signers {
+        signer.serve().await;
+    }
+    let url = format!("{}/health", signers[0].url);
+    let health = || {
+        let url = url.clone();
+        async move {
+            // Readiness probes carry no admin token
+            let (status, health) = get_json(&url).await;
+            assert_eq!(status, 200);
+            serde_json::from_value::<HealthResponse>(health).unwrap()
+        }
+    };
+    let idle = health().await;
+    assert_eq!(idle.public_key, signers[0].public_key);
+    assert_eq!(idle.active_sessions, 0);
+
+    let ctx = key_agg_ctx(&[signers[0].public_key, signers[1].public_key]);
+    for session_id in ["first", "second"] {
+        let request = nonce_request(session_id, "hello", &ctx, 0);
+        let (status, _) = post_json(&format!("{}/nonce", signers[0].url), &request).await;
+        assert_eq!(status, 200);
+    }
+    assert_eq!(health().await.active_sessions, 2);
+
+    // A session moving on to its second round still counts once
+    let other = nonce_request("first", "hello", &ctx, 1);
+    let (_, other_nonce) = post_json(&format!("{}/nonce", signers[1].url), &other).await;
+    let request = json!({ "session_id": "first", "nonces": { "1": other_nonce } });
+    let (status, _) = put_json(&format!("{}/nonces", signers[0].url), &request).await;
+    assert_eq!(status, 200);
+    assert_eq!(health().await.active_sessions, 2);
+}